@@ -0,0 +1,359 @@
+use crate::game_data::*;
+use crate::zobrist::{get_hash, get_piece_key};
+
+/** A set of squares where bit 0 is a8 and bit 63 is h1, the same order as Position where 0,0 is the top left */
+pub type Bitboard = u64;
+
+pub const EMPTY_BITBOARD: Bitboard = 0;
+pub const FULL_BITBOARD: Bitboard = !0;
+
+const BOARD_SQUARES: usize = BOARD_SIZE * BOARD_SIZE;
+
+const KNIGHT_OFFSETS: [(i8, i8); 8] = [
+    (2, 1),
+    (1, 2),
+    (-2, 1),
+    (-1, 2),
+    (2, -1),
+    (1, -2),
+    (-2, -1),
+    (-1, -2),
+];
+
+const KING_OFFSETS: [(i8, i8); 8] = [
+    (0, 1),
+    (0, -1),
+    (-1, 0),
+    (1, 0),
+    (1, 1),
+    (-1, -1),
+    (-1, 1),
+    (1, -1),
+];
+
+/** the first 4 directions are used by rooks and the last 4 by bishops */
+const DIRECTIONS: [(i8, i8); 8] = [
+    (0, -1),
+    (0, 1),
+    (1, 0),
+    (-1, 0),
+    (1, -1),
+    (-1, -1),
+    (1, 1),
+    (-1, 1),
+];
+
+static KNIGHT_ATTACKS: [Bitboard; BOARD_SQUARES] = generate_jumps(&KNIGHT_OFFSETS);
+static KING_ATTACKS: [Bitboard; BOARD_SQUARES] = generate_jumps(&KING_OFFSETS);
+/** 0 is the white player moving up the board and 1 is the black player moving down */
+static PAWN_ATTACKS: [[Bitboard; BOARD_SQUARES]; 2] = [
+    generate_jumps(&[(-1, -1), (1, -1)]),
+    generate_jumps(&[(-1, 1), (1, 1)]),
+];
+static RAYS: [[Bitboard; BOARD_SQUARES]; 8] = generate_rays();
+static BETWEEN: [[Bitboard; BOARD_SQUARES]; BOARD_SQUARES] = generate_lines(true);
+static LINES: [[Bitboard; BOARD_SQUARES]; BOARD_SQUARES] = generate_lines(false);
+
+const fn get_offset_square(square: usize, offset: (i8, i8)) -> Option<usize> {
+    let x = (square % BOARD_SIZE) as i8 + offset.0;
+    let y = (square / BOARD_SIZE) as i8 + offset.1;
+    if x < 0 || y < 0 || x >= BOARD_SIZE as i8 || y >= BOARD_SIZE as i8 {
+        return None;
+    }
+    Some(y as usize * BOARD_SIZE + x as usize)
+}
+
+const fn generate_jumps(offsets: &[(i8, i8)]) -> [Bitboard; BOARD_SQUARES] {
+    let mut table = [EMPTY_BITBOARD; BOARD_SQUARES];
+    let mut square = 0;
+    while square < BOARD_SQUARES {
+        let mut index = 0;
+        while index < offsets.len() {
+            if let Some(target) = get_offset_square(square, offsets[index]) {
+                table[square] |= 1 << target;
+            }
+            index += 1;
+        }
+        square += 1;
+    }
+    table
+}
+
+/** every square in a direction until the edge of the board, not including the start square */
+const fn generate_rays() -> [[Bitboard; BOARD_SQUARES]; 8] {
+    let mut table = [[EMPTY_BITBOARD; BOARD_SQUARES]; 8];
+    let mut direction = 0;
+    while direction < DIRECTIONS.len() {
+        let mut square = 0;
+        while square < BOARD_SQUARES {
+            let mut current = square;
+            while let Some(target) = get_offset_square(current, DIRECTIONS[direction]) {
+                table[direction][square] |= 1 << target;
+                current = target;
+            }
+            square += 1;
+        }
+        direction += 1;
+    }
+    table
+}
+
+/**
+If only_between is true the table contains the squares between two squares on the same line,
+else the whole line through both squares from edge to edge. Squares that are not on a line are empty.
+*/
+const fn generate_lines(only_between: bool) -> [[Bitboard; BOARD_SQUARES]; BOARD_SQUARES] {
+    let mut table = [[EMPTY_BITBOARD; BOARD_SQUARES]; BOARD_SQUARES];
+    let mut square = 0;
+    while square < BOARD_SQUARES {
+        let mut direction = 0;
+        while direction < DIRECTIONS.len() {
+            let offset = DIRECTIONS[direction];
+            let mut between = EMPTY_BITBOARD;
+            let mut current = square;
+            while let Some(target) = get_offset_square(current, offset) {
+                table[square][target] = if only_between {
+                    between
+                } else {
+                    // both directions from the start square and the start square itself
+                    let mut line = 1 << square;
+                    let mut back = square;
+                    while let Some(back_target) = get_offset_square(back, (-offset.0, -offset.1)) {
+                        line |= 1 << back_target;
+                        back = back_target;
+                    }
+                    let mut forward = square;
+                    while let Some(forward_target) = get_offset_square(forward, offset) {
+                        line |= 1 << forward_target;
+                        forward = forward_target;
+                    }
+                    line
+                };
+                between |= 1 << target;
+                current = target;
+            }
+            direction += 1;
+        }
+        square += 1;
+    }
+    table
+}
+
+pub(crate) fn get_square_index(pos: &Position) -> usize {
+    pos.y * BOARD_SIZE + pos.x
+}
+
+pub(crate) fn get_square_position(square: usize) -> Position {
+    Position {
+        x: square % BOARD_SIZE,
+        y: square / BOARD_SIZE,
+    }
+}
+
+pub(crate) fn get_square_bitboard(square: usize) -> Bitboard {
+    1 << square
+}
+
+/** Removes the first square from the bitboard and returns it, the bitboard must not be empty */
+pub(crate) fn pop_square(bitboard: &mut Bitboard) -> usize {
+    let square = bitboard.trailing_zeros() as usize;
+    *bitboard &= *bitboard - 1;
+    square
+}
+
+/** 0 is the white player and 1 is the black player, same as castle */
+pub(crate) fn get_color_index(is_white: bool) -> usize {
+    if is_white {
+        0
+    } else {
+        1
+    }
+}
+
+/** Index into piece_bitboards, must not be called with Piece::None */
+pub(crate) fn get_piece_index(piece: Piece) -> usize {
+    piece as usize - 1
+}
+
+pub(crate) fn get_knight_attacks(square: usize) -> Bitboard {
+    KNIGHT_ATTACKS[square]
+}
+
+pub(crate) fn get_king_attacks(square: usize) -> Bitboard {
+    KING_ATTACKS[square]
+}
+
+pub(crate) fn get_pawn_attacks(square: usize, is_white: bool) -> Bitboard {
+    PAWN_ATTACKS[get_color_index(is_white)][square]
+}
+
+/** The squares strictly between two squares, empty if they are not on the same line */
+pub(crate) fn get_between(from: usize, to: usize) -> Bitboard {
+    BETWEEN[from][to]
+}
+
+/** The whole line through both squares, empty if they are not on the same line */
+pub(crate) fn get_line(from: usize, to: usize) -> Bitboard {
+    LINES[from][to]
+}
+
+fn get_ray_attacks(square: usize, occupied: Bitboard, direction: usize) -> Bitboard {
+    let ray = RAYS[direction][square];
+    let blockers = ray & occupied;
+    if blockers == EMPTY_BITBOARD {
+        return ray;
+    }
+
+    // the closest blocker is the lowest bit when moving to higher squares
+    let (x, y) = DIRECTIONS[direction];
+    let blocker = if y > 0 || (y == 0 && x > 0) {
+        blockers.trailing_zeros() as usize
+    } else {
+        63 - blockers.leading_zeros() as usize
+    };
+    ray ^ RAYS[direction][blocker]
+}
+
+pub(crate) fn get_rook_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    get_ray_attacks(square, occupied, 0)
+        | get_ray_attacks(square, occupied, 1)
+        | get_ray_attacks(square, occupied, 2)
+        | get_ray_attacks(square, occupied, 3)
+}
+
+pub(crate) fn get_bishop_attacks(square: usize, occupied: Bitboard) -> Bitboard {
+    get_ray_attacks(square, occupied, 4)
+        | get_ray_attacks(square, occupied, 5)
+        | get_ray_attacks(square, occupied, 6)
+        | get_ray_attacks(square, occupied, 7)
+}
+
+pub(crate) fn get_pieces(game: &Game, piece: Piece, is_white: bool) -> Bitboard {
+    game.piece_bitboards[get_piece_index(piece)] & game.color_bitboards[get_color_index(is_white)]
+}
+
+pub(crate) fn get_occupied(game: &Game) -> Bitboard {
+    game.color_bitboards[0] | game.color_bitboards[1]
+}
+
+/** Changes a square on the board, the bitboards and the hash, all board changes should use this */
+pub(crate) fn set_square(game: &mut Game, pos: Position, piece_data: PieceData) {
+    let old_piece = game.board[pos.x][pos.y];
+    let square = get_square_index(&pos);
+    let bitboard = get_square_bitboard(square);
+    game.hash ^= get_piece_key(old_piece, square) ^ get_piece_key(piece_data, square);
+
+    if old_piece.piece != Piece::None {
+        game.piece_bitboards[get_piece_index(old_piece.piece)] &= !bitboard;
+        game.color_bitboards[get_color_index(old_piece.is_white)] &= !bitboard;
+    }
+
+    if piece_data.piece != Piece::None {
+        game.piece_bitboards[get_piece_index(piece_data.piece)] |= bitboard;
+        game.color_bitboards[get_color_index(piece_data.is_white)] |= bitboard;
+    }
+
+    game.board[pos.x][pos.y] = piece_data;
+}
+
+/** Generates all bitboards and the hash from the board */
+pub(crate) fn update_bitboards(game: &mut Game) {
+    game.piece_bitboards = [EMPTY_BITBOARD; 6];
+    game.color_bitboards = [EMPTY_BITBOARD; 2];
+
+    for x in 0..BOARD_SIZE {
+        for y in 0..BOARD_SIZE {
+            let pos = Position { x, y };
+            let piece_data = game.board[x][y];
+            // set_square only changes the bitboards if the old piece is set
+            game.board[x][y] = EMPTY_PEICE;
+            set_square(game, pos, piece_data);
+        }
+    }
+    game.hash = get_hash(game);
+}
+
+/** All pieces of one team that attacks a square with the given occupancy */
+pub(crate) fn get_attackers(
+    game: &Game,
+    square: usize,
+    occupied: Bitboard,
+    is_white: bool,
+) -> Bitboard {
+    let rooks = get_pieces(game, Piece::Rook, is_white) | get_pieces(game, Piece::Queen, is_white);
+    let bishops =
+        get_pieces(game, Piece::Bishop, is_white) | get_pieces(game, Piece::Queen, is_white);
+
+    // a pawn attacks the squares that a pawn of the other color would attack from the target
+    (get_pawn_attacks(square, !is_white) & get_pieces(game, Piece::Pawn, is_white))
+        | (get_knight_attacks(square) & get_pieces(game, Piece::Knight, is_white))
+        | (get_king_attacks(square) & get_pieces(game, Piece::King, is_white))
+        | (get_rook_attacks(square, occupied) & rooks)
+        | (get_bishop_attacks(square, occupied) & bishops)
+}
+
+/** Every square one team attacks with the given occupancy */
+pub(crate) fn get_attacked_squares(game: &Game, occupied: Bitboard, is_white: bool) -> Bitboard {
+    let mut attacked = EMPTY_BITBOARD;
+
+    let mut pawns = get_pieces(game, Piece::Pawn, is_white);
+    while pawns != EMPTY_BITBOARD {
+        attacked |= get_pawn_attacks(pop_square(&mut pawns), is_white);
+    }
+
+    let mut knights = get_pieces(game, Piece::Knight, is_white);
+    while knights != EMPTY_BITBOARD {
+        attacked |= get_knight_attacks(pop_square(&mut knights));
+    }
+
+    let mut kings = get_pieces(game, Piece::King, is_white);
+    while kings != EMPTY_BITBOARD {
+        attacked |= get_king_attacks(pop_square(&mut kings));
+    }
+
+    let queens = get_pieces(game, Piece::Queen, is_white);
+    let mut rooks = get_pieces(game, Piece::Rook, is_white) | queens;
+    while rooks != EMPTY_BITBOARD {
+        attacked |= get_rook_attacks(pop_square(&mut rooks), occupied);
+    }
+
+    let mut bishops = get_pieces(game, Piece::Bishop, is_white) | queens;
+    while bishops != EMPTY_BITBOARD {
+        attacked |= get_bishop_attacks(pop_square(&mut bishops), occupied);
+    }
+
+    attacked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attack_table_test() {
+        // a8 is square 0 and h1 is square 63
+        assert_eq!(get_knight_attacks(0).count_ones(), 2);
+        assert_eq!(get_king_attacks(63).count_ones(), 3);
+        assert_eq!(get_king_attacks(27).count_ones(), 8);
+        assert_eq!(get_rook_attacks(0, EMPTY_BITBOARD).count_ones(), 14);
+        assert_eq!(get_bishop_attacks(0, EMPTY_BITBOARD).count_ones(), 7);
+
+        // a rook on a8 blocked on a6 and d8
+        let blockers = get_square_bitboard(16) | get_square_bitboard(3);
+        assert_eq!(
+            get_rook_attacks(0, blockers),
+            get_square_bitboard(8) | get_square_bitboard(16) | 0b1110
+        );
+    }
+
+    #[test]
+    fn line_table_test() {
+        // a8 to h1
+        assert_eq!(get_between(0, 63).count_ones(), 6);
+        assert_eq!(get_line(9, 18).count_ones(), 8);
+        assert_eq!(get_between(0, 17), EMPTY_BITBOARD);
+        assert_eq!(get_line(0, 17), EMPTY_BITBOARD);
+        assert_eq!(get_between(0, 1), EMPTY_BITBOARD);
+        assert_eq!(get_line(1, 0), 0xff);
+    }
+}
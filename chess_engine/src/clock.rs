@@ -0,0 +1,457 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::game_data::*;
+use crate::logic::has_insufficient_material;
+
+/** How the bonus of a stage is given to the player */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingMethod {
+    /** Fischer increment, the bonus is added after every move. Sudden death is an increment of 0 */
+    Increment,
+    /** Simple delay, the clock waits for the bonus before it starts counting down */
+    SimpleDelay,
+    /** Bronstein delay, the time used for the move is given back but never more than the bonus */
+    Bronstein,
+}
+
+/** One part of a time control, like 40 moves in 90 minutes with 30 seconds increment */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeControlStage {
+    /** the moves to play in the stage, None if the stage lasts for the rest of the game */
+    pub moves: Option<u32>,
+    /** added to the clock when the stage starts */
+    pub time: Duration,
+    pub method: TimingMethod,
+    /** the increment or delay for every move */
+    pub bonus: Duration,
+}
+
+/**
+The stages are played in order and the last one is repeated if it has a number of moves.
+Written as [moves/]minutes[+|d|b seconds] for every stage separated by commas,
++ is increment, d is simple delay and b is Bronstein delay. 5 is sudden death and 40/90+30,30+30 is the FIDE time control
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeControl {
+    pub stages: Vec<TimeControlStage>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Clock {
+    pub time_control: TimeControl,
+    /** 0 is the white player and 1 is the black player, without the time used on the move in progress */
+    time_left: [Duration; 2],
+    /** the stage each player is in and how many moves they have played in it */
+    stages: [usize; 2],
+    stage_moves: [u32; 2],
+    /** the player whose clock is running or would be running if it was not paused */
+    pub is_white_to_move: bool,
+    /** when the clock of the player to move was started, None if the clock is paused */
+    started: Option<Instant>,
+    /** time used on the move in progress before the clock was paused */
+    used: Duration,
+    /** the player who ran out of time */
+    flagged: Option<bool>,
+}
+
+const SECONDS_PER_MINUTE: f64 = 60.0;
+
+fn parse_number(input: &str, stage: &str) -> Result<f64, ChessError> {
+    match input.parse::<f64>() {
+        Ok(number) if number.is_finite() && number >= 0.0 => Ok(number),
+        _ => Err(ChessError::InvalidInput(format!(
+            "'{}' is not a time control like 40/90+30",
+            stage
+        ))),
+    }
+}
+
+pub fn parse_time_control(input: &str) -> Result<TimeControl, ChessError> {
+    let mut stages = Vec::new();
+    for stage in input.split(',').map(str::trim) {
+        let invalid_input =
+            || ChessError::InvalidInput(format!("'{}' is not a time control like 40/90+30", stage));
+        // a number can be too large to be a Duration
+        let to_duration =
+            |seconds: f64| Duration::try_from_secs_f64(seconds).map_err(|_| invalid_input());
+
+        let (moves, rest) = match stage.split_once('/') {
+            Some((moves, rest)) => match moves.parse::<u32>() {
+                Ok(moves) if moves > 0 => (Some(moves), rest),
+                _ => return Err(invalid_input()),
+            },
+            None => (None, stage),
+        };
+
+        let (time, method, bonus) = match rest.find(['+', 'd', 'b']) {
+            Some(index) => {
+                let method = match &rest[index..index + 1] {
+                    "+" => TimingMethod::Increment,
+                    "d" => TimingMethod::SimpleDelay,
+                    _ => TimingMethod::Bronstein,
+                };
+                (
+                    &rest[..index],
+                    method,
+                    parse_number(&rest[index + 1..], stage)?,
+                )
+            }
+            None => (rest, TimingMethod::Increment, 0.0),
+        };
+
+        stages.push(TimeControlStage {
+            moves,
+            time: to_duration(parse_number(time, stage)? * SECONDS_PER_MINUTE)?,
+            method,
+            bonus: to_duration(bonus)?,
+        });
+    }
+
+    // only the last stage can last for the rest of the game
+    if stages[..stages.len() - 1]
+        .iter()
+        .any(|stage| stage.moves.is_none())
+    {
+        return Err(ChessError::InvalidInput(format!(
+            "'{}' has a stage without moves before the last stage",
+            input
+        )));
+    }
+    Ok(TimeControl { stages })
+}
+
+/** Writes 90 and not 90.0, but keeps the fraction if there is one */
+fn format_number(number: f64) -> String {
+    let rounded = (number * 1000.0).round() / 1000.0;
+    if rounded.fract() == 0.0 {
+        format!("{}", rounded as u64)
+    } else {
+        format!("{}", rounded)
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stages: Vec<String> = self
+            .stages
+            .iter()
+            .map(|stage| {
+                let mut output = String::new();
+                if let Some(moves) = stage.moves {
+                    output.push_str(&format!("{}/", moves));
+                }
+                output.push_str(&format_number(
+                    stage.time.as_secs_f64() / SECONDS_PER_MINUTE,
+                ));
+                if stage.bonus != Duration::ZERO {
+                    output.push(match stage.method {
+                        TimingMethod::Increment => '+',
+                        TimingMethod::SimpleDelay => 'd',
+                        TimingMethod::Bronstein => 'b',
+                    });
+                    output.push_str(&format_number(stage.bonus.as_secs_f64()));
+                }
+                output
+            })
+            .collect();
+        write!(f, "{}", stages.join(","))
+    }
+}
+
+/** The stage, or the last stage if the player has played past all of them */
+fn get_stage(time_control: &TimeControl, index: usize) -> Option<&TimeControlStage> {
+    time_control
+        .stages
+        .get(index)
+        .or_else(|| time_control.stages.last())
+}
+
+/** The clock is paused until start_clock is called */
+pub fn init_clock(time_control: TimeControl, is_white_to_move: bool) -> Clock {
+    let time = get_stage(&time_control, 0).map_or(Duration::ZERO, |stage| stage.time);
+    Clock {
+        time_control,
+        time_left: [time; 2],
+        stages: [0; 2],
+        stage_moves: [0; 2],
+        is_white_to_move,
+        started: None,
+        used: Duration::ZERO,
+        flagged: None,
+    }
+}
+
+/** Starts or resumes the clock of the player to move, does nothing if it is already running or a flag has fallen */
+pub fn start_clock(clock: &mut Clock, now: Instant) {
+    if clock.started.is_none() && clock.flagged.is_none() {
+        clock.started = Some(now);
+    }
+}
+
+/** Stops the clock, the time used on the move so far is kept until it is started again */
+pub fn pause_clock(clock: &mut Clock, now: Instant) {
+    if let Some(started) = clock.started.take() {
+        clock.used += now.saturating_duration_since(started);
+    }
+}
+
+pub fn is_clock_running(clock: &Clock) -> bool {
+    clock.started.is_some()
+}
+
+fn get_used(clock: &Clock, now: Instant) -> Duration {
+    clock.used
+        + clock.started.map_or(Duration::ZERO, |started| {
+            now.saturating_duration_since(started)
+        })
+}
+
+pub fn get_time_left(clock: &Clock, is_white: bool, now: Instant) -> Duration {
+    let index = if is_white { 0 } else { 1 };
+    if is_white != clock.is_white_to_move {
+        return clock.time_left[index];
+    }
+
+    let mut used = get_used(clock, now);
+    if let Some(stage) = get_stage(&clock.time_control, clock.stages[index]) {
+        if stage.method == TimingMethod::SimpleDelay {
+            used = used.saturating_sub(stage.bonus);
+        }
+    }
+    clock.time_left[index].saturating_sub(used)
+}
+
+/**
+Ends the move of the player to move and switches to the other clock, the bonus and the time of the next stage are added.
+Returns false if the flag had already fallen, then nothing is changed
+*/
+pub fn press_clock(clock: &mut Clock, now: Instant) -> bool {
+    if check_flag(clock, now).is_some() {
+        return false;
+    }
+
+    let index = if clock.is_white_to_move { 0 } else { 1 };
+    let used = get_used(clock, now);
+    let mut time_left = get_time_left(clock, clock.is_white_to_move, now);
+
+    if let Some(stage) = get_stage(&clock.time_control, clock.stages[index]).copied() {
+        time_left = time_left.saturating_add(match stage.method {
+            TimingMethod::Increment => stage.bonus,
+            TimingMethod::SimpleDelay => Duration::ZERO,
+            TimingMethod::Bronstein => used.min(stage.bonus),
+        });
+
+        clock.stage_moves[index] += 1;
+        if Some(clock.stage_moves[index]) == stage.moves {
+            clock.stages[index] += 1;
+            clock.stage_moves[index] = 0;
+            if let Some(next_stage) = get_stage(&clock.time_control, clock.stages[index]) {
+                time_left = time_left.saturating_add(next_stage.time);
+            }
+        }
+    }
+
+    clock.time_left[index] = time_left;
+    clock.is_white_to_move = !clock.is_white_to_move;
+    clock.used = Duration::ZERO;
+    if clock.started.is_some() {
+        clock.started = Some(now);
+    }
+    true
+}
+
+/**
+Returns the player who has run out of time, true if it is white.
+The clock is stopped as soon as a flag falls
+*/
+pub fn check_flag(clock: &mut Clock, now: Instant) -> Option<bool> {
+    if clock.flagged.is_none()
+        && get_time_left(clock, clock.is_white_to_move, now) == Duration::ZERO
+    {
+        let index = if clock.is_white_to_move { 0 } else { 1 };
+        clock.flagged = Some(clock.is_white_to_move);
+        clock.time_left[index] = Duration::ZERO;
+        clock.started = None;
+        clock.used = Duration::ZERO;
+    }
+    clock.flagged
+}
+
+/**
+The result if a flag has fallen, the player who ran out of time loses
+unless the other player can not checkmate with any series of legal moves, then it is a tie
+*/
+pub fn get_time_result(clock: &mut Clock, game: &Game, now: Instant) -> GameResult {
+    let outcome = match check_flag(clock, now) {
+        None => return GAME_ONGOING,
+        Some(is_white) if has_insufficient_material(game, !is_white) => WinStatus::Tie,
+        Some(true) => WinStatus::BlackWon,
+        Some(false) => WinStatus::WhiteWon,
+    };
+    GameResult {
+        outcome,
+        reason: Some(GameEndReason::Timeout),
+    }
+}
+
+/** Sets the time left of both players, used to follow a clock that is running somewhere else */
+pub fn sync_clock(
+    clock: &mut Clock,
+    time_left: [Duration; 2],
+    is_white_to_move: bool,
+    is_running: bool,
+    now: Instant,
+) {
+    clock.time_left = time_left;
+    clock.is_white_to_move = is_white_to_move;
+    clock.used = Duration::ZERO;
+    clock.flagged = None;
+    clock.started = if is_running { Some(now) } else { None };
+}
+
+/** h:mm:ss, m:ss or m:ss.t with tenths of a second when there are less than 10 seconds left */
+pub fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else if seconds >= 10 {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    } else {
+        format!("0:{:02}.{}", seconds, time.subsec_millis() / 100)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::get_board;
+
+    fn seconds(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn time_control_test() {
+        let time_control = parse_time_control("40/90+30,30+30").unwrap();
+        assert_eq!(
+            time_control.stages,
+            vec![
+                TimeControlStage {
+                    moves: Some(40),
+                    time: seconds(90 * 60),
+                    method: TimingMethod::Increment,
+                    bonus: seconds(30),
+                },
+                TimeControlStage {
+                    moves: None,
+                    time: seconds(30 * 60),
+                    method: TimingMethod::Increment,
+                    bonus: seconds(30),
+                }
+            ]
+        );
+        assert_eq!(time_control.to_string(), "40/90+30,30+30");
+
+        for input in ["5", "3+2", "0.5+1", "90d5", "60b10", "40/120,20/60"] {
+            assert_eq!(parse_time_control(input).unwrap().to_string(), input);
+        }
+        for input in [
+            "", "x", "5+", "0/5", "5,40/90", "-5", "5+2+1", "1e300", "5+1e300",
+        ] {
+            assert!(parse_time_control(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn clock_test() {
+        let start = Instant::now();
+        let at = |time: u64| start + seconds(time);
+
+        // fischer increment
+        let mut clock = init_clock(parse_time_control("1+2").unwrap(), true);
+        assert_eq!(get_time_left(&clock, true, at(10)), seconds(60));
+        start_clock(&mut clock, start);
+        assert_eq!(get_time_left(&clock, true, at(10)), seconds(50));
+        assert!(press_clock(&mut clock, at(10)));
+        assert_eq!(get_time_left(&clock, true, at(15)), seconds(52));
+        assert_eq!(get_time_left(&clock, false, at(15)), seconds(55));
+
+        // paused time is not counted
+        pause_clock(&mut clock, at(15));
+        start_clock(&mut clock, at(100));
+        assert_eq!(get_time_left(&clock, false, at(105)), seconds(50));
+
+        // simple delay, nothing is used during the delay
+        let mut clock = init_clock(parse_time_control("1d5").unwrap(), true);
+        start_clock(&mut clock, start);
+        assert_eq!(get_time_left(&clock, true, at(4)), seconds(60));
+        press_clock(&mut clock, at(8));
+        assert_eq!(get_time_left(&clock, true, at(8)), seconds(57));
+
+        // bronstein, the used time is given back but never more than the delay
+        let mut clock = init_clock(parse_time_control("1b5").unwrap(), true);
+        start_clock(&mut clock, start);
+        assert_eq!(get_time_left(&clock, true, at(4)), seconds(56));
+        press_clock(&mut clock, at(4));
+        assert_eq!(get_time_left(&clock, true, at(4)), seconds(60));
+        press_clock(&mut clock, at(4));
+        press_clock(&mut clock, at(14));
+        assert_eq!(get_time_left(&clock, true, at(14)), seconds(55));
+
+        // the time of the next stage is added after the moves of the stage
+        let mut clock = init_clock(parse_time_control("2/1,1").unwrap(), true);
+        start_clock(&mut clock, start);
+        for time in 1..=4 {
+            press_clock(&mut clock, at(time));
+        }
+        assert_eq!(get_time_left(&clock, true, at(4)), seconds(118));
+    }
+
+    #[test]
+    fn flag_test() {
+        let start = Instant::now();
+        let game = get_board("r3k3/8/8/8/8/8/8/R3K3 w - - 0 1".to_string()).unwrap();
+
+        let mut clock = init_clock(parse_time_control("1").unwrap(), true);
+        start_clock(&mut clock, start);
+        assert_eq!(
+            get_time_result(&mut clock, &game, start + seconds(59)).outcome,
+            WinStatus::Nothing
+        );
+        assert_eq!(
+            get_time_result(&mut clock, &game, start + seconds(61)).outcome,
+            WinStatus::BlackWon
+        );
+        assert_eq!(
+            get_time_result(&mut clock, &game, start + seconds(62)).reason,
+            Some(GameEndReason::Timeout)
+        );
+        assert!(!is_clock_running(&clock));
+        assert!(!press_clock(&mut clock, start + seconds(62)));
+
+        // a lone king can not win on time
+        let game = get_board("4k3/8/8/8/8/8/8/R3K3 w - - 0 1".to_string()).unwrap();
+        let mut clock = init_clock(parse_time_control("1").unwrap(), false);
+        start_clock(&mut clock, start);
+        assert_eq!(
+            get_time_result(&mut clock, &game, start + seconds(61)).outcome,
+            WinStatus::WhiteWon
+        );
+        let mut clock = init_clock(parse_time_control("1").unwrap(), true);
+        start_clock(&mut clock, start);
+        assert_eq!(
+            get_time_result(&mut clock, &game, start + seconds(61)).outcome,
+            WinStatus::Tie
+        );
+
+        assert_eq!(format_time(seconds(3723)), "1:02:03");
+        assert_eq!(format_time(seconds(59)), "0:59");
+        assert_eq!(format_time(Duration::from_millis(9_870)), "0:09.8");
+    }
+}
@@ -0,0 +1,503 @@
+use crate::bitboard::*;
+use crate::game_data::*;
+
+/**
+Static evaluation in centipawns, positive is good for white.
+Every term has one value for the middlegame and one for the endgame, they are mixed by how many pieces are left
+https://www.chessprogramming.org/Tapered_Eval
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Evaluation {
+    pub material: i32,
+    /** bonus for pieces standing on good squares */
+    pub piece_squares: i32,
+    /** bonus for the number of squares the pieces can move to */
+    pub mobility: i32,
+    /** pawns in front of the king and pieces attacking the squares around it */
+    pub king_safety: i32,
+    /** doubled, isolated and passed pawns */
+    pub pawn_structure: i32,
+    pub bishop_pair: i32,
+    /** MAX_PHASE when all pieces are on the board and 0 when only kings and pawns are left */
+    pub phase: i32,
+    /** the sum of all terms */
+    pub total: i32,
+}
+
+pub const MAX_PHASE: i32 = 24;
+
+/** How much every piece adds to the phase, same order as piece_bitboards */
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+
+const MIDDLEGAME_VALUES: [i32; 6] = [82, 337, 365, 477, 1025, 0];
+const ENDGAME_VALUES: [i32; 6] = [94, 281, 297, 512, 936, 0];
+
+// piece square tables from https://www.chessprogramming.org/Simplified_Evaluation_Function
+// seen from white with a8 first, the same index as the squares of the bitboards
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const PAWN_ENDGAME_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    80, 80, 80, 80, 80, 80, 80, 80,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    30, 30, 30, 30, 30, 30, 30, 30,
+    20, 20, 20, 20, 20, 20, 20, 20,
+    10, 10, 10, 10, 10, 10, 10, 10,
+    10, 10, 10, 10, 10, 10, 10, 10,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const KING_TABLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+const MIDDLEGAME_TABLES: [[i32; 64]; 6] = [
+    PAWN_TABLE,
+    KNIGHT_TABLE,
+    BISHOP_TABLE,
+    ROOK_TABLE,
+    QUEEN_TABLE,
+    KING_TABLE,
+];
+const ENDGAME_TABLES: [[i32; 64]; 6] = [
+    PAWN_ENDGAME_TABLE,
+    KNIGHT_TABLE,
+    BISHOP_TABLE,
+    ROOK_TABLE,
+    QUEEN_TABLE,
+    KING_ENDGAME_TABLE,
+];
+
+/** Middlegame and endgame bonus for every square a knight, bishop, rook and queen can move to */
+const MOBILITY_WEIGHTS: [(i32, i32); 4] = [(4, 4), (5, 5), (2, 4), (1, 2)];
+/** The number of squares a piece usually can move to, fewer squares than this gives a penalty */
+const MOBILITY_BASE: [i32; 4] = [4, 6, 7, 13];
+
+/** How dangerous an attack on the squares around the king is for a knight, bishop, rook and queen */
+const KING_ATTACK_WEIGHTS: [i32; 4] = [2, 2, 3, 5];
+const MAX_KING_ATTACK_PENALTY: i32 = 500;
+/** Bonus for a pawn one and two squares in front of the king */
+const PAWN_SHIELD_BONUS: [i32; 2] = [10, 5];
+
+const DOUBLED_PAWN_PENALTY: (i32, i32) = (-10, -20);
+const ISOLATED_PAWN_PENALTY: (i32, i32) = (-10, -15);
+/** Indexed by how many squares the pawn has moved forward */
+const PASSED_PAWN_BONUS: [(i32, i32); BOARD_SIZE] = [
+    (0, 0),
+    (5, 10),
+    (10, 20),
+    (15, 35),
+    (30, 60),
+    (50, 100),
+    (0, 0),
+    (0, 0),
+];
+const BISHOP_PAIR_BONUS: (i32, i32) = (30, 50);
+
+/** A score for the middlegame and the endgame that is mixed by the phase */
+#[derive(Clone, Copy, Default)]
+struct TaperedScore {
+    middlegame: i32,
+    endgame: i32,
+}
+
+impl TaperedScore {
+    fn add(&mut self, (middlegame, endgame): (i32, i32), count: i32) {
+        self.middlegame += middlegame * count;
+        self.endgame += endgame * count;
+    }
+
+    fn taper(&self, phase: i32) -> i32 {
+        (self.middlegame * phase + self.endgame * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+/** Squares are seen from white, so the board is flipped for black */
+fn get_relative_square(square: usize, is_white: bool) -> usize {
+    if is_white {
+        square
+    } else {
+        square ^ (BOARD_SIZE * (BOARD_SIZE - 1))
+    }
+}
+
+fn get_file_bitboard(x: usize) -> Bitboard {
+    (0..BOARD_SIZE).fold(EMPTY_BITBOARD, |bitboard, y| {
+        bitboard | get_square_bitboard(y * BOARD_SIZE + x)
+    })
+}
+
+/** The files next to the file, used for isolated and passed pawns */
+fn get_adjacent_files(x: usize) -> Bitboard {
+    let mut bitboard = EMPTY_BITBOARD;
+    if x > 0 {
+        bitboard |= get_file_bitboard(x - 1);
+    }
+    if x + 1 < BOARD_SIZE {
+        bitboard |= get_file_bitboard(x + 1);
+    }
+    bitboard
+}
+
+/** All squares in front of the rank for the player */
+fn get_forward_ranks(y: usize, is_white: bool) -> Bitboard {
+    (0..BOARD_SIZE)
+        .filter(|&rank| if is_white { rank < y } else { rank > y })
+        .fold(EMPTY_BITBOARD, |bitboard, rank| {
+            bitboard | (0xFF << (rank * BOARD_SIZE))
+        })
+}
+
+fn get_piece_attacks(piece: Piece, square: usize, occupied: Bitboard) -> Bitboard {
+    match piece {
+        Piece::Knight => get_knight_attacks(square),
+        Piece::Bishop => get_bishop_attacks(square, occupied),
+        Piece::Rook => get_rook_attacks(square, occupied),
+        Piece::Queen => get_rook_attacks(square, occupied) | get_bishop_attacks(square, occupied),
+        _ => EMPTY_BITBOARD,
+    }
+}
+
+const MINOR_AND_MAJOR_PIECES: [Piece; 4] =
+    [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen];
+
+fn add_material_and_squares(
+    game: &Game,
+    is_white: bool,
+    material: &mut TaperedScore,
+    piece_squares: &mut TaperedScore,
+) {
+    let sign = if is_white { 1 } else { -1 };
+    for (index, piece) in [
+        Piece::Pawn,
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Rook,
+        Piece::Queen,
+        Piece::King,
+    ]
+    .iter()
+    .enumerate()
+    {
+        let mut pieces = get_pieces(game, *piece, is_white);
+        // the pieces in the pocket in Variant::Crazyhouse can be dropped, so they count as material
+        let pocket = game.pockets[get_color_index(is_white)];
+        let in_pocket = pocket.get(index).copied().unwrap_or(0) as i32;
+        material.add(
+            (MIDDLEGAME_VALUES[index], ENDGAME_VALUES[index]),
+            sign * (pieces.count_ones() as i32 + in_pocket),
+        );
+        while pieces != EMPTY_BITBOARD {
+            let square = get_relative_square(pop_square(&mut pieces), is_white);
+            piece_squares.add(
+                (
+                    MIDDLEGAME_TABLES[index][square],
+                    ENDGAME_TABLES[index][square],
+                ),
+                sign,
+            );
+        }
+    }
+}
+
+fn add_mobility(game: &Game, is_white: bool, mobility: &mut TaperedScore) {
+    let sign = if is_white { 1 } else { -1 };
+    let occupied = get_occupied(game);
+    let team_pieces = game.color_bitboards[get_color_index(is_white)];
+
+    // squares attacked by the pawns of the other team are not safe to move to
+    let mut other_pawns = get_pieces(game, Piece::Pawn, !is_white);
+    let mut pawn_attacks = EMPTY_BITBOARD;
+    while other_pawns != EMPTY_BITBOARD {
+        pawn_attacks |= get_pawn_attacks(pop_square(&mut other_pawns), !is_white);
+    }
+
+    for (index, piece) in MINOR_AND_MAJOR_PIECES.iter().enumerate() {
+        let mut pieces = get_pieces(game, *piece, is_white);
+        while pieces != EMPTY_BITBOARD {
+            let attacks = get_piece_attacks(*piece, pop_square(&mut pieces), occupied);
+            let squares = (attacks & !team_pieces & !pawn_attacks).count_ones() as i32;
+            mobility.add(
+                MOBILITY_WEIGHTS[index],
+                sign * (squares - MOBILITY_BASE[index]),
+            );
+        }
+    }
+}
+
+/** Only counted in the middlegame, in the endgame the king should be active */
+fn add_king_safety(game: &Game, is_white: bool, king_safety: &mut TaperedScore) {
+    let sign = if is_white { 1 } else { -1 };
+    let king = get_pieces(game, Piece::King, is_white);
+    if king == EMPTY_BITBOARD {
+        return;
+    }
+    let king_square = king.trailing_zeros() as usize;
+    let king_x = king_square % BOARD_SIZE;
+    let king_y = king_square / BOARD_SIZE;
+
+    // the pawn shield only matters if the king is still at home
+    let spawn_y = if is_white { WHITE_SPAWN } else { BLACK_SPAWN };
+    if king_y == spawn_y {
+        let pawns = get_pieces(game, Piece::Pawn, is_white);
+        let shield_files = get_file_bitboard(king_x) | get_adjacent_files(king_x);
+        for (distance, bonus) in PAWN_SHIELD_BONUS.iter().enumerate() {
+            let y = if is_white {
+                king_y - distance - 1
+            } else {
+                king_y + distance + 1
+            };
+            let rank: Bitboard = 0xFF << (y * BOARD_SIZE);
+            let shield = (pawns & shield_files & rank).count_ones() as i32;
+            king_safety.add((*bonus, 0), sign * shield);
+        }
+    }
+
+    let king_zone = get_king_attacks(king_square) | king;
+    let occupied = get_occupied(game);
+    let mut attack_units = 0;
+    for (index, piece) in MINOR_AND_MAJOR_PIECES.iter().enumerate() {
+        let mut pieces = get_pieces(game, *piece, !is_white);
+        while pieces != EMPTY_BITBOARD {
+            let attacks = get_piece_attacks(*piece, pop_square(&mut pieces), occupied);
+            attack_units += KING_ATTACK_WEIGHTS[index] * (attacks & king_zone).count_ones() as i32;
+        }
+    }
+
+    // a few attackers are fine, but the danger grows fast with more of them
+    let penalty = (attack_units * attack_units / 2).min(MAX_KING_ATTACK_PENALTY);
+    king_safety.add((-penalty, 0), sign);
+}
+
+fn add_pawn_structure(game: &Game, is_white: bool, pawn_structure: &mut TaperedScore) {
+    let sign = if is_white { 1 } else { -1 };
+    let pawns = get_pieces(game, Piece::Pawn, is_white);
+    let other_pawns = get_pieces(game, Piece::Pawn, !is_white);
+
+    for x in 0..BOARD_SIZE {
+        let file_pawns = (pawns & get_file_bitboard(x)).count_ones() as i32;
+        if file_pawns > 1 {
+            pawn_structure.add(DOUBLED_PAWN_PENALTY, sign * (file_pawns - 1));
+        }
+    }
+
+    let mut remaining = pawns;
+    while remaining != EMPTY_BITBOARD {
+        let square = pop_square(&mut remaining);
+        let x = square % BOARD_SIZE;
+        let y = square / BOARD_SIZE;
+
+        if pawns & get_adjacent_files(x) == EMPTY_BITBOARD {
+            pawn_structure.add(ISOLATED_PAWN_PENALTY, sign);
+        }
+
+        // no pawn of the other team can stop it
+        let front_span =
+            (get_file_bitboard(x) | get_adjacent_files(x)) & get_forward_ranks(y, is_white);
+        if other_pawns & front_span == EMPTY_BITBOARD {
+            let advanced = if is_white {
+                WHITE_PAWN_Y.saturating_sub(y)
+            } else {
+                y.saturating_sub(BLACK_PAWN_Y)
+            };
+            pawn_structure.add(PASSED_PAWN_BONUS[advanced.min(BOARD_SIZE - 1)], sign);
+        }
+    }
+}
+
+/** Evaluates the position term by term, use evaluate if only the total is needed */
+pub fn get_evaluation(game: &Game) -> Evaluation {
+    let mut phase = 0;
+    for (index, weight) in PHASE_WEIGHTS.iter().enumerate() {
+        phase += weight * game.piece_bitboards[index].count_ones() as i32;
+    }
+    let phase = phase.min(MAX_PHASE);
+
+    let mut material = TaperedScore::default();
+    let mut piece_squares = TaperedScore::default();
+    let mut mobility = TaperedScore::default();
+    let mut king_safety = TaperedScore::default();
+    let mut pawn_structure = TaperedScore::default();
+    let mut bishop_pair = TaperedScore::default();
+
+    for is_white in [true, false] {
+        add_material_and_squares(game, is_white, &mut material, &mut piece_squares);
+        add_mobility(game, is_white, &mut mobility);
+        add_king_safety(game, is_white, &mut king_safety);
+        add_pawn_structure(game, is_white, &mut pawn_structure);
+
+        if get_pieces(game, Piece::Bishop, is_white).count_ones() >= 2 {
+            bishop_pair.add(BISHOP_PAIR_BONUS, if is_white { 1 } else { -1 });
+        }
+    }
+
+    let mut evaluation = Evaluation {
+        material: material.taper(phase),
+        piece_squares: piece_squares.taper(phase),
+        mobility: mobility.taper(phase),
+        king_safety: king_safety.taper(phase),
+        pawn_structure: pawn_structure.taper(phase),
+        bishop_pair: bishop_pair.taper(phase),
+        phase,
+        total: 0,
+    };
+    evaluation.total = evaluation.material
+        + evaluation.piece_squares
+        + evaluation.mobility
+        + evaluation.king_safety
+        + evaluation.pawn_structure
+        + evaluation.bishop_pair;
+    evaluation
+}
+
+/** A pawn for every piece, in Variant::Antichess every piece is one more that has to be lost */
+const ANTICHESS_PIECE_VALUE: i32 = 100;
+
+/**
+Centipawns from the view of white, positive if white is better.
+In Variant::Antichess only the number of pieces counts and the player with fewer pieces is better
+*/
+pub fn evaluate(game: &Game) -> i32 {
+    if game.variant == Variant::Antichess {
+        let pieces = |is_white: bool| game.color_bitboards[get_color_index(is_white)].count_ones();
+        return ANTICHESS_PIECE_VALUE * (pieces(false) as i32 - pieces(true) as i32);
+    }
+    get_evaluation(game).total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{get_board, STANDARD_BOARD};
+
+    fn evaluate_fen(fen: &str) -> Evaluation {
+        get_evaluation(&get_board(fen.to_string()).unwrap())
+    }
+
+    #[test]
+    fn symmetry_test() {
+        let evaluation = evaluate_fen(STANDARD_BOARD);
+        assert_eq!(
+            evaluation,
+            Evaluation {
+                phase: MAX_PHASE,
+                ..Default::default()
+            }
+        );
+
+        // the same position with the colors swapped
+        let white =
+            evaluate_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4");
+        let black =
+            evaluate_fen("rnbqk2r/pppp1ppp/5n2/2b1p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R b KQkq - 4 4");
+        assert_eq!(white.total, -black.total);
+        assert_eq!(white.mobility, -black.mobility);
+    }
+
+    #[test]
+    fn terms_test() {
+        // white is a knight up
+        let evaluation = evaluate_fen("rnbqkb1r/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert!(evaluation.material > 250);
+        assert!(evaluation.total > 250);
+
+        // only kings and pawns, the passed pawn on the sixth rank is worth a lot
+        let evaluation = evaluate_fen("4k3/8/1P6/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(evaluation.phase, 0);
+        assert_eq!(evaluation.pawn_structure, 60 + ISOLATED_PAWN_PENALTY.1);
+
+        // doubled pawns
+        let evaluation = evaluate_fen("4k3/8/8/8/8/3P4/2PP4/4K3 w - - 0 1");
+        assert!(evaluation.pawn_structure < 0);
+
+        let evaluation = evaluate_fen("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1");
+        assert_eq!(
+            evaluation.bishop_pair,
+            (BISHOP_PAIR_BONUS.0 * 2 + BISHOP_PAIR_BONUS.1 * 22) / MAX_PHASE
+        );
+
+        // the queen next to the castled king
+        let safe = evaluate_fen("r4rk1/5ppp/8/8/8/8/5PPP/R4RK1 w - - 0 1");
+        let attacked = evaluate_fen("r4rk1/5ppp/8/8/8/8/5PPq/R4RK1 w - - 0 1");
+        assert!(attacked.king_safety < safe.king_safety);
+    }
+}
@@ -1,10 +1,31 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::bitboard::Bitboard;
 
 pub const BOARD_SIZE: usize = 8;
 pub const BLACK_SPAWN: usize = 0;
 pub const WHITE_SPAWN: usize = BOARD_SIZE - 1;
 pub const WHITE_PAWN_Y: usize = BOARD_SIZE - 2;
 pub const BLACK_PAWN_Y: usize = 1;
+/** every piece a pawn can promote to, every choice is its own legal move */
+pub const PROMOTION_PIECES: [Piece; 4] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight];
+/** in Variant::Antichess the king is an ordinary piece that a pawn can also promote to */
+pub const ANTICHESS_PROMOTION_PIECES: [Piece; 5] = [
+    Piece::Queen,
+    Piece::Rook,
+    Piece::Bishop,
+    Piece::Knight,
+    Piece::King,
+];
+/** the pieces that can be in a pocket in Variant::Crazyhouse, same order as Game::pockets */
+pub const POCKET_PIECES: [Piece; 5] = [
+    Piece::Pawn,
+    Piece::Knight,
+    Piece::Bishop,
+    Piece::Rook,
+    Piece::Queen,
+];
 pub const EMPTY_PEICE: PieceData = PieceData {
     piece: Piece::None,
     is_white: false,
@@ -37,12 +58,19 @@ pub struct PieceData {
     pub is_white: bool,
 }
 
+/** How one team threatens the other team, generated by get_threats */
 pub struct ThreatMap {
-    pub all_moves: HashSet<Position>,
-    pub all_threats: HashSet<Position>,
-    pub all_pinned: Vec<HashSet<Position>>,
-    pub all_king_threats: HashSet<Position>,
-    pub all_king_threats_full: HashSet<Position>,
+    /** every square the team attacks, the other king is seen through so it cant step back along an attack */
+    pub all_threats: Bitboard,
+    /** the pieces that checks the king of the other team */
+    pub all_king_threats: Bitboard,
+    /**
+    the squares the other team has to move to in order to capture or block a single check,
+    all squares if there is no check and no squares if there is a double check
+    */
+    pub check_mask: Bitboard,
+    /** the pieces of the other team that are pinned to their king */
+    pub all_pinned: Bitboard,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
@@ -54,6 +82,53 @@ pub struct Castle {
     pub king_side_rook: Position,
 }
 
+/** The rules the game is played with, chosen when the game board is created */
+#[derive(PartialEq, Eq, Debug, Hash, Clone, Copy)]
+pub enum Variant {
+    Standard,
+    /** a player also wins by checking the other king three times, the checks are counted in the FEN like +2+1 */
+    ThreeCheck,
+    /** a player also wins by moving the king to d4, e4, d5 or e5 */
+    KingOfTheHill,
+    /**
+    Captures explode the capturing piece and every piece that is not a pawn next to the capture,
+    kings can not capture and a player wins by exploding the other king
+    */
+    Atomic,
+    /**
+    Captured pieces go to the pocket of the player that captured them and can be dropped on an empty square
+    instead of moving, a promoted piece goes back to the pocket as a pawn
+    */
+    Crazyhouse,
+    /**
+    Captures are compulsory and the king is an ordinary piece that can be captured, there is no check and no castling.
+    A player wins by losing all pieces or by being stalemated
+    */
+    Antichess,
+    /**
+    White has 36 pawns and no king, the pawns on the first rank can also move two squares.
+    Black wins by capturing every white piece and white by checkmating the black king
+    */
+    Horde,
+    /**
+    Both players start on the first two ranks and no move can give check, the first king to reach the eighth rank wins.
+    When the white king gets there first black gets one more move to also reach it and draw
+    */
+    RacingKings,
+}
+
+/** Every variant in the order they are declared, used to read a variant back from its name */
+pub const VARIANTS: [Variant; 8] = [
+    Variant::Standard,
+    Variant::ThreeCheck,
+    Variant::KingOfTheHill,
+    Variant::Atomic,
+    Variant::Crazyhouse,
+    Variant::Antichess,
+    Variant::Horde,
+    Variant::RacingKings,
+];
+
 /** 0,0 is the top left; 8,8 is the bottom right */
 #[derive(PartialEq, Eq, Debug, Hash, Clone, Copy)]
 pub struct Position {
@@ -62,14 +137,57 @@ pub struct Position {
 }
 
 /**You might want to implement names for the players or want to extend with any metadata then add it here */
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Gameboard {
     /** all data used for the game logic */
     pub game: Game,
     /**
-    Used for 3 fold repetition Format in FEN string
+    Used for 3 fold repetition, the key is from get_repetition_key so positions are the same
+    if they have the same pieces, player to move, castle rights and en passant capture
+    */
+    pub same_board: HashMap<u64, u8>,
+    /** all moves that has been played, the last move is the most recent one */
+    pub history: Vec<Move>,
+    /** moves that has been taken back with undo_move, cleared as soon as a new move is played */
+    pub redo_history: Vec<Move>,
+    /**
+    A result that can not be seen on the board, like a resignation, a loss on time or an agreed draw.
+    get_game_state returns it when it is set
     */
-    pub same_board: HashMap<String, u8>,
+    pub result: Option<GameResult>,
+    /** the player who has offered a draw, true if it is white. The offer is declined when the other player moves */
+    pub draw_offer: Option<bool>,
+}
+
+/**
+A single move, it also stores the state before the move so that it can be taken back.
+For castling the move is stored as the king move, e1g1 for white king side.
+In Chess960 it is stored as the king taking its own rook, e1h1 for white king side.
+A drop in Variant::Crazyhouse has the same from and to, piece is the piece taken from the pocket.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Move {
+    pub from: Position,
+    pub to: Position,
+    /** Piece::None if the move is not a promotion or if the promotion is still pending */
+    pub promotion: Piece,
+    /** the piece that was moved */
+    pub piece: PieceData,
+    /** EMPTY_PEICE if nothing was captured, for en passant this is the captured pawn */
+    pub captured: PieceData,
+    /**
+    The pieces on the squares next to the end square in the order of the squares before the move,
+    only stored for captures in Variant::Atomic so the explosion can be taken back
+    */
+    pub exploded: [PieceData; 8],
+
+    // state before the move was played
+    pub castle: [Castle; 2],
+    pub pockets: [[u8; 5]; 2],
+    pub promoted: Bitboard,
+    pub en_passant_position: Option<Position>,
+    pub half_move_clock: u16,
+    pub full_move_clock: u16,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -81,6 +199,25 @@ pub struct Game {
 
     pub is_white_to_move: bool,
 
+    /**
+    Fischer Random Chess https://en.wikipedia.org/wiki/Fischer_random_chess
+    Castling moves are the king taking its own rook and the FEN uses the files of the rooks
+    */
+    pub is_chess960: bool,
+
+    pub variant: Variant,
+
+    /** The number of checks each player has given, only counted in Variant::ThreeCheck. 0 is the white player */
+    pub checks: [u8; 2],
+
+    /**
+    The number of pieces each player can drop in Variant::Crazyhouse, in the order of POCKET_PIECES.
+    0 is the white player
+    */
+    pub pockets: [[u8; 5]; 2],
+    /** The pieces that was promoted from pawns, only kept in Variant::Crazyhouse as they are captured as pawns */
+    pub promoted: Bitboard,
+
     /**
     This is recorded regardless of whether there is a pawn in position to make an en passant capture.
     */
@@ -95,6 +232,20 @@ pub struct Game {
     Fullmove number: The number of the full move. It starts at 1, and is incremented after Black's move.
     */
     pub full_move_clock: u16,
+
+    /**
+    One bitboard per piece in the same order as Piece but without Piece::None.
+    Always kept in sync with board, so change the board with bitboard::set_square
+    */
+    pub piece_bitboards: [Bitboard; 6],
+    /** 0 is the white player and 1 is the black player, kept in sync with board */
+    pub color_bitboards: [Bitboard; 2],
+
+    /**
+    Zobrist hash of the pieces, side to move, castle rights, en passant file, checks and pockets.
+    Updated for every move so it can be used as a key for the position
+    */
+    pub hash: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
@@ -103,11 +254,6 @@ pub struct Vector2 {
     pub y: i8,
 }
 
-pub(crate) struct Moveset {
-    pub(crate) regular_moves: &'static [Vector2],
-    pub(crate) inf_moves: &'static [Vector2],
-}
-
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum WinStatus {
     WhiteWon,
@@ -116,74 +262,227 @@ pub enum WinStatus {
     Nothing,
 }
 
-const DIAGONAL_MOVESET: &'static [Vector2; 4] = &[
-    Vector2 { x: 1, y: 1 },
-    Vector2 { x: -1, y: -1 },
-    Vector2 { x: -1, y: 1 },
-    Vector2 { x: 1, y: -1 },
-];
+/** Why the game ended */
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GameEndReason {
+    Checkmate,
+    Resignation,
+    /** a player ran out of time, a tie if the other player can not checkmate */
+    Timeout,
+    /** both players agreed to a draw */
+    Agreement,
+    Stalemate,
+    /** the same position three times, only a tie when it is claimed */
+    ThreefoldRepetition,
+    /** the same position five times */
+    FivefoldRepetition,
+    /** 50 moves by each player without a capture or pawn move, only a tie when it is claimed */
+    FiftyMoveRule,
+    /** 75 moves by each player without a capture or pawn move */
+    SeventyFiveMoveRule,
+    /** neither player has enough pieces to checkmate */
+    InsufficientMaterial,
+    /** no series of legal moves can end in checkmate, even if there is material left */
+    DeadPosition,
+    /** a king is missing from the board */
+    KingCaptured,
+    /** the third check in Variant::ThreeCheck */
+    ThirdCheck,
+    /** a king reached the center in Variant::KingOfTheHill */
+    KingInCenter,
+    /** a player has no pieces left, that player wins in Variant::Antichess and loses in Variant::Horde */
+    AllPiecesLost,
+    /** a king reached the eighth rank in Variant::RacingKings, a tie if both kings got there */
+    KingInGoal,
+}
 
-const HORIZONTAL_MOVESET: &'static [Vector2; 4] = &[
-    Vector2 { x: 0, y: 1 },
-    Vector2 { x: 0, y: -1 },
-    Vector2 { x: -1, y: 0 },
-    Vector2 { x: 1, y: 0 },
+/** Every reason in the order they are declared, used to read a reason back from its text */
+pub const GAME_END_REASONS: [GameEndReason; 16] = [
+    GameEndReason::Checkmate,
+    GameEndReason::Resignation,
+    GameEndReason::Timeout,
+    GameEndReason::Agreement,
+    GameEndReason::Stalemate,
+    GameEndReason::ThreefoldRepetition,
+    GameEndReason::FivefoldRepetition,
+    GameEndReason::FiftyMoveRule,
+    GameEndReason::SeventyFiveMoveRule,
+    GameEndReason::InsufficientMaterial,
+    GameEndReason::DeadPosition,
+    GameEndReason::KingCaptured,
+    GameEndReason::ThirdCheck,
+    GameEndReason::KingInCenter,
+    GameEndReason::AllPiecesLost,
+    GameEndReason::KingInGoal,
 ];
 
-const BOTH_MOVESET: &'static [Vector2; 8] = &[
-    Vector2 { x: 0, y: 1 },
-    Vector2 { x: 0, y: -1 },
-    Vector2 { x: -1, y: 0 },
-    Vector2 { x: 1, y: 0 },
-    Vector2 { x: 1, y: 1 },
-    Vector2 { x: -1, y: -1 },
-    Vector2 { x: -1, y: 1 },
-    Vector2 { x: 1, y: -1 },
-];
+/** How the game ended and why, reason is None while the game is still being played */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GameResult {
+    pub outcome: WinStatus,
+    pub reason: Option<GameEndReason>,
+}
 
-const KNIGHT_MOVESET: &'static [Vector2; 8] = &[
-    Vector2 { x: 2, y: 1 },
-    Vector2 { x: 1, y: 2 },
-    Vector2 { x: -2, y: 1 },
-    Vector2 { x: -1, y: 2 },
-    Vector2 { x: 2, y: -1 },
-    Vector2 { x: 1, y: -2 },
-    Vector2 { x: -2, y: -1 },
-    Vector2 { x: -1, y: -2 },
+pub const GAME_ONGOING: GameResult = GameResult {
+    outcome: WinStatus::Nothing,
+    reason: None,
+};
+
+/** Why a move or promotion was refused */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IllegalMoveReason {
+    /** the start or end square is outside of the board */
+    OutsideBoard,
+    /** there is no piece on the start square */
+    NoPiece,
+    NotYourTurn,
+    CaptureOwnPiece,
+    /** the piece can not move like that, even if the king was safe */
+    InvalidMovement,
+    /** the piece would reveal an attack on its own king */
+    PiecePinned,
+    /** the king is in check and the move does not stop it */
+    KingInCheck,
+    /** the king would move to an attacked square */
+    MovesIntoCheck,
+    /** no move can give check in Variant::RacingKings */
+    GivesCheck,
+    /** a pawn has reached the last rank and has to be promoted with promote_pawn first */
+    PromotionPending,
+    /** a capture in Variant::Atomic would explode the king of the player that captures */
+    ExplodesOwnKing,
+    /** the piece is not in the pocket of the player in Variant::Crazyhouse */
+    NotInPocket,
+    /** pieces can only be dropped on empty squares and pawns not on the first or last rank */
+    InvalidDrop,
+    /** a capture is possible in Variant::Antichess, so the move has to be a capture */
+    CaptureRequired,
+    /** promote_pawn was called without a pawn on the last rank */
+    NoPromotion,
+    /** pawns can only be promoted to a queen, rook, bishop or knight, and a king in Variant::Antichess */
+    InvalidPromotion,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChessError {
+    /** field is the index of the space separated FEN field that failed, 0 is the board */
+    InvalidFen {
+        field: usize,
+        reason: String,
+    },
+    /** the input could not be parsed */
+    InvalidInput(String),
+    /** game is the number of the game in the file, starting at 1 */
+    InvalidPgn {
+        game: usize,
+        reason: String,
+    },
+    IllegalMove(IllegalMoveReason),
+    NothingToUndo,
+    NothingToRedo,
+    /** a draw was claimed without threefold repetition or 50 moves without a capture or pawn move */
+    NoDrawToClaim,
+    /** there is no draw offer from the other player to accept or decline */
+    NoDrawOffer,
+    /** the game already has a result */
+    GameOver,
+}
+
+impl fmt::Display for IllegalMoveReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            IllegalMoveReason::OutsideBoard => "outside of the board",
+            IllegalMoveReason::NoPiece => "no piece to move",
+            IllegalMoveReason::NotYourTurn => "not your turn",
+            IllegalMoveReason::CaptureOwnPiece => "can not capture own piece",
+            IllegalMoveReason::InvalidMovement => "the piece can not move there",
+            IllegalMoveReason::PiecePinned => "piece pinned",
+            IllegalMoveReason::KingInCheck => "king in check",
+            IllegalMoveReason::MovesIntoCheck => "king would be in check",
+            IllegalMoveReason::GivesCheck => "the move would give check",
+            IllegalMoveReason::ExplodesOwnKing => "own king would explode",
+            IllegalMoveReason::NotInPocket => "the piece is not in the pocket",
+            IllegalMoveReason::InvalidDrop => "the piece can not be dropped there",
+            IllegalMoveReason::CaptureRequired => "a capture is required",
+            IllegalMoveReason::PromotionPending => "promotion pending",
+            IllegalMoveReason::NoPromotion => "no pawn to promote",
+            IllegalMoveReason::InvalidPromotion => "invalid promotion piece",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+impl fmt::Display for GameEndReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            GameEndReason::Checkmate => "checkmate",
+            GameEndReason::Resignation => "resignation",
+            GameEndReason::Timeout => "timeout",
+            GameEndReason::Agreement => "agreement",
+            GameEndReason::Stalemate => "stalemate",
+            GameEndReason::ThreefoldRepetition => "threefold repetition",
+            GameEndReason::FivefoldRepetition => "fivefold repetition",
+            GameEndReason::FiftyMoveRule => "fifty-move rule",
+            GameEndReason::SeventyFiveMoveRule => "seventy-five-move rule",
+            GameEndReason::InsufficientMaterial => "insufficient material",
+            GameEndReason::DeadPosition => "dead position",
+            GameEndReason::KingCaptured => "king captured",
+            GameEndReason::ThirdCheck => "third check",
+            GameEndReason::KingInCenter => "king in the center",
+            GameEndReason::AllPiecesLost => "all pieces lost",
+            GameEndReason::KingInGoal => "king reached the eighth rank",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+/** The name used in the Variant tag of PGN */
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Variant::Standard => "Standard",
+            Variant::ThreeCheck => "Three-check",
+            Variant::KingOfTheHill => "King of the Hill",
+            Variant::Atomic => "Atomic",
+            Variant::Crazyhouse => "Crazyhouse",
+            Variant::Antichess => "Antichess",
+            Variant::Horde => "Horde",
+            Variant::RacingKings => "Racing Kings",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/** the names of the FEN fields, in the same order as they are written */
+const FEN_FIELDS: [&str; 7] = [
+    "board",
+    "side to move",
+    "castling",
+    "en passant",
+    "halfmove clock",
+    "fullmove number",
+    "check count",
 ];
 
-const EMPTY_MOVESET: &'static [Vector2; 0] = &[];
-
-/** GET MOVESET, WONT WORK FOR PAWN */
-pub(crate) fn get_moveset(piece: Piece) -> Moveset {
-    match piece {
-        Piece::None => Moveset {
-            regular_moves: EMPTY_MOVESET,
-            inf_moves: EMPTY_MOVESET,
-        },
-        Piece::Pawn => Moveset {
-            regular_moves: EMPTY_MOVESET,
-            inf_moves: EMPTY_MOVESET,
-        },
-        Piece::Knight => Moveset {
-            regular_moves: KNIGHT_MOVESET,
-            inf_moves: EMPTY_MOVESET,
-        },
-        Piece::Bishop => Moveset {
-            regular_moves: EMPTY_MOVESET,
-            inf_moves: DIAGONAL_MOVESET,
-        },
-        Piece::Rook => Moveset {
-            regular_moves: EMPTY_MOVESET,
-            inf_moves: HORIZONTAL_MOVESET,
-        },
-        Piece::Queen => Moveset {
-            regular_moves: EMPTY_MOVESET,
-            inf_moves: BOTH_MOVESET,
-        },
-        Piece::King => Moveset {
-            regular_moves: BOTH_MOVESET,
-            inf_moves: EMPTY_MOVESET,
-        },
+impl fmt::Display for ChessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChessError::InvalidFen { field, reason } => match FEN_FIELDS.get(*field) {
+                Some(name) => write!(f, "invalid FEN field {} ({}): {}", field, name, reason),
+                None => write!(f, "invalid FEN: {}", reason),
+            },
+            ChessError::InvalidInput(input) => write!(f, "invalid input: {}", input),
+            ChessError::InvalidPgn { game, reason } => {
+                write!(f, "invalid PGN in game {}: {}", game, reason)
+            }
+            ChessError::IllegalMove(reason) => write!(f, "illegal move: {}", reason),
+            ChessError::NothingToUndo => write!(f, "no move to undo"),
+            ChessError::NothingToRedo => write!(f, "no move to redo"),
+            ChessError::NoDrawToClaim => write!(f, "no draw to claim"),
+            ChessError::NoDrawOffer => write!(f, "no draw offer"),
+            ChessError::GameOver => write!(f, "the game is over"),
+        }
     }
 }
+
+impl std::error::Error for ChessError {}
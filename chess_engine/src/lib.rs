@@ -1,28 +1,37 @@
+pub mod bitboard;
+pub mod clock;
+pub mod evaluation;
 pub mod game_data;
 pub mod logic;
 pub mod parser;
+pub mod perft;
+pub mod pgn;
 pub mod render;
+pub mod search;
+pub mod uci;
+pub mod zobrist;
 
 #[cfg(test)]
 mod tests {
     use crate::game_data::*;
     use crate::logic::*;
     use crate::parser::*;
+    use crate::perft::*;
 
     fn load_board(board: &str, moves: Vec<&str>) -> Option<(String, bool)> {
         let mut game_board = match init_game_board(board.to_string()) {
-            Some(gm) => gm,
-            None => return None,
+            Ok(gm) => gm,
+            Err(_) => return None,
         };
 
         let mut is_successful = true;
         for input in moves {
             let (move_start, move_end) = match parse_move(&input) {
-                Some(t) => t,
-                None => return None,
+                Ok(t) => t,
+                Err(_) => return None,
             };
             let has_moves = move_piece_no_map(&mut game_board, move_start, move_end, true);
-            if !has_moves {
+            if has_moves.is_err() {
                 is_successful = false;
                 break;
             }
@@ -30,8 +39,8 @@ mod tests {
         //let flags = last_flags.iter().map(|s| => s).coll;
         return Some((
             match get_fen(&game_board.game) {
-                Some(str) => str,
-                None => return None,
+                Ok(str) => str,
+                Err(_) => return None,
             },
             is_successful,
         ));
@@ -67,6 +76,973 @@ mod tests {
         );
     }
 
+    #[test]
+    fn insufficient_material_test() {
+        for (fen, white, black) in [
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", true, true),
+            ("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", false, true),
+            ("4k3/8/8/8/8/8/8/1N2K3 w - - 0 1", true, true),
+            ("4k3/8/8/8/8/8/8/NN2K3 w - - 0 1", false, true),
+            // a knight or bishop can mate if the other king is blocked in by its own pieces
+            ("4kb2/8/8/8/8/8/8/1N2K3 w - - 0 1", false, false),
+            ("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1", false, true),
+            ("4k3/8/8/8/8/8/8/B1B1K3 w - - 0 1", true, true),
+            ("1b2k3/8/8/8/8/8/8/B3K3 w - - 0 1", true, true),
+            ("2b1k3/8/8/8/8/8/8/B3K3 w - - 0 1", false, false),
+            ("4k3/p7/8/8/8/8/8/B3K3 w - - 0 1", false, false),
+            ("7k/8/8/8/8/8/8/K1b1R3 w - - 0 1", false, false),
+        ] {
+            let game = init_game_board(fen.to_string()).unwrap().game;
+            assert_eq!(has_insufficient_material(&game, true), white, "{}", fen);
+            assert_eq!(has_insufficient_material(&game, false), black, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn game_result_test() {
+        let get_result = |fen: &str, moves: Vec<&str>| {
+            let mut game_board = init_game_board(fen.to_string()).unwrap();
+            for input in moves {
+                let (move_start, move_end) = parse_move(input).unwrap();
+                move_piece_no_map(&mut game_board, move_start, move_end, true).unwrap();
+            }
+            get_game_state(&game_board, &get_threats(&game_board), false)
+        };
+        let get_tie = |reason| GameResult {
+            outcome: WinStatus::Tie,
+            reason: Some(reason),
+        };
+
+        assert_eq!(get_result(STANDARD_BOARD, vec![]), GAME_ONGOING);
+        assert_eq!(
+            get_result(STANDARD_BOARD, vec!["f2f3", "e7e5", "g2g4", "d8h4"]),
+            GameResult {
+                outcome: WinStatus::BlackWon,
+                reason: Some(GameEndReason::Checkmate),
+            }
+        );
+        assert_eq!(
+            get_result("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", vec![]),
+            get_tie(GameEndReason::Stalemate)
+        );
+        assert_eq!(
+            get_result("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1", vec![]),
+            get_tie(GameEndReason::InsufficientMaterial)
+        );
+
+        // the fifty-move rule has to be claimed, after 75 moves it is a draw by itself
+        let fifty_moves = "4k3/8/8/8/8/8/8/R3K3 w - - 99 80";
+        assert_eq!(get_result(fifty_moves, vec!["a1a2"]), GAME_ONGOING);
+        assert_eq!(
+            get_result("4k3/8/8/8/8/8/8/R3K3 w - - 149 80", vec!["a1a2"]),
+            get_tie(GameEndReason::SeventyFiveMoveRule)
+        );
+        // and no move can be played after it
+        let mut game_board =
+            init_game_board("4k3/8/8/8/8/8/8/R3K3 w - - 150 80".to_string()).unwrap();
+        let (move_start, move_end) = parse_move("a1a2").unwrap();
+        assert_eq!(
+            move_piece_no_map(&mut game_board, move_start, move_end, true),
+            Err(ChessError::GameOver)
+        );
+        // a pawn move resets the count
+        assert_eq!(
+            get_result("4k3/8/8/8/8/8/P7/R3K3 w - - 149 80", vec!["a2a3"]),
+            GAME_ONGOING
+        );
+        // checkmate on the last move wins
+        assert_eq!(
+            get_result("6k1/5ppp/8/8/8/8/8/R3K3 w - - 149 80", vec!["a1a8"]).reason,
+            Some(GameEndReason::Checkmate)
+        );
+
+        let mut game_board = init_game_board(fifty_moves.to_string()).unwrap();
+        assert!(!can_claim_fifty_move_draw(&game_board));
+        assert_eq!(
+            claim_fifty_move_draw(&mut game_board),
+            Err(ChessError::NoDrawToClaim)
+        );
+        let (move_start, move_end) = parse_move("a1a2").unwrap();
+        move_piece_no_map(&mut game_board, move_start, move_end, true).unwrap();
+        assert!(can_claim_fifty_move_draw(&game_board));
+        assert_eq!(
+            get_game_state(&game_board, &get_threats(&game_board), true),
+            get_tie(GameEndReason::FiftyMoveRule)
+        );
+        assert_eq!(claim_fifty_move_draw(&mut game_board), Ok(()));
+        assert_eq!(
+            get_game_state(&game_board, &get_threats(&game_board), false),
+            get_tie(GameEndReason::FiftyMoveRule)
+        );
+
+        // threefold repetition is only a tie when it is forced, fivefold always is
+        let knight_moves = vec!["g1f3", "g8f6", "f3g1", "f6g8"];
+        let repeated = [knight_moves.clone(), knight_moves.clone()].concat();
+        assert_eq!(get_result(STANDARD_BOARD, repeated.clone()), GAME_ONGOING);
+        let mut game_board = init_game_board(STANDARD_BOARD.to_string()).unwrap();
+        for input in &repeated {
+            let (move_start, move_end) = parse_move(input).unwrap();
+            move_piece_no_map(&mut game_board, move_start, move_end, true).unwrap();
+        }
+        assert_eq!(
+            get_game_state(&game_board, &get_threats(&game_board), true),
+            get_tie(GameEndReason::ThreefoldRepetition)
+        );
+        let repeated = [repeated.clone(), repeated].concat();
+        assert_eq!(
+            get_result(STANDARD_BOARD, repeated),
+            get_tie(GameEndReason::FivefoldRepetition)
+        );
+
+        // results that can not be seen on the board are stored on the game board
+        game_board.result = Some(GameResult {
+            outcome: WinStatus::WhiteWon,
+            reason: Some(GameEndReason::Resignation),
+        });
+        assert_eq!(
+            get_game_state(&game_board, &get_threats(&game_board), true),
+            game_board.result.unwrap()
+        );
+    }
+
+    #[test]
+    fn dead_position_test() {
+        let get_reason = |fen: &str| {
+            let game_board = init_game_board(fen.to_string()).unwrap();
+            get_game_state(&game_board, &get_threats(&game_board), false).reason
+        };
+
+        // bishops that are all on the same color can never checkmate
+        assert_eq!(
+            get_reason("1b2k3/8/8/8/8/8/1B6/2B1K3 w - - 0 1"),
+            Some(GameEndReason::InsufficientMaterial)
+        );
+        assert_eq!(get_reason("1b2k3/8/8/8/8/8/8/1B2KB2 w - - 0 1"), None);
+        // two knights can not force mate but it is still possible
+        assert_eq!(get_reason("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1"), None);
+
+        // the kings can not get past the locked pawns
+        assert_eq!(
+            get_reason("8/8/4k3/p1p1p1p1/P1P1P1P1/4K3/8/8 w - - 0 1"),
+            Some(GameEndReason::DeadPosition)
+        );
+        // the white king can walk around the pawns and take e5
+        assert_eq!(get_reason("8/8/4k3/p1p1p3/P1P1P3/4K3/8/8 w - - 0 1"), None);
+        // the pawns can capture each other
+        assert_eq!(
+            get_reason("8/8/4k3/p1pp2p1/P1PP2P1/4K3/8/8 w - - 0 1"),
+            None
+        );
+        assert_eq!(
+            get_reason("8/8/4k3/p1p1p1p1/P1P1P1P1/4K3/8/7R w - - 0 1"),
+            None
+        );
+
+        // a pawn waiting to be promoted is not locked
+        for (fen, input) in [
+            ("8/P7/8/8/8/8/8/k6K w - - 0 1", "a7a8"),
+            ("K7/8/8/8/8/8/p7/7k b - - 0 1", "a2a1"),
+        ] {
+            let mut game_board = init_game_board(fen.to_string()).unwrap();
+            let (move_start, move_end) = parse_move(input).unwrap();
+            move_piece_no_map(&mut game_board, move_start, move_end, false).unwrap();
+            assert_eq!(
+                get_game_state(&game_board, &get_threats(&game_board), false),
+                GAME_ONGOING
+            );
+        }
+    }
+
+    #[test]
+    fn repetition_test() {
+        let get_key = |fen: &str| get_repetition_key(&get_board(fen.to_string()).unwrap());
+
+        // the en passant square only counts when the pawn can be taken
+        assert_eq!(
+            get_key("4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1"),
+            get_key("4k3/8/8/8/4P3/8/8/4K3 b - - 0 1")
+        );
+        assert_ne!(
+            get_key("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1"),
+            get_key("4k3/8/8/8/3pP3/8/8/4K3 b - - 0 1")
+        );
+        // taking en passant would leave the king in check from the rook
+        assert_eq!(
+            get_key("8/8/8/8/k2pP2R/8/8/4K3 b - e3 0 1"),
+            get_key("8/8/8/8/k2pP2R/8/8/4K3 b - - 0 1")
+        );
+        assert_ne!(
+            get_key(STANDARD_BOARD),
+            get_key(&STANDARD_BOARD.replace("KQkq", "Qkq"))
+        );
+        assert_ne!(
+            get_key(STANDARD_BOARD),
+            get_key(&STANDARD_BOARD.replace(" w ", " b "))
+        );
+
+        // the position after e4 is repeated even if it was first reached with an en passant square
+        let knight_moves = ["g8f6", "g1f3", "f6g8", "f3g1"];
+        let moves = [&["e2e4"], &knight_moves[..], &knight_moves[..]].concat();
+        let game_board =
+            init_game_board_with_moves(STANDARD_BOARD.to_string(), Variant::Standard, &moves)
+                .unwrap();
+        assert_eq!(
+            get_claimable_draw(&game_board),
+            Some(GameEndReason::ThreefoldRepetition)
+        );
+
+        // a game loaded in the middle knows nothing about the earlier positions until they are seeded
+        let fen = get_fen(&game_board.game).unwrap();
+        let mut game_board = init_game_board(fen.clone()).unwrap();
+        assert_eq!(get_claimable_draw(&game_board), None);
+        seed_repetition_history(&mut game_board, &[&fen, &fen]).unwrap();
+        assert_eq!(
+            get_claimable_draw(&game_board),
+            Some(GameEndReason::ThreefoldRepetition)
+        );
+        assert!(seed_repetition_history(&mut game_board, &["not a fen"]).is_err());
+        assert!(init_game_board_with_moves(
+            STANDARD_BOARD.to_string(),
+            Variant::Standard,
+            &["e2e5"]
+        )
+        .is_err());
+
+        // the moves are played with the rules of the variant
+        let crazyhouse = get_start_fen(Variant::Crazyhouse);
+        let moves = ["e2e4", "d7d5", "e4d5", "d8d5", "P@e6"];
+        let game_board =
+            init_game_board_with_moves(crazyhouse.clone(), Variant::Crazyhouse, &moves).unwrap();
+        assert_eq!(game_board.history.len(), moves.len());
+        assert!(init_game_board_with_moves(crazyhouse, Variant::Standard, &moves).is_err());
+    }
+
+    #[test]
+    fn draw_offer_test() {
+        let mut game_board = init_game_board(STANDARD_BOARD.to_string()).unwrap();
+        assert_eq!(
+            accept_draw(&mut game_board, false),
+            Err(ChessError::NoDrawOffer)
+        );
+
+        // a move by the other player declines the offer
+        offer_draw(&mut game_board, true).unwrap();
+        assert_eq!(game_board.draw_offer, Some(true));
+        let (move_start, move_end) = parse_move("e2e4").unwrap();
+        move_piece_no_map(&mut game_board, move_start, move_end, true).unwrap();
+        assert_eq!(game_board.draw_offer, Some(true));
+        let (move_start, move_end) = parse_move("e7e5").unwrap();
+        move_piece_no_map(&mut game_board, move_start, move_end, true).unwrap();
+        assert_eq!(game_board.draw_offer, None);
+
+        offer_draw(&mut game_board, true).unwrap();
+        assert_eq!(
+            decline_draw(&mut game_board, true),
+            Err(ChessError::NoDrawOffer)
+        );
+        assert_eq!(decline_draw(&mut game_board, false), Ok(()));
+        assert_eq!(game_board.draw_offer, None);
+
+        offer_draw(&mut game_board, false).unwrap();
+        assert_eq!(accept_draw(&mut game_board, true), Ok(()));
+        assert_eq!(
+            get_game_state(&game_board, &get_threats(&game_board), false),
+            GameResult {
+                outcome: WinStatus::Tie,
+                reason: Some(GameEndReason::Agreement),
+            }
+        );
+        assert_eq!(offer_draw(&mut game_board, true), Err(ChessError::GameOver));
+        let (move_start, move_end) = parse_move("g1f3").unwrap();
+        assert_eq!(
+            move_piece_no_map(&mut game_board, move_start, move_end, true),
+            Err(ChessError::GameOver)
+        );
+    }
+
+    #[test]
+    fn resign_test() {
+        let mut game_board = init_game_board(STANDARD_BOARD.to_string()).unwrap();
+        offer_draw(&mut game_board, false).unwrap();
+        assert_eq!(resign(&mut game_board, false), Ok(()));
+        assert_eq!(game_board.draw_offer, None);
+        assert_eq!(
+            get_game_state(&game_board, &get_threats(&game_board), true),
+            GameResult {
+                outcome: WinStatus::WhiteWon,
+                reason: Some(GameEndReason::Resignation),
+            }
+        );
+        assert_eq!(resign(&mut game_board, true), Err(ChessError::GameOver));
+
+        // no move can be played or taken back after a resignation
+        let mut game_board = init_game_board(STANDARD_BOARD.to_string()).unwrap();
+        let (move_start, move_end) = parse_move("e2e4").unwrap();
+        move_piece_no_map(&mut game_board, move_start, move_end, true).unwrap();
+        undo_move(&mut game_board).unwrap();
+        resign(&mut game_board, true).unwrap();
+        assert_eq!(
+            move_piece_no_map(&mut game_board, move_start, move_end, true),
+            Err(ChessError::GameOver)
+        );
+        assert_eq!(redo_move(&mut game_board), Err(ChessError::GameOver));
+        assert_eq!(undo_move(&mut game_board), Err(ChessError::GameOver));
+        assert_eq!(get_fen(&game_board.game).unwrap(), STANDARD_BOARD);
+
+        // there is nothing to resign after checkmate
+        let mut game_board = init_game_board(
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3".to_string(),
+        )
+        .unwrap();
+        assert_eq!(resign(&mut game_board, true), Err(ChessError::GameOver));
+    }
+
+    #[test]
+    fn claim_draw_test() {
+        let mut game_board = init_game_board(STANDARD_BOARD.to_string()).unwrap();
+        for input in ["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1"] {
+            let (move_start, move_end) = parse_move(input).unwrap();
+            move_piece_no_map(&mut game_board, move_start, move_end, true).unwrap();
+        }
+        assert_eq!(get_claimable_draw(&game_board), None);
+        assert_eq!(claim_draw(&mut game_board), Err(ChessError::NoDrawToClaim));
+
+        // the claim can be made with the move that repeats the position
+        let fen = get_fen(&game_board.game).unwrap();
+        let illegal_move = parse_long_move(&game_board.game, "f6h5").unwrap();
+        assert!(claim_draw_with_move(&mut game_board, illegal_move).is_err());
+        let no_draw_move = parse_san(&game_board.game, "Nd5").unwrap();
+        assert_eq!(
+            claim_draw_with_move(&mut game_board, no_draw_move),
+            Err(ChessError::NoDrawToClaim)
+        );
+        assert_eq!(get_fen(&game_board.game).unwrap(), fen);
+
+        let repeating_move = parse_san(&game_board.game, "Ng8").unwrap();
+        assert_eq!(
+            claim_draw_with_move(&mut game_board, repeating_move),
+            Ok(GameEndReason::ThreefoldRepetition)
+        );
+        assert_eq!(game_board.history.len(), 8);
+        assert_eq!(
+            game_board.result.unwrap().reason,
+            Some(GameEndReason::ThreefoldRepetition)
+        );
+        let (move_start, move_end) = parse_move("g1f3").unwrap();
+        assert_eq!(
+            move_piece_no_map(&mut game_board, move_start, move_end, true),
+            Err(ChessError::GameOver)
+        );
+
+        // the fifty-move rule can also be claimed on the move
+        let mut game_board =
+            init_game_board("4k3/8/8/8/8/8/8/R3K3 w - - 99 80".to_string()).unwrap();
+        assert_eq!(claim_draw(&mut game_board), Err(ChessError::NoDrawToClaim));
+        let game_move = parse_san(&game_board.game, "Ra2").unwrap();
+        assert_eq!(
+            claim_draw_with_move(&mut game_board, game_move),
+            Ok(GameEndReason::FiftyMoveRule)
+        );
+    }
+
+    #[test]
+    fn three_check_test() {
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w - - 0 1 +2+0";
+        assert!(get_board(fen.to_string()).is_err());
+        let mut game_board = init_variant_game_board(fen.to_string(), Variant::ThreeCheck).unwrap();
+        assert_eq!(get_fen(&game_board.game).unwrap(), fen);
+        let hash = game_board.game.hash;
+
+        // the third check wins even if it is not mate
+        let (move_start, move_end) = parse_move("a1a8").unwrap();
+        move_piece_no_map(&mut game_board, move_start, move_end, true).unwrap();
+        assert_eq!(
+            get_fen(&game_board.game).unwrap(),
+            "R3k3/8/8/8/8/8/8/4K3 b - - 1 1 +3+0"
+        );
+        let threats = get_threats(&game_board);
+        let result = get_game_state(&game_board, &threats, false);
+        assert_eq!(result.outcome, WinStatus::WhiteWon);
+        assert_eq!(result.reason, Some(GameEndReason::ThirdCheck));
+        assert!(legal_moves(&game_board).is_empty());
+        assert_eq!(
+            game_board.game.hash,
+            crate::zobrist::get_hash(&game_board.game)
+        );
+
+        undo_move(&mut game_board).unwrap();
+        assert_eq!(game_board.game.checks, [2, 0]);
+        assert_eq!(game_board.game.hash, hash);
+
+        // the check from a promotion is counted when the pawn is promoted
+        let mut game_board = init_variant_game_board(
+            "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1".to_string(),
+            Variant::ThreeCheck,
+        )
+        .unwrap();
+        let (move_start, move_end) = parse_move("b7b8").unwrap();
+        move_piece_no_map(&mut game_board, move_start, move_end, false).unwrap();
+        assert_eq!(game_board.game.checks, [0, 0]);
+        promote_pawn(&mut game_board, Piece::Rook).unwrap();
+        assert_eq!(game_board.game.checks, [1, 0]);
+        assert_eq!(
+            game_board.game.hash,
+            crate::zobrist::get_hash(&game_board.game)
+        );
+        undo_move(&mut game_board).unwrap();
+        assert_eq!(game_board.game.checks, [0, 0]);
+
+        // a knight can not mate but it can check
+        let game = get_variant_board(
+            "4k3/8/8/8/8/8/8/1N2K3 w - - 0 1".to_string(),
+            Variant::ThreeCheck,
+        )
+        .unwrap();
+        assert!(!has_insufficient_material(&game, true));
+        assert!(has_insufficient_material(&game, false));
+
+        for invalid in ["+4+0", "+1", "2+1", "+3+3"] {
+            let fen = format!("4k3/8/8/8/8/8/8/4K3 w - - 0 1 {}", invalid);
+            assert!(matches!(
+                get_variant_board(fen, Variant::ThreeCheck),
+                Err(ChessError::InvalidFen { field: 6, .. })
+            ));
+        }
+
+        // the checks do not change the moves until the game is won
+        let game =
+            get_variant_board(get_start_fen(Variant::ThreeCheck), Variant::ThreeCheck).unwrap();
+        assert_eq!(perft(&game, 3), 8902);
+    }
+
+    #[test]
+    fn king_of_the_hill_test() {
+        let fen = "4k3/8/8/8/8/3K4/8/8 w - - 0 1";
+        let mut game_board =
+            init_variant_game_board(fen.to_string(), Variant::KingOfTheHill).unwrap();
+        // the kings can always walk to the center
+        let threats = get_threats(&game_board);
+        assert_eq!(get_game_state(&game_board, &threats, true), GAME_ONGOING);
+
+        let (move_start, move_end) = parse_move("d3d4").unwrap();
+        move_piece_no_map(&mut game_board, move_start, move_end, true).unwrap();
+        let threats = get_threats(&game_board);
+        let result = get_game_state(&game_board, &threats, false);
+        assert_eq!(result.outcome, WinStatus::WhiteWon);
+        assert_eq!(result.reason, Some(GameEndReason::KingInCenter));
+
+        let (move_start, move_end) = parse_move("e8e7").unwrap();
+        assert_eq!(
+            move_piece_no_map(&mut game_board, move_start, move_end, true),
+            Err(ChessError::GameOver)
+        );
+
+        assert_eq!(
+            parse_variant("King of the Hill"),
+            Ok(Variant::KingOfTheHill)
+        );
+        assert_eq!(parse_variant("koth"), Ok(Variant::KingOfTheHill));
+        assert_eq!(parse_variant("three check"), Ok(Variant::ThreeCheck));
+        assert!(parse_variant("bughouse").is_err());
+    }
+
+    fn load_variant(fen: &str, variant: Variant) -> Gameboard {
+        init_variant_game_board(fen.to_string(), variant).unwrap()
+    }
+
+    // moves are given like e2e4 and drops like P@e4
+    fn play_input(game_board: &mut Gameboard, input: &str) -> Result<(), ChessError> {
+        if input.contains('@') {
+            let game_move = parse_long_move(&game_board.game, input)?;
+            let threats = get_threats(game_board);
+            return drop_piece(game_board, game_move.piece.piece, game_move.to, &threats);
+        }
+        let (move_start, move_end) = parse_move(input)?;
+        move_piece_no_map(game_board, move_start, move_end, true)
+    }
+
+    fn game_state(game_board: &Gameboard) -> GameResult {
+        get_game_state(game_board, &get_threats(game_board), false)
+    }
+
+    fn variant_perft(fen: &str, variant: Variant, depth: u32) -> u64 {
+        perft(&get_variant_board(fen.to_string(), variant).unwrap(), depth)
+    }
+
+    #[test]
+    fn atomic_test() {
+        // the knight, the bishop, the knight and the rook explodes but the pawns are left
+        let fen = "4k3/8/2n1r3/3b4/2p1p3/4N3/8/4K3 w - - 0 1";
+        let mut game_board = load_variant(fen, Variant::Atomic);
+        assert_eq!(play_input(&mut game_board, "e3d5"), Ok(()));
+        assert_eq!(
+            get_fen(&game_board.game).unwrap(),
+            "4k3/8/8/8/2p1p3/8/8/4K3 b - - 0 1"
+        );
+        undo_move(&mut game_board).unwrap();
+        assert_eq!(get_fen(&game_board.game).unwrap(), fen);
+        assert_eq!(
+            game_board.game.hash,
+            crate::zobrist::get_hash(&game_board.game)
+        );
+
+        // kings can not capture
+        let mut game_board = load_variant("4k3/8/8/8/8/8/4p3/4K3 w - - 0 1", Variant::Atomic);
+        assert_eq!(
+            play_input(&mut game_board, "e1e2"),
+            Err(ChessError::IllegalMove(IllegalMoveReason::InvalidMovement))
+        );
+
+        // the king would explode with the pawn that checks it
+        let mut game_board = load_variant("4k3/8/8/8/8/8/3p4/3QK3 w - - 0 1", Variant::Atomic);
+        assert_eq!(
+            play_input(&mut game_board, "d1d2"),
+            Err(ChessError::IllegalMove(IllegalMoveReason::ExplodesOwnKing))
+        );
+
+        // kings next to each other can not be checked
+        let mut game_board = load_variant("4r3/8/8/8/8/3k4/4K2P/8 w - - 0 1", Variant::Atomic);
+        assert_eq!(
+            play_input(&mut game_board, "e2e1"),
+            Err(ChessError::IllegalMove(IllegalMoveReason::MovesIntoCheck))
+        );
+        assert!(!is_in_check(&game_board.game, &get_threats(&game_board)));
+
+        // exploding the other king wins
+        let mut game_board = load_variant("3qk3/8/8/8/8/8/8/3RK3 w - - 0 1", Variant::Atomic);
+        assert_eq!(play_input(&mut game_board, "d1d8"), Ok(()));
+        let result = game_state(&game_board);
+        assert_eq!(result.outcome, WinStatus::WhiteWon);
+        assert_eq!(result.reason, Some(GameEndReason::KingCaptured));
+        assert!(legal_moves(&game_board).is_empty());
+
+        // a single bishop can not explode a bare king, but a queen can
+        let game = load_variant("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1", Variant::Atomic).game;
+        assert!(has_insufficient_material(&game, true));
+        let game = load_variant("4k3/8/8/8/8/8/8/2Q1K3 w - - 0 1", Variant::Atomic).game;
+        assert!(!has_insufficient_material(&game, true));
+    }
+
+    #[test]
+    fn atomic_perft_test() {
+        assert_eq!(variant_perft(STANDARD_BOARD, Variant::Atomic, 4), 197326);
+        assert_eq!(
+            variant_perft(
+                "rn2kb1r/1pp1p2p/p2q1pp1/3P4/2P3b1/4PN2/PP3PPP/R2QKB1R b KQkq - 0 1",
+                Variant::Atomic,
+                3
+            ),
+            45237
+        );
+        assert_eq!(
+            variant_perft(
+                "rn1qkb1r/p5pp/2p5/3p4/N3P3/5P2/PPP4P/R1BQK3 w Qkq - 0 1",
+                Variant::Atomic,
+                4
+            ),
+            714499
+        );
+        // the king can castle next to the other king even if the square is attacked
+        assert_eq!(
+            variant_perft("8/8/8/8/8/8/2k5/rR4KR w KQ - 0 1", Variant::Atomic, 5),
+            1603055
+        );
+    }
+
+    #[test]
+    fn crazyhouse_test() {
+        // captured pieces go to the pocket of the player that captured them
+        let mut game_board = load_variant(&get_start_fen(Variant::Crazyhouse), Variant::Crazyhouse);
+        for input in ["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5g2", "P@g3"] {
+            assert_eq!(play_input(&mut game_board, input), Ok(()), "{}", input);
+        }
+        assert_eq!(
+            get_fen(&game_board.game).unwrap(),
+            "rnb1kbnr/ppp1pppp/8/8/8/2N3P1/PPPP1PqP/R1BQKBNR[pp] b KQkq - 1 4"
+        );
+        undo_move(&mut game_board).unwrap();
+        assert_eq!(game_board.game.pockets[0][0], 1);
+        redo_move(&mut game_board).unwrap();
+        assert_eq!(
+            game_board.game.hash,
+            crate::zobrist::get_hash(&game_board.game)
+        );
+
+        // a promoted piece goes back to the pocket as a pawn
+        let mut game_board =
+            load_variant("4k3/8/8/8/8/8/1q6/Q~3K3[] b - - 0 1", Variant::Crazyhouse);
+        assert_eq!(play_input(&mut game_board, "b2a1"), Ok(()));
+        assert_eq!(
+            get_fen(&game_board.game).unwrap(),
+            "4k3/8/8/8/8/8/8/q3K3[p] w - - 0 2"
+        );
+
+        // pieces can only be dropped on empty squares and pawns not on the first or last rank
+        let mut game_board = load_variant("4k3/8/8/8/8/8/8/4K3[P] w - - 0 1", Variant::Crazyhouse);
+        let threats = get_threats(&game_board);
+        let mut drop = |piece: Piece, square: &str| {
+            let position = parse_move(&format!("{}{}", square, square)).unwrap().0;
+            drop_piece(&mut game_board, piece, position, &threats)
+        };
+        let refused = |reason: IllegalMoveReason| Err(ChessError::IllegalMove(reason));
+        assert_eq!(
+            drop(Piece::Pawn, "a1"),
+            refused(IllegalMoveReason::InvalidDrop)
+        );
+        assert_eq!(
+            drop(Piece::Pawn, "e1"),
+            refused(IllegalMoveReason::InvalidDrop)
+        );
+        assert_eq!(
+            drop(Piece::Knight, "e4"),
+            refused(IllegalMoveReason::NotInPocket)
+        );
+        assert_eq!(drop(Piece::Pawn, "e4"), Ok(()));
+
+        // a drop can block a check, but nothing else is legal
+        let game_board = load_variant("4k3/8/8/8/8/8/8/r3K3[N] w - - 0 1", Variant::Crazyhouse);
+        let threats = get_threats(&game_board);
+        let mut drops: Vec<String> = get_valid_drops(&game_board, &threats, Piece::Knight)
+            .into_iter()
+            .map(get_position)
+            .collect();
+        drops.sort();
+        assert_eq!(drops, vec!["b1", "c1", "d1"]);
+        let mut game_board = game_board;
+        let position = parse_move("h5h5").unwrap().0;
+        assert_eq!(
+            drop_piece(&mut game_board, Piece::Knight, position, &threats),
+            Err(ChessError::IllegalMove(IllegalMoveReason::KingInCheck))
+        );
+
+        // the back rank mate is stopped by a knight from the pocket
+        let state_after_drop = |fen: &str| {
+            let mut game_board = load_variant(fen, Variant::Crazyhouse);
+            play_input(&mut game_board, "R@e8").unwrap();
+            game_state(&game_board)
+        };
+        let result = state_after_drop("6k1/5ppp/8/8/8/8/8/6K1[R] w - - 0 1");
+        assert_eq!(result.outcome, WinStatus::WhiteWon);
+        assert_eq!(result.reason, Some(GameEndReason::Checkmate));
+        assert_eq!(
+            state_after_drop("6k1/5ppp/8/8/8/8/8/6K1[Rn] w - - 0 1"),
+            GAME_ONGOING
+        );
+
+        // the pieces in the pockets can still checkmate
+        let insufficient = |fen: &str| {
+            has_insufficient_material(&load_variant(fen, Variant::Crazyhouse).game, true)
+        };
+        assert!(insufficient("4k3/8/8/8/8/8/8/4K3[] w - - 0 1"));
+        assert!(insufficient("4k3/8/8/8/8/8/8/4K3[n] w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/4K3[p] w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/3NK3[n] w - - 0 1"));
+    }
+
+    #[test]
+    fn crazyhouse_perft_test() {
+        assert_eq!(
+            variant_perft(&get_start_fen(Variant::Crazyhouse), Variant::Crazyhouse, 5),
+            4888832
+        );
+        // every piece can be dropped
+        assert_eq!(
+            variant_perft(
+                "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1",
+                Variant::Crazyhouse,
+                2
+            ),
+            75353
+        );
+        assert_eq!(
+            variant_perft(
+                "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - 0 1",
+                Variant::Crazyhouse,
+                4
+            ),
+            2083382
+        );
+    }
+
+    #[test]
+    fn antichess_test() {
+        // captures are compulsory, even for the king
+        let mut game_board = load_variant("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", Variant::Antichess);
+        assert_eq!(legal_moves(&game_board).len(), 1);
+        assert_eq!(
+            play_input(&mut game_board, "e1e2"),
+            Err(ChessError::IllegalMove(IllegalMoveReason::CaptureRequired))
+        );
+        assert_eq!(play_input(&mut game_board, "e4d5"), Ok(()));
+
+        // the king can move into an attack and be captured, there is no check
+        let mut game_board = load_variant("4k3/8/8/8/7P/8/r7/4K3 w - - 0 1", Variant::Antichess);
+        assert!(!is_in_check(&game_board.game, &get_threats(&game_board)));
+        assert_eq!(play_input(&mut game_board, "e1e2"), Ok(()));
+        assert_eq!(play_input(&mut game_board, "a2e2"), Ok(()));
+        assert_eq!(game_state(&game_board), GAME_ONGOING);
+
+        // the player that loses all pieces wins
+        let mut game_board = load_variant("8/8/8/8/8/8/4k3/4R3 w - - 0 1", Variant::Antichess);
+        assert_eq!(play_input(&mut game_board, "e1e2"), Ok(()));
+        let result = game_state(&game_board);
+        assert_eq!(result.outcome, WinStatus::BlackWon);
+        assert_eq!(result.reason, Some(GameEndReason::AllPiecesLost));
+
+        // and so does the player that is stalemated
+        let result = game_state(&load_variant(
+            "8/8/8/8/8/p7/P7/8 w - - 0 1",
+            Variant::Antichess,
+        ));
+        assert_eq!(result.outcome, WinStatus::WhiteWon);
+        assert_eq!(result.reason, Some(GameEndReason::Stalemate));
+
+        // a pawn can promote to a king
+        let mut game_board = load_variant("8/P7/8/8/8/8/8/7k w - - 0 1", Variant::Antichess);
+        assert_eq!(legal_moves(&game_board).len(), 5);
+        let game_move = parse_long_move(&game_board.game, "a7a8k").unwrap();
+        assert_eq!(get_san(&game_board.game, &game_move), "a8=K");
+        let threats = get_threats(&game_board);
+        let (move_start, move_end) = parse_move("a7a8").unwrap();
+        move_piece(&mut game_board, move_start, move_end, &threats, false).unwrap();
+        assert_eq!(promote_pawn(&mut game_board, Piece::King), Ok(()));
+        assert_eq!(
+            get_fen(&game_board.game).unwrap(),
+            "K7/8/8/8/8/8/8/7k b - - 0 1"
+        );
+
+        // there is no castling
+        assert_eq!(
+            get_variant_board(STANDARD_BOARD.to_string(), Variant::Antichess).map(|_| ()),
+            Err(ChessError::InvalidFen {
+                field: 2,
+                reason: "there is no castling in Antichess".to_string()
+            })
+        );
+
+        // bishops on other square colors can never capture each other
+        let result = game_state(&load_variant(
+            "8/8/8/8/8/8/8/2b2B2 w - - 0 1",
+            Variant::Antichess,
+        ));
+        assert_eq!(result.outcome, WinStatus::Tie);
+        assert_eq!(result.reason, Some(GameEndReason::InsufficientMaterial));
+        assert_eq!(
+            game_state(&load_variant(
+                "8/8/8/8/8/8/8/1b3B2 w - - 0 1",
+                Variant::Antichess
+            )),
+            GAME_ONGOING
+        );
+    }
+
+    #[test]
+    fn antichess_perft_test() {
+        assert_eq!(
+            variant_perft(&get_start_fen(Variant::Antichess), Variant::Antichess, 5),
+            2732672
+        );
+        // the pawns have to capture each other
+        let depths: Vec<u64> = (1..=6)
+            .map(|depth| variant_perft("8/1p6/8/8/8/8/P7/8 w - - 0 1", Variant::Antichess, depth))
+            .collect();
+        assert_eq!(depths, vec![2, 4, 4, 3, 1, 0]);
+        // the king is also a promotion choice
+        assert_eq!(
+            variant_perft("8/P7/8/8/8/8/8/7k w - - 0 1", Variant::Antichess, 1),
+            5
+        );
+    }
+
+    #[test]
+    fn horde_test() {
+        // the pawns on the first rank are not waiting to be promoted
+        let game_board = load_variant(HORDE_BOARD, Variant::Horde);
+        assert_eq!(get_fen(&game_board.game).unwrap(), HORDE_BOARD);
+        assert_eq!(legal_moves(&game_board).len(), 8);
+
+        // a pawn can move two squares from the first rank, but it can not be taken en passant
+        let mut game_board = load_variant("4k3/8/8/8/8/1p6/8/P7 w - - 0 1", Variant::Horde);
+        assert_eq!(legal_moves(&game_board).len(), 2);
+        assert_eq!(play_input(&mut game_board, "a1a3"), Ok(()));
+        assert_eq!(
+            get_fen(&game_board.game).unwrap(),
+            "4k3/8/8/8/8/Pp6/8/8 b - - 0 1"
+        );
+
+        // black wins by capturing every white piece
+        let mut game_board = load_variant("r3k3/8/8/8/8/8/8/P7 b - - 0 1", Variant::Horde);
+        assert_eq!(play_input(&mut game_board, "a8a1"), Ok(()));
+        let result = game_state(&game_board);
+        assert_eq!(result.outcome, WinStatus::BlackWon);
+        assert_eq!(result.reason, Some(GameEndReason::AllPiecesLost));
+
+        // white has no king, so white can only be stalemated
+        let result = game_state(&load_variant(
+            "4k3/8/8/8/8/p7/P7/8 w - - 0 1",
+            Variant::Horde,
+        ));
+        assert_eq!(result.outcome, WinStatus::Tie);
+        assert_eq!(result.reason, Some(GameEndReason::Stalemate));
+
+        assert_eq!(
+            get_variant_board(STANDARD_BOARD.to_string(), Variant::Horde).map(|_| ()),
+            Err(ChessError::InvalidFen {
+                field: 0,
+                reason: "white has 1 kings".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn horde_perft_test() {
+        assert_eq!(variant_perft(HORDE_BOARD, Variant::Horde, 5), 265223);
+        assert_eq!(
+            variant_perft(
+                "4k3/pp4q1/3P2p1/8/P3PP2/PPP2r2/PPP5/PPPP4 b - - 0 1",
+                Variant::Horde,
+                4
+            ),
+            56539
+        );
+        assert_eq!(
+            variant_perft(
+                "k7/5p2/4p2P/3p2P1/2p2P2/1p2P2P/p2P2P1/2P2P2 w - - 0 1",
+                Variant::Horde,
+                4
+            ),
+            33781
+        );
+    }
+
+    #[test]
+    fn racing_kings_test() {
+        // no move can give check
+        let mut game_board = load_variant("8/8/8/8/8/8/k7/6RK w - - 0 1", Variant::RacingKings);
+        assert_eq!(
+            play_input(&mut game_board, "g1g2"),
+            Err(ChessError::IllegalMove(IllegalMoveReason::GivesCheck))
+        );
+        assert_eq!(play_input(&mut game_board, "g1g3"), Ok(()));
+
+        // the first king on the eighth rank wins
+        let mut game_board = load_variant("8/k7/8/8/8/8/8/6RK b - - 0 1", Variant::RacingKings);
+        assert_eq!(play_input(&mut game_board, "a7a8"), Ok(()));
+        let result = game_state(&game_board);
+        assert_eq!(result.outcome, WinStatus::BlackWon);
+        assert_eq!(result.reason, Some(GameEndReason::KingInGoal));
+
+        // black can draw by also reaching the eighth rank on the next move
+        let mut game_board = load_variant("8/1K4k1/8/8/8/8/8/8 w - - 0 1", Variant::RacingKings);
+        assert_eq!(play_input(&mut game_board, "b7b8"), Ok(()));
+        assert_eq!(game_state(&game_board), GAME_ONGOING);
+        let mut follow = game_board.clone();
+        assert_eq!(play_input(&mut follow, "g7g8"), Ok(()));
+        let result = game_state(&follow);
+        assert_eq!(result.outcome, WinStatus::Tie);
+        assert_eq!(result.reason, Some(GameEndReason::KingInGoal));
+        assert_eq!(play_input(&mut game_board, "g7g6"), Ok(()));
+        assert_eq!(game_state(&game_board).outcome, WinStatus::WhiteWon);
+
+        // but not if the black king is too far away
+        let mut game_board = load_variant("8/1K6/8/8/8/8/6k1/8 w - - 0 1", Variant::RacingKings);
+        assert_eq!(play_input(&mut game_board, "b7b8"), Ok(()));
+        assert_eq!(game_state(&game_board).outcome, WinStatus::WhiteWon);
+        assert!(legal_moves(&game_board).is_empty());
+
+        assert_eq!(
+            get_variant_board(STANDARD_BOARD.to_string(), Variant::RacingKings).map(|_| ()),
+            Err(ChessError::InvalidFen {
+                field: 0,
+                reason: "there are no pawns in Racing Kings".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn racing_kings_perft_test() {
+        assert_eq!(
+            variant_perft(RACING_KINGS_BOARD, Variant::RacingKings, 4),
+            296242
+        );
+        // the black king can still reach the eighth rank after the white king
+        assert_eq!(
+            variant_perft("4brn1/2K2k2/8/8/8/8/8/8 w - - 0 1", Variant::RacingKings, 5),
+            12981
+        );
+    }
+
+    #[test]
+    fn castle_rights_test() {
+        let str = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        // capturing a rook removes the castle rights of the captured rook
+        assert_eq!(
+            "R3k2r/8/8/8/8/8/8/4K2R b Kk - 0 1",
+            load_board(str, vec!["a1a8"]).unwrap().0
+        );
+        // moving a rook removes its own castle rights
+        assert_eq!(
+            "r3k3/7r/8/8/8/8/7R/R3K3 w Qq - 2 2",
+            load_board(str, vec!["h1h2", "h8h7"]).unwrap().0
+        );
+    }
+
+    #[test]
+    fn chess960_castle_test() {
+        // the king takes its own rook and they end on g1 and f1
+        let str = "1r2k1r1/1p4p1/8/8/8/8/1P4P1/1R2K1R1 w GBgb - 0 1";
+        assert_eq!(
+            "1r2k1r1/1p4p1/8/8/8/8/1P4P1/1R3RK1 b gb - 1 1",
+            load_board(str, vec!["e1g1"]).unwrap().0
+        );
+        assert_eq!(
+            "2kr2r1/1p4p1/8/8/8/8/1P4P1/1R3RK1 w - - 2 2",
+            load_board(str, vec!["e1g1", "e8b8"]).unwrap().0
+        );
+
+        // the king does not move, only the rook
+        let str = "4k3/8/8/8/8/8/8/6KR w H - 0 1";
+        assert_eq!(
+            "4k3/8/8/8/8/8/8/5RK1 b - - 1 1",
+            load_board(str, vec!["g1h1"]).unwrap().0
+        );
+
+        // the king and the rook swap squares
+        let str = "4k3/8/8/8/8/8/8/5KR1 w G - 0 1";
+        assert_eq!(
+            "4k3/8/8/8/8/8/8/5RK1 b - - 1 1",
+            load_board(str, vec!["f1g1"]).unwrap().0
+        );
+        // the king can not end in check when the rook moves away
+        let str = "4k3/8/8/8/8/8/8/1RK5 w B - 0 1";
+        assert!(load_board(str, vec!["c1b1"]).unwrap().1);
+        let str = "4k3/8/8/8/8/8/8/qRK5 w B - 0 1";
+        assert!(!load_board(str, vec!["c1b1"]).unwrap().1);
+
+        // standard chess also takes the king moving to the rook
+        assert_eq!(
+            "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1",
+            load_board("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", vec!["e1h1"])
+                .unwrap()
+                .0
+        );
+
+        let mut game_board = init_game_board(get_chess960_fen(0).unwrap()).unwrap();
+        for input in ["f2f4", "f7f5", "f1f3", "f8f6"] {
+            let (from, to) = parse_move(input).unwrap();
+            move_piece_no_map(&mut game_board, from, to, true).unwrap();
+        }
+        let castle = generate_legal_moves(&game_board.game)
+            .into_iter()
+            .find(|m| m.piece.piece == Piece::King && m.to.x == 7)
+            .unwrap();
+        assert_eq!(get_san(&game_board.game, &castle), "O-O");
+        assert_eq!(get_long_move(&castle), "g1h1");
+        assert_eq!(parse_san(&game_board.game, "O-O").unwrap(), castle);
+        undo_move(&mut game_board).unwrap();
+        redo_move(&mut game_board).unwrap();
+    }
+
     #[test]
     fn en_passant_test() {
         let str = "rn2kb1r/pp1q1ppp/3p3n/2pPp3/4P1b1/2N2N2/PPP1KPPP/R1BQ1B1R w kq c6 0 7";
@@ -76,41 +1052,179 @@ mod tests {
         );
     }
 
-    fn total_moves(game: Game, depth: u32) -> u32 {
-        return move_all(game, depth).0;
+    fn play_moves(game_board: &mut Gameboard, moves: Vec<&str>) {
+        for input in moves {
+            let (move_start, move_end) = parse_move(input).unwrap();
+            move_piece_no_map(game_board, move_start, move_end, true).unwrap();
+        }
     }
 
-    /* does all possible moves, returns as moves + time*/
-    fn move_all(game: Game, depth: u32) -> (u32, u128) {
-        if depth == 0 {
-            return (1, 0);
-        }
+    #[test]
+    fn undo_redo_test() {
+        // covers captures, en passant, castling and promotion
+        let str = "r3k2r/1P6/8/8/3p4/8/4P3/R3K2R w KQkq - 0 1";
+        let moves = vec!["e2e4", "d4e3", "e1c1", "e8g8", "b7a8", "f8a8"];
+        let mut game_board = init_game_board(str.to_string()).unwrap();
+        play_moves(&mut game_board, moves.clone());
+
+        let end_fen = get_fen(&game_board.game).unwrap();
+        let end_same_board = game_board.same_board.clone();
+        assert_eq!(game_board.history.len(), moves.len());
+        assert_eq!(game_board.history[4].promotion, Piece::Queen);
 
-        let mut num_moves = 0u32;
-        let mut time = 0;
-        for x in 0..BOARD_SIZE {
-            for y in 0..BOARD_SIZE {
-                let pos = Position { x: x, y: y };
-
-                let piece_data = game.board[x][y];
-                if piece_data.piece != Piece::None && piece_data.is_white == game.is_white_to_move {
-                    let now = std::time::Instant::now();
-                    let threatmap = generate_all_threats(&game, !game.is_white_to_move);
-
-                    let valid_moves = generate_valid_moves(&game, &threatmap, &pos);
-                    time += now.elapsed().as_nanos();
-                    for move_end in valid_moves {
-                        let mut clone_board = game.clone();
-                        if move_piece_unsafe(&mut clone_board, pos, move_end) {
-                            let (r_moves, r_time) = move_all(clone_board, depth - 1);
-                            num_moves += r_moves;
-                            time += r_time;
-                        }
-                    }
-                }
+        while undo_move(&mut game_board).is_ok() {}
+        assert_eq!(str, get_fen(&game_board.game).unwrap());
+        assert_eq!(game_board.same_board.len(), 1);
+        assert_eq!(game_board.redo_history.len(), moves.len());
+
+        while redo_move(&mut game_board).is_ok() {}
+        assert_eq!(end_fen, get_fen(&game_board.game).unwrap());
+        assert_eq!(end_same_board, game_board.same_board);
+    }
+
+    #[test]
+    fn undo_clears_redo_test() {
+        let mut game_board = init_game_board(STANDARD_BOARD.to_string()).unwrap();
+        assert_eq!(undo_move(&mut game_board), Err(ChessError::NothingToUndo));
+
+        play_moves(&mut game_board, vec!["e2e4", "e7e5"]);
+        undo_move(&mut game_board).unwrap();
+        assert_eq!(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            get_fen(&game_board.game).unwrap()
+        );
+
+        // a new move makes the taken back move invalid
+        play_moves(&mut game_board, vec!["c7c5"]);
+        assert!(game_board.redo_history.is_empty());
+        assert_eq!(redo_move(&mut game_board), Err(ChessError::NothingToRedo));
+    }
+
+    #[test]
+    fn undo_pending_promotion_test() {
+        let str = "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1";
+        let mut game_board = init_game_board(str.to_string()).unwrap();
+        let (move_start, move_end) = parse_move("b7b8").unwrap();
+        let threats = get_threats(&game_board);
+        move_piece(&mut game_board, move_start, move_end, &threats, false).unwrap();
+        promote_pawn(&mut game_board, Piece::Knight).unwrap();
+
+        undo_move(&mut game_board).unwrap();
+        assert_eq!(str, get_fen(&game_board.game).unwrap());
+        redo_move(&mut game_board).unwrap();
+        assert_eq!(
+            "1N2k3/8/8/8/8/8/8/4K3 b - - 0 1",
+            get_fen(&game_board.game).unwrap()
+        );
+    }
+
+    #[test]
+    fn illegal_move_reason_test() {
+        let refuse_reason = |board: &str, input: &str| {
+            let mut game_board = init_game_board(board.to_string()).unwrap();
+            let (move_start, move_end) = parse_move(input).unwrap();
+            let threats = get_threats(&game_board);
+            match move_piece(&mut game_board, move_start, move_end, &threats, false) {
+                Err(ChessError::IllegalMove(reason)) => reason,
+                result => panic!("{} was not refused: {:?}", input, result),
             }
+        };
+
+        assert_eq!(
+            refuse_reason(STANDARD_BOARD, "e7e5"),
+            IllegalMoveReason::NotYourTurn
+        );
+        assert_eq!(
+            refuse_reason(STANDARD_BOARD, "e3e4"),
+            IllegalMoveReason::NoPiece
+        );
+        assert_eq!(
+            refuse_reason(STANDARD_BOARD, "e1e2"),
+            IllegalMoveReason::CaptureOwnPiece
+        );
+        assert_eq!(
+            refuse_reason(STANDARD_BOARD, "e2e5"),
+            IllegalMoveReason::InvalidMovement
+        );
+
+        // the bishop on e2 is pinned by the rook on e8
+        let pinned = "4r1k1/8/8/8/8/8/4B3/4K3 w - - 0 1";
+        assert_eq!(
+            refuse_reason(pinned, "e2d3"),
+            IllegalMoveReason::PiecePinned
+        );
+        assert_eq!(
+            refuse_reason(pinned, "e1e2"),
+            IllegalMoveReason::CaptureOwnPiece
+        );
+
+        let checked = "4r1k1/8/8/8/8/8/3B4/4K3 w - - 0 1";
+        assert_eq!(
+            refuse_reason(checked, "d2c3"),
+            IllegalMoveReason::KingInCheck
+        );
+        assert_eq!(
+            refuse_reason(checked, "e1e2"),
+            IllegalMoveReason::MovesIntoCheck
+        );
+
+        let mut game_board =
+            init_game_board("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1".to_string()).unwrap();
+        let (move_start, move_end) = parse_move("b7b8").unwrap();
+        let threats = get_threats(&game_board);
+        move_piece(&mut game_board, move_start, move_end, &threats, false).unwrap();
+        let (move_start, move_end) = parse_move("e1e2").unwrap();
+        assert_eq!(
+            move_piece(&mut game_board, move_start, move_end, &threats, false),
+            Err(ChessError::IllegalMove(IllegalMoveReason::PromotionPending))
+        );
+        assert_eq!(
+            promote_pawn(&mut game_board, Piece::King),
+            Err(ChessError::IllegalMove(IllegalMoveReason::InvalidPromotion))
+        );
+    }
+
+    #[test]
+    fn legal_moves_test() {
+        let game_board = init_game_board(STANDARD_BOARD.to_string()).unwrap();
+        assert_eq!(legal_moves(&game_board).len(), 20);
+
+        // 2 castles, 1 en passant and 4 promotions
+        let game_board =
+            init_game_board("4k3/1P6/8/3pP3/8/8/8/R3K2R w KQ d6 0 1".to_string()).unwrap();
+        let moves = legal_moves(&game_board);
+        let has_move = |from: &str, to: &str, promotion: Piece| {
+            let (from, to) = parse_move(&format!("{}{}", from, to)).unwrap();
+            moves
+                .iter()
+                .any(|m| m.from == from && m.to == to && m.promotion == promotion)
+        };
+        assert!(has_move("e1", "g1", Piece::None));
+        assert!(has_move("e1", "c1", Piece::None));
+        assert!(has_move("e5", "d6", Piece::None));
+        for promotion in PROMOTION_PIECES {
+            assert!(has_move("b7", "b8", promotion));
         }
-        return (num_moves, time);
+        assert!(!has_move("b7", "b8", Piece::None));
+        assert_eq!(moves.len(), 4 + 1 + 1 + 5 + 10 + 9 + 2);
+    }
+
+    fn total_moves(game: Game, depth: u32) -> u64 {
+        perft(&game, depth)
+    }
+
+    #[test]
+    fn perft_divide_test() {
+        let game = get_board(STANDARD_BOARD.to_string()).unwrap();
+        let divide = perft_divide(&game, 3);
+        assert_eq!(divide.len(), 20);
+        assert_eq!(divide.iter().map(|(_, nodes)| nodes).sum::<u64>(), 8902);
+
+        let (_, e2e4_nodes) = divide
+            .iter()
+            .find(|(game_move, _)| get_long_move(game_move) == "e2e4")
+            .unwrap();
+        assert_eq!(*e2e4_nodes, 600);
     }
 
     //https://www.chessprogramming.org/Perft_Results
@@ -123,19 +1237,21 @@ mod tests {
         assert_eq!(total_moves(game, 1), 48);
         assert_eq!(total_moves(game, 2), 2039);
         assert_eq!(total_moves(game, 3), 97862);
-        // assert_eq!(total_moves(game, 4), 4085603); // 4074224 ;-;
+        assert_eq!(total_moves(game, 4), 4085603);
+        assert_eq!(total_moves(game, 5), 193690690);
     }
 
-    /*#[test]
+    #[test]
     fn perft_test_3() {
         let game = get_board("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 0".to_string()).unwrap();
         assert_eq!(total_moves(game, 1), 14);
         assert_eq!(total_moves(game, 2), 191);
         assert_eq!(total_moves(game, 3), 2812);
         assert_eq!(total_moves(game, 4), 43238);
-    }*/
+        assert_eq!(total_moves(game, 5), 674624);
+    }
 
-    /*#[test]
+    #[test]
     fn perft_test_4() {
         let game = get_board(
             "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1".to_string(),
@@ -144,10 +1260,11 @@ mod tests {
         assert_eq!(total_moves(game, 1), 6);
         assert_eq!(total_moves(game, 2), 264);
         assert_eq!(total_moves(game, 3), 9467);
-        // assert_eq!(total_moves(game, 4), 422333);
-    }*/
+        assert_eq!(total_moves(game, 4), 422333);
+        assert_eq!(total_moves(game, 5), 15833292);
+    }
 
-    /*#[test]
+    #[test]
     fn perft_test_5() {
         let game =
             get_board("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8".to_string())
@@ -155,8 +1272,9 @@ mod tests {
         assert_eq!(total_moves(game, 1), 44);
         assert_eq!(total_moves(game, 2), 1486);
         assert_eq!(total_moves(game, 3), 62379);
-        //  assert_eq!(total_moves(game, 4),  2103487 );
-    }*/
+        assert_eq!(total_moves(game, 4), 2103487);
+        assert_eq!(total_moves(game, 5), 89941194);
+    }
 
     #[test]
     fn perft_test_6() {
@@ -168,20 +1286,43 @@ mod tests {
         assert_eq!(total_moves(game, 1), 46);
         assert_eq!(total_moves(game, 2), 2079);
         assert_eq!(total_moves(game, 3), 89890);
-        // assert_eq!(total_moves(game, 4),  3894594 );
+        assert_eq!(total_moves(game, 4), 3894594);
+        assert_eq!(total_moves(game, 5), 164075551);
+    }
+
+    #[test]
+    fn chess960_perft_test() {
+        let game = get_board(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9".to_string(),
+        )
+        .unwrap();
+        assert_eq!(total_moves(game, 1), 21);
+        assert_eq!(total_moves(game, 2), 528);
+        assert_eq!(total_moves(game, 3), 12189);
+        assert_eq!(total_moves(game, 4), 326672);
+        assert_eq!(total_moves(game, 5), 8146062);
+
+        let game =
+            get_board("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9".to_string())
+                .unwrap();
+        assert_eq!(total_moves(game, 4), 667366);
+
+        let game = get_board(
+            "1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9".to_string(),
+        )
+        .unwrap();
+        assert_eq!(total_moves(game, 4), 1171749);
     }
 
     #[test]
     fn run_deep_test_1() {
         let game = get_board(STANDARD_BOARD.to_string()).unwrap();
-        //println!("TIME {}ms", move_all(game, 3).1 / 1_000_000);
         assert_eq!(total_moves(game, 1), 20);
         assert_eq!(total_moves(game, 2), 400);
         assert_eq!(total_moves(game, 3), 8902);
 
-        //assert_eq!(total_moves(game, 4), 197281);
-        //627.90s and it works!!
-        //assert_eq!(total_moves(game, 5, 0), 4865609);
+        assert_eq!(total_moves(game, 4), 197281);
+        assert_eq!(total_moves(game, 5), 4865609);
     }
 
     #[test]
@@ -194,7 +1335,7 @@ mod tests {
         assert_eq!(total_moves(game, 1), 27);
         assert_eq!(total_moves(game, 2), 671);
         assert_eq!(total_moves(game, 3), 19218);
-        //assert_eq!(total_moves(game, 4), 492460);
+        assert_eq!(total_moves(game, 4), 492460);
     }
 
     #[test]
@@ -207,7 +1348,7 @@ mod tests {
         assert_eq!(total_moves(game, 1), 32);
         assert_eq!(total_moves(game, 2), 1173);
         assert_eq!(total_moves(game, 3), 36787);
-        //assert_eq!(total_moves(game, 4), 1373011);
+        assert_eq!(total_moves(game, 4), 1373011);
     }
 
     #[test]
@@ -220,7 +1361,7 @@ mod tests {
         assert_eq!(total_moves(game, 1), 31);
         assert_eq!(total_moves(game, 2), 767);
         assert_eq!(total_moves(game, 3), 24016);
-        //assert_eq!(total_moves(game, 4), 679848);
+        assert_eq!(total_moves(game, 4), 679848);
     }
 
     #[test]
@@ -233,6 +1374,6 @@ mod tests {
         assert_eq!(total_moves(game, 1), 26);
         assert_eq!(total_moves(game, 2), 1194);
         assert_eq!(total_moves(game, 3), 33003);
-        //assert_eq!(total_moves(game, 4), 1419244);
+        assert_eq!(total_moves(game, 4), 1419244);
     }
 }
@@ -1,6 +1,10 @@
 use std::collections::HashSet;
 
-use crate::{game_data::*, parser::get_board_fen};
+use crate::{
+    bitboard::*,
+    game_data::*,
+    zobrist::{get_en_passant_key, get_state_key},
+};
 
 fn get_position(pos: &Position, offset: &Vector2) -> Option<Position> {
     let new_position = Vector2 {
@@ -22,280 +26,76 @@ fn get_position(pos: &Position, offset: &Vector2) -> Option<Position> {
     })
 }
 
-fn is_square_none(game: &Game, piece_position: &Position) -> bool {
-    return game.board[piece_position.x][piece_position.y].piece == Piece::None;
-}
-
 pub(crate) fn is_square_color(game: &Game, piece_position: &Position, is_white: bool) -> bool {
     let piece_data = game.board[piece_position.x][piece_position.y];
     return piece_data.piece != Piece::None && piece_data.is_white == is_white;
 }
 
-/**
-Excludes casteling to not make an while true loop
-*/
-pub(crate) fn generate_all_moves(game: &Game, piece_position: &Position) -> ThreatMap {
-    let mut all_moves: HashSet<Position> = HashSet::new();
-    let mut all_threats: HashSet<Position> = HashSet::new();
-    let mut all_pinned: Vec<HashSet<Position>> = Vec::new();
-    let mut all_king_threats: HashSet<Position> = HashSet::new();
-    let mut all_king_threats_full: HashSet<Position> = HashSet::new();
-
-    let start_piece = game.board[piece_position.x][piece_position.y];
-    if start_piece.piece == Piece::None {
-        return ThreatMap {
-            all_moves,
-            all_threats,
-            all_pinned,
-            all_king_threats,
-            all_king_threats_full,
-        };
-    } else {
-        // special case for pawns because they have so many rules
-        if start_piece.piece == Piece::Pawn {
-            let start_position = if start_piece.is_white {
-                WHITE_PAWN_Y
-            } else {
-                BLACK_PAWN_Y
-            };
-
-            let move_direction: i8 = if start_piece.is_white { -1 } else { 1 };
-
-            // handle standard advance
-            let pos_advance = get_position(
-                piece_position,
-                &Vector2 {
-                    x: 0,
-                    y: move_direction,
-                },
-            );
-            if pos_advance.is_some() {
-                let valid_pos_advance = pos_advance.unwrap();
-                if is_square_none(game, &valid_pos_advance) {
-                    all_moves.insert(valid_pos_advance);
-
-                    // if the pawn has not moved
-                    if start_position == piece_position.y {
-                        let pos = get_position(
-                            piece_position,
-                            &Vector2 {
-                                x: 0,
-                                y: move_direction * 2,
-                            },
-                        )
-                        .unwrap();
-                        if is_square_none(game, &pos) {
-                            all_moves.insert(pos);
-                        }
-                    };
-                }
-            }
-
-            // handle diagonal moves
-            let pawn_movelist: &[Vector2; 2] = &[
-                Vector2 {
-                    x: -1,
-                    y: move_direction,
-                },
-                Vector2 {
-                    x: 1,
-                    y: move_direction,
-                },
-            ];
-
-            for new_move in pawn_movelist {
-                let new_valid_position = match get_position(piece_position, &new_move) {
-                    Some(pos) => pos,
-                    None => continue,
-                };
-
-                // en passant is avalible
-                if game.en_passant_position.is_some()
-                    && new_valid_position == game.en_passant_position.unwrap()
-                {
-                    /*let real_capture_unchecked = get_position(
-                        &new_valid_position,
-                        &Vector2 {
-                            x: 0,
-                            y: -move_direction,
-                        },
-                    );*/
-                    // ye, this is a dirty solution, however it works
-                    //if real_capture_unchecked.is_some() {
-                    let mut clone_game = game.clone();
-                    move_piece_unsafe(&mut clone_game, *piece_position, new_valid_position);
-                    let other_threats =
-                        generate_all_threats(&clone_game, clone_game.is_white_to_move);
-                    let len = other_threats.all_king_threats.len();
-                    if len == 0 {
-                        all_moves.insert(new_valid_position);
-                    }
-                    //}
-                } else if !is_square_color(game, &new_valid_position, start_piece.is_white) {
-                    // I dont want the pawn to be able to move sideways, but to threaten the sideways pieces
-
-                    // aka is about to capture the king
-                    if game.board[new_valid_position.x][new_valid_position.y].piece == Piece::King {
-                        all_king_threats.insert(new_valid_position);
-                        all_king_threats.insert(*piece_position);
-                    }
-                    if is_square_color(game, &new_valid_position, !start_piece.is_white) {
-                        all_moves.insert(new_valid_position);
-                    }
-                }
-                all_threats.insert(new_valid_position);
-            }
-        } else {
-            let moveset = get_moveset(start_piece.piece);
-
-            // Goes though all jumps
-            for r_move in moveset.regular_moves {
-                let new_valid_position = match get_position(piece_position, &r_move) {
-                    Some(pos) => pos,
-                    None => continue,
-                };
-
-                if !is_square_color(&game, &new_valid_position, start_piece.is_white) {
-                    //aka is about to capture the king
-                    if game.board[new_valid_position.x][new_valid_position.y].piece == Piece::King {
-                        all_king_threats.insert(new_valid_position);
-                        all_king_threats.insert(*piece_position);
-                    }
-                    all_moves.insert(new_valid_position);
-                }
-                all_threats.insert(new_valid_position);
-            }
-
-            // Goes though all inf move directions
-            for i_move in moveset.inf_moves {
-                let mut index = 0;
-                // 0 means direct attacks, 1 means attacks that jump over 1 piece
-                let mut ghost_index = 0;
-                let mut local_line: HashSet<Position> = HashSet::new();
-                local_line.insert(*piece_position);
-                //let mut is_king = -1;
-                loop {
-                    index += 1;
-                    let new_move = Vector2 {
-                        x: i_move.x * index,
-                        y: i_move.y * index,
-                    };
-
-                    let new_valid_position = match get_position(piece_position, &new_move) {
-                        Some(pos) => pos,
-                        None => break,
-                    };
-
-                    local_line.insert(new_valid_position);
-
-                    // if not the same color
-                    if !is_square_color(&game, &new_valid_position, start_piece.is_white) {
-                        if ghost_index == 0 {
-                            all_moves.insert(new_valid_position);
-                            all_threats.insert(new_valid_position);
-                        }
-
-                        // break if the piece is of another color, because the pawn cant ghost though pieces
-                        let capture_piece = game.board[new_valid_position.x][new_valid_position.y];
-                        if capture_piece.piece != Piece::None
-                        //&& (capture_piece.is_white != start_piece.is_white)
-                        {
-                            // as we are only intrested in the first 2 or king, the rest can go
-                            if capture_piece.piece == Piece::King {
-                                if ghost_index == 0 {
-                                    // if something threatens the king, then a valid move is to capture that piece
-                                    all_king_threats.insert(*piece_position);
-                                    for l_move in local_line {
-                                        all_king_threats.insert(l_move);
-                                    }
-
-                                    all_king_threats_full = all_king_threats.clone();
-
-                                    let new_extra_move = Vector2 {
-                                        x: i_move.x * (index + 1),
-                                        y: i_move.y * (index + 1),
-                                    };
-                                    let new_extra =
-                                        match get_position(piece_position, &new_extra_move) {
-                                            Some(pos) => pos,
-                                            None => break,
-                                        };
-                                    all_king_threats_full.insert(new_extra);
-                                } else if ghost_index == 1 {
-                                    all_pinned.push(local_line);
-                                }
-                                break;
-                            }
-
-                            ghost_index += 1;
-                            if ghost_index >= 2 {
-                                break;
-                            }
-                        }
-                    } else {
-                        if ghost_index == 0 {
-                            all_threats.insert(new_valid_position);
-                        }
-                        break;
-                    }
-                }
-                /*if is_king == 0 {
-                    // if something threatens the king, then a valid move is to capture that piece
-                    all_king_positions.insert(*piece_position);
-                    for l_move in local_line {
-                        all_king_positions.insert(l_move);
-                    }
-                } else if ghost_index == 1 {
-                    all_secondary_positions.push(local_line);
-                }*/
-            }
-        }
-    }
-
-    ThreatMap {
-        all_moves,
-        all_threats,
-        all_pinned,
-        all_king_threats,
-        all_king_threats_full,
-    }
-}
-
 /** basic move check */
-fn is_valid_move(game: &Game, move_start: &Position, move_end: &Position) -> bool {
+fn is_valid_move(
+    game: &Game,
+    move_start: &Position,
+    move_end: &Position,
+) -> Result<(), ChessError> {
     if move_start.x >= BOARD_SIZE
         || move_start.y >= BOARD_SIZE
         || move_end.x >= BOARD_SIZE
         || move_end.y >= BOARD_SIZE
-        || move_start == move_end
     {
-        return false;
+        return Err(ChessError::IllegalMove(IllegalMoveReason::OutsideBoard));
     }
 
     let start_piece = game.board[move_start.x][move_start.y];
 
     // start peice is invalid
-    if (start_piece.is_white != game.is_white_to_move) || start_piece.piece == Piece::None {
-        return false;
+    if start_piece.piece == Piece::None {
+        return Err(ChessError::IllegalMove(IllegalMoveReason::NoPiece));
+    }
+    if start_piece.is_white != game.is_white_to_move {
+        return Err(ChessError::IllegalMove(IllegalMoveReason::NotYourTurn));
     }
 
-    // cant capture own color
-    if is_square_color(&game, move_end, game.is_white_to_move) {
-        return false;
+    if move_start == move_end {
+        return Err(ChessError::IllegalMove(IllegalMoveReason::InvalidMovement));
     }
 
-    true
+    // cant capture own color, except for the rook when castling
+    if is_square_color(game, move_end, game.is_white_to_move)
+        && get_castle_rook(&game.castle, start_piece, *move_start, *move_end).is_none()
+    {
+        return Err(ChessError::IllegalMove(IllegalMoveReason::CaptureOwnPiece));
+    }
+
+    Ok(())
 }
 
-//will fail if some gamemode spawns pawns at the beginning
-fn get_promotion_pawn(game: &Game) -> Option<Position> {
-    const PAWN_CHECKS: [usize; 2] = [BLACK_SPAWN, WHITE_SPAWN];
+/** The rank the pawns of the player promotes on, the first rank of the other player */
+pub(crate) fn get_promotion_y(is_white: bool) -> usize {
+    if is_white {
+        BLACK_SPAWN
+    } else {
+        WHITE_SPAWN
+    }
+}
+
+/**
+True if a pawn of the player on the square can move two squares.
+In Variant::Horde the white pawns on the first rank can also do it
+*/
+pub(crate) fn is_pawn_start(game: &Game, square: usize, is_white: bool) -> bool {
+    let y = square / BOARD_SIZE;
+    let start_y = if is_white { WHITE_PAWN_Y } else { BLACK_PAWN_Y };
+    y == start_y || (game.variant == Variant::Horde && is_white && y == WHITE_SPAWN)
+}
 
-    for y in PAWN_CHECKS {
+/** The pawn that has reached the last rank and is waiting to be promoted */
+fn get_promotion_pawn(game: &Game) -> Option<Position> {
+    for is_white in [true, false] {
+        let y = get_promotion_y(is_white);
         for x in 0..BOARD_SIZE {
             let piece_data = game.board[x][y];
-            if piece_data.piece == Piece::Pawn {
-                return Some(Position { x: x, y: y });
+            if piece_data.piece == Piece::Pawn && piece_data.is_white == is_white {
+                return Some(Position { x, y });
             }
         }
     }
@@ -303,25 +103,62 @@ fn get_promotion_pawn(game: &Game) -> Option<Position> {
     None
 }
 
-/** returns true if the promotion took place */
-pub fn promote_pawn(game: &mut Game, promotion: Piece) -> bool {
+/** The pieces a pawn can promote to in the variant */
+pub(crate) fn get_promotion_pieces(variant: Variant) -> &'static [Piece] {
+    if variant == Variant::Antichess {
+        &ANTICHESS_PROMOTION_PIECES
+    } else {
+        &PROMOTION_PIECES
+    }
+}
+
+fn promote_game_pawn(game: &mut Game, promotion: Piece) -> Result<(), ChessError> {
     // check for invalid input
-    if promotion != Piece::Bishop
-        && promotion != Piece::Knight
-        && promotion != Piece::Rook
-        && promotion != Piece::Queen
-    {
-        return false;
+    if !get_promotion_pieces(game.variant).contains(&promotion) {
+        return Err(ChessError::IllegalMove(IllegalMoveReason::InvalidPromotion));
     }
 
     // no pawn to promote
     let position = match get_promotion_pawn(game) {
         Some(pos) => pos,
-        None => return false,
+        None => return Err(ChessError::IllegalMove(IllegalMoveReason::NoPromotion)),
     };
 
-    game.board[position.x][position.y].piece = promotion;
-    return true;
+    let pawn = game.board[position.x][position.y];
+    let was_checked = is_king_checked(game, !pawn.is_white);
+    set_square(
+        game,
+        position,
+        PieceData {
+            piece: promotion,
+            is_white: pawn.is_white,
+        },
+    );
+    if game.variant == Variant::Crazyhouse {
+        game.promoted |= get_square_bitboard(get_square_index(&position));
+    }
+
+    // the check from the pawn move is already counted, a pawn on the last rank attacks nothing
+    if !was_checked {
+        let old_state_key = get_state_key(game);
+        count_check(game, pawn.is_white);
+        game.hash ^= old_state_key ^ get_state_key(game);
+    }
+    Ok(())
+}
+
+/** Promotes the pawn on the last rank, fails if there is no pawn to promote or the piece is invalid */
+pub fn promote_pawn(game_board: &mut Gameboard, promotion: Piece) -> Result<(), ChessError> {
+    promote_game_pawn(&mut game_board.game, promotion)?;
+
+    // the last move is the one that reached the back rank
+    if let Some(last_move) = game_board.history.last_mut() {
+        last_move.promotion = promotion;
+    }
+
+    // the position is first counted when the promotion is done
+    add_repetition(game_board);
+    Ok(())
 }
 
 pub fn get_threats(game_board: &Gameboard) -> ThreatMap {
@@ -330,324 +167,787 @@ pub fn get_threats(game_board: &Gameboard) -> ThreatMap {
 
 /** Includes all threat positions generate by that team */
 pub(crate) fn generate_all_threats(game: &Game, is_white: bool) -> ThreatMap {
-    let mut all_moves: HashSet<Position> = HashSet::new();
-    let mut all_threats: HashSet<Position> = HashSet::new();
-    let mut all_pinned: Vec<HashSet<Position>> = Vec::new();
-    let mut all_king_threats: HashSet<Position> = HashSet::new();
-    let mut all_king_threats_full: HashSet<Position> = HashSet::new();
-
-    for x in 0..BOARD_SIZE {
-        for y in 0..BOARD_SIZE {
-            let position = Position { x, y };
-            if is_square_color(game, &position, is_white) {
-                let piece_threads = generate_all_moves(game, &position);
-                for t_pos in piece_threads.all_pinned {
-                    all_pinned.push(t_pos);
-                }
-                for t_pos in piece_threads.all_threats {
-                    all_threats.insert(t_pos);
-                }
-                for t_pos in piece_threads.all_king_threats {
-                    all_king_threats.insert(t_pos);
-                }
-                for t_pos in piece_threads.all_king_threats_full {
-                    all_king_threats_full.insert(t_pos);
-                }
-                for t_pos in piece_threads.all_moves {
-                    all_moves.insert(t_pos);
-                }
-            }
+    let occupied = get_occupied(game);
+    let team_pieces = game.color_bitboards[get_color_index(is_white)];
+    let other_pieces = game.color_bitboards[get_color_index(!is_white)];
+    let other_king = get_pieces(game, Piece::King, !is_white);
+
+    // the king is removed so that it cant escape a check by moving along the attack
+    let all_threats = get_attacked_squares(game, occupied & !other_king, is_white);
+
+    // gamemodes without kings can never be checked
+    if other_king == EMPTY_BITBOARD {
+        return ThreatMap {
+            all_threats,
+            all_king_threats: EMPTY_BITBOARD,
+            check_mask: FULL_BITBOARD,
+            all_pinned: EMPTY_BITBOARD,
+        };
+    }
+
+    let king_square = other_king.trailing_zeros() as usize;
+    let all_king_threats = get_attackers(game, king_square, occupied, is_white);
+
+    let check_mask = match all_king_threats.count_ones() {
+        0 => FULL_BITBOARD,
+        1 => {
+            all_king_threats | get_between(king_square, all_king_threats.trailing_zeros() as usize)
+        }
+        _ => EMPTY_BITBOARD,
+    };
+
+    // sliding pieces that would attack the king if only the pieces of this team blocked them
+    let queens = get_pieces(game, Piece::Queen, is_white);
+    let mut snipers = (get_rook_attacks(king_square, team_pieces)
+        & (get_pieces(game, Piece::Rook, is_white) | queens))
+        | (get_bishop_attacks(king_square, team_pieces)
+            & (get_pieces(game, Piece::Bishop, is_white) | queens));
+
+    let mut all_pinned = EMPTY_BITBOARD;
+    while snipers != EMPTY_BITBOARD {
+        let blockers = get_between(king_square, pop_square(&mut snipers)) & occupied;
+        if blockers.count_ones() == 1 && blockers & other_pieces != EMPTY_BITBOARD {
+            all_pinned |= blockers;
         }
     }
 
     ThreatMap {
-        all_moves,
         all_threats,
-        all_pinned,
         all_king_threats,
-        all_king_threats_full,
+        check_mask,
+        all_pinned,
     }
 }
 
-/*fn find_king(game: &Game, is_white: bool) -> Option<Position> {
-    for x in 0..BOARD_SIZE {
-        for y in 0..BOARD_SIZE {
-            let piece_data = game.board[x][y];
-            if piece_data.piece == Piece::King && piece_data.is_white == is_white {
-                return Some(Position { x, y });
-            }
+fn add_pawn_moves(from: usize, to: usize, add_move: &mut impl FnMut(usize, usize, Piece)) {
+    let y = to / BOARD_SIZE;
+    if y == BLACK_SPAWN || y == WHITE_SPAWN {
+        for promotion in PROMOTION_PIECES {
+            add_move(from, to, promotion);
         }
+    } else {
+        add_move(from, to, Piece::None);
     }
-    None
-}*/
+}
 
-fn get_castle_positions(
-    game: &Game,
-    other_team_threat_map: &ThreatMap,
-    piece_position: &Position,
-    is_white: bool,
-) -> HashSet<Position> {
-    let mut moves = HashSet::new();
+fn add_moves(from: usize, mut targets: Bitboard, add_move: &mut impl FnMut(usize, usize, Piece)) {
+    while targets != EMPTY_BITBOARD {
+        add_move(from, pop_square(&mut targets), Piece::None);
+    }
+}
 
-    let castle_status = if is_white {
-        &game.castle[0]
-    } else {
-        &game.castle[1]
+/** en passant can reveal a check on the king along the rank, so the capture is simulated */
+fn is_valid_en_passant(game: &Game, from: usize, to: usize, is_white: bool) -> bool {
+    let capture_position = Position {
+        x: to % BOARD_SIZE,
+        y: from / BOARD_SIZE,
     };
+    let capture_piece = game.board[capture_position.x][capture_position.y];
+    if capture_piece.piece != Piece::Pawn || capture_piece.is_white == is_white {
+        return false;
+    }
 
-    let piece_data = game.board[piece_position.x][piece_position.y];
-
-    // has no king... how? or cant castle
-    if piece_data.is_white != is_white
-        || piece_data.piece != Piece::King
-        || (!castle_status.can_castle_king_side && !castle_status.can_castle_queen_side)
+    // in Variant::Atomic the capture is played to see if the king is safe after the explosion
+    // and in Variant::Antichess the king can not be checked
+    let king = get_pieces(game, Piece::King, is_white);
+    if king == EMPTY_BITBOARD
+        || game.variant == Variant::Atomic
+        || game.variant == Variant::Antichess
     {
-        return moves;
+        return true;
     }
 
-    // be aware that you can trick this by adding a second rook because
-    // it only keeps track of the first rook on the left or right side
-    let spawn_y = if is_white { WHITE_SPAWN } else { BLACK_SPAWN };
+    let capture_bitboard = get_square_bitboard(get_square_index(&capture_position));
+    let occupied = (get_occupied(game) & !get_square_bitboard(from) & !capture_bitboard)
+        | get_square_bitboard(to);
+    let attackers = get_attackers(game, king.trailing_zeros() as usize, occupied, !is_white);
+    attackers & !capture_bitboard == EMPTY_BITBOARD
+}
 
-    // right y pos
-    if spawn_y == piece_position.y {
-        // king cant castle if checked
-        const KING_SIDES: [i8; 2] = [-1, 1];
-        for offset in KING_SIDES {
-            if !other_team_threat_map
-                .all_king_threats
-                .contains(piece_position)
-            {
-                let is_king_side = offset > 0;
+/** The end files of the king and the rook after castling, they are the same in Chess960 */
+const KING_SIDE_CASTLE_X: (usize, usize) = (6, 5);
+const QUEEN_SIDE_CASTLE_X: (usize, usize) = (2, 3);
 
-                // if can castle
-                if if is_king_side {
-                    castle_status.can_castle_king_side
-                } else {
-                    castle_status.can_castle_queen_side
-                } {
-                    let rook_pos = if is_king_side {
-                        castle_status.king_side_rook
-                    } else {
-                        castle_status.queen_side_rook
-                    };
+/**
+The start position of the rook if the move is castling, else None.
+Castling is either the king moving two squares or the king taking its own rook
+*/
+pub(crate) fn get_castle_rook(
+    castle: &[Castle; 2],
+    piece_data: PieceData,
+    from: Position,
+    to: Position,
+) -> Option<Position> {
+    if piece_data.piece != Piece::King || from.y != to.y {
+        return None;
+    }
 
-                    for index in 1..BOARD_SIZE {
-                        let new_valid_position = match get_position(
-                            piece_position,
-                            &Vector2 {
-                                x: (index as i8) * offset,
-                                y: 0,
-                            },
-                        ) {
-                            Some(pos) => pos,
-                            None => break,
-                        };
-
-                        // the king cant be checked on his way over to the rook,
-                        // because the king only moves 2 squares, the index is 2
-                        if other_team_threat_map
-                            .all_threats
-                            .contains(&new_valid_position)
-                            && index <= 2
-                        {
-                            break;
-                        }
+    let castle_status = castle[get_color_index(piece_data.is_white)];
+    if castle_status.can_castle_king_side
+        && (to == castle_status.king_side_rook || to.x == from.x + 2)
+    {
+        Some(castle_status.king_side_rook)
+    } else if castle_status.can_castle_queen_side
+        && (to == castle_status.queen_side_rook || to.x + 2 == from.x)
+    {
+        Some(castle_status.queen_side_rook)
+    } else {
+        None
+    }
+}
 
-                        let piece_data = game.board[new_valid_position.x][new_valid_position.y];
+/** Where the king and the rook ends up after castling */
+pub(crate) fn get_castle_ends(king: Position, rook: Position) -> (Position, Position) {
+    let (king_x, rook_x) = if rook.x > king.x {
+        KING_SIDE_CASTLE_X
+    } else {
+        QUEEN_SIDE_CASTLE_X
+    };
+    (
+        Position {
+            x: king_x,
+            y: king.y,
+        },
+        Position {
+            x: rook_x,
+            y: king.y,
+        },
+    )
+}
 
-                        // found rook
-                        if piece_data.is_white == is_white
-                            && piece_data.piece == Piece::Rook
-                            && new_valid_position == rook_pos
-                        {
-                            let new_valid_king = match get_position(
-                                piece_position,
-                                &Vector2 {
-                                    x: 2i8 * offset,
-                                    y: 0,
-                                },
-                            ) {
-                                Some(pos) => pos,
-                                None => break,
-                            };
-                            moves.insert(new_valid_king);
-                        } else if piece_data.piece != Piece::None {
-                            // cant jump over pieces
-                            break;
-                        }
-                    }
-                }
-            }
-        }
+/**
+Castling can be written both as the king moving two squares and as the king taking its own rook,
+this returns the end square that the legal moves of the game uses
+*/
+pub(crate) fn get_castle_move_end(game: &Game, from: Position, to: Position) -> Position {
+    if from.x >= BOARD_SIZE || from.y >= BOARD_SIZE {
+        return to;
+    }
+    match get_castle_rook(&game.castle, game.board[from.x][from.y], from, to) {
+        Some(rook_position) if game.is_chess960 => rook_position,
+        Some(rook_position) => get_castle_ends(from, rook_position).0,
+        None => to,
     }
-    return moves;
 }
 
-pub(crate) fn generate_all_moves_and_castle(
+fn add_castle_moves(
     game: &Game,
     other_team_threat_map: &ThreatMap,
-    piece_position: &Position,
-) -> HashSet<Position> {
-    if piece_position.x >= BOARD_SIZE || piece_position.y >= BOARD_SIZE {
-        return HashSet::new();
+    from: usize,
+    is_white: bool,
+    add_move: &mut impl FnMut(usize, usize, Piece),
+) {
+    let castle_status = game.castle[get_color_index(is_white)];
+    let spawn_y = if is_white { WHITE_SPAWN } else { BLACK_SPAWN };
+
+    // king cant castle if checked or if it is not on the first row
+    if is_in_check(game, other_team_threat_map) || from / BOARD_SIZE != spawn_y {
+        return;
     }
-    let mut moves = generate_all_moves(&game, piece_position).all_moves;
 
-    let start_piece = game.board[piece_position.x][piece_position.y];
-    if start_piece.piece == Piece::King {
-        let castle_moves = get_castle_positions(
-            game,
-            other_team_threat_map,
-            piece_position,
-            start_piece.is_white,
-        );
-        for c_move in castle_moves {
-            moves.insert(c_move);
+    let sides = [
+        (
+            castle_status.can_castle_king_side,
+            castle_status.king_side_rook,
+        ),
+        (
+            castle_status.can_castle_queen_side,
+            castle_status.queen_side_rook,
+        ),
+    ];
+
+    let occupied = get_occupied(game);
+    for (can_castle, rook_position) in sides {
+        if !can_castle {
+            continue;
         }
-    }
 
-    return moves;
-}
+        let rook_data = game.board[rook_position.x][rook_position.y];
+        if rook_data.piece != Piece::Rook || rook_data.is_white != is_white {
+            continue;
+        }
 
-/**Used for debugging */
-/*pub(crate) fn generate_valid_moves_for_team(
-    game: &Game,
-    other_team_threat_map: &ThreatMap,
-    is_white: bool,
-) -> HashSet<Position> {
-    let mut map: HashSet<Position> = HashSet::new();
-
-    for x in 0..BOARD_SIZE {
-        for y in 0..BOARD_SIZE {
-            let pos = &Position { x: x, y: y };
-            if is_square_color(game, pos, is_white) {
-                for v_pos in generate_valid_moves(game, other_team_threat_map, pos) {
-                    map.insert(v_pos);
-                }
+        // in Chess960 the king and the rook can start anywhere, so both paths has to be empty
+        let rook_square = get_square_index(&rook_position);
+        let (king_end, rook_end) = get_castle_ends(get_square_position(from), rook_position);
+        let king_to = get_square_index(&king_end);
+        let rook_to = get_square_index(&rook_end);
+        let castle_pieces = get_square_bitboard(from) | get_square_bitboard(rook_square);
+        let king_path = get_between(from, king_to) | get_square_bitboard(king_to);
+        let rook_path = get_between(rook_square, rook_to) | get_square_bitboard(rook_to);
+        if (king_path | rook_path) & occupied & !castle_pieces != EMPTY_BITBOARD {
+            continue;
+        }
+
+        // the king cant be checked on his way over to the rook
+        let mut king_passes = king_path & !get_square_bitboard(from);
+        if game.variant == Variant::Atomic {
+            // the pseudo legal moves has no threats, the king is safe next to the other king
+            let mut is_attacked = false;
+            while king_passes != EMPTY_BITBOARD {
+                let square = pop_square(&mut king_passes);
+                let occupied_without_king = occupied & !get_square_bitboard(from);
+                is_attacked |=
+                    is_king_square_attacked(game, square, occupied_without_king, is_white);
+            }
+            if is_attacked {
+                continue;
             }
+        } else if king_passes & other_team_threat_map.all_threats != EMPTY_BITBOARD {
+            continue;
         }
-    }
-    return map;
-}*/
 
-pub fn get_all_valid_moves(
-    game_board: &Gameboard,
-    other_team_threat_map: &ThreatMap,
-    piece_position: &Position,
-) -> HashSet<Position> {
-    return generate_valid_moves(&game_board.game, other_team_threat_map, piece_position);
+        // the rook can be the piece that blocked a check on the end square
+        let occupied_after = (occupied & !castle_pieces)
+            | get_square_bitboard(king_to)
+            | get_square_bitboard(rook_to);
+        if is_king_square_attacked(game, king_to, occupied_after, is_white) {
+            continue;
+        }
+
+        let to = if game.is_chess960 {
+            rook_square
+        } else {
+            king_to
+        };
+        add_move(from, to, Piece::None);
+    }
 }
 
-/** TODO en passant fuckery */
-pub(crate) fn generate_valid_moves(
+/** Calls add_move with from, to and promotion for every legal move of the piece */
+fn generate_piece_moves(
     game: &Game,
     other_team_threat_map: &ThreatMap,
-    piece_position: &Position,
-) -> HashSet<Position> {
-    let mut valid_positions: HashSet<Position> = HashSet::new();
+    from: usize,
+    add_move: &mut impl FnMut(usize, usize, Piece),
+) {
+    let piece_position = get_square_position(from);
+    let piece_data = game.board[piece_position.x][piece_position.y];
+    let is_white = piece_data.is_white;
+    if piece_data.piece == Piece::None || is_white != game.is_white_to_move {
+        return;
+    }
 
-    let all_positions = generate_all_moves_and_castle(game, other_team_threat_map, piece_position);
-    if all_positions.len() == 0 {
-        return valid_positions;
+    let occupied = get_occupied(game);
+    let team_pieces = game.color_bitboards[get_color_index(is_white)];
+    let other_pieces = game.color_bitboards[get_color_index(!is_white)];
+
+    if piece_data.piece == Piece::King {
+        let targets = get_king_attacks(from) & !team_pieces & !other_team_threat_map.all_threats;
+        add_moves(from, targets, add_move);
+        add_castle_moves(game, other_team_threat_map, from, is_white, add_move);
+        return;
     }
 
-    let piece_data = game.board[piece_position.x][piece_position.y];
-    let is_white = piece_data.is_white;
+    // place infront of king or capture
+    // but cant move if it will reveal the king
+    let mut mask = !team_pieces & other_team_threat_map.check_mask;
+    if other_team_threat_map.all_pinned & get_square_bitboard(from) != EMPTY_BITBOARD {
+        let king = get_pieces(game, Piece::King, is_white);
+        mask &= get_line(king.trailing_zeros() as usize, from);
+    }
 
-    // 1. find king threats
-    if other_team_threat_map.all_king_threats.len() > 0 {
-        if piece_data.piece == Piece::King {
-            for pos in all_positions {
-                // 1. King cant capture own color
-                // 2. King can capture the one that threatens the king, but cant capture a piece that threats it if it is also threatend
-                if !is_square_color(game, &pos, is_white) {
-                    if (is_square_color(game, &pos, !is_white)
-                        && !other_team_threat_map.all_threats.contains(&pos))
-                        || (!other_team_threat_map.all_threats.contains(&pos)
-                            && !other_team_threat_map.all_king_threats_full.contains(&pos))
-                    {
-                        valid_positions.insert(pos);
+    match piece_data.piece {
+        Piece::Knight => add_moves(from, get_knight_attacks(from) & mask, add_move),
+        Piece::Bishop => add_moves(from, get_bishop_attacks(from, occupied) & mask, add_move),
+        Piece::Rook => add_moves(from, get_rook_attacks(from, occupied) & mask, add_move),
+        Piece::Queen => add_moves(
+            from,
+            (get_rook_attacks(from, occupied) | get_bishop_attacks(from, occupied)) & mask,
+            add_move,
+        ),
+        Piece::Pawn => {
+            // handle standard advance
+            let advance = if is_white {
+                from.checked_sub(BOARD_SIZE)
+            } else {
+                Some(from + BOARD_SIZE).filter(|&to| to < BOARD_SIZE * BOARD_SIZE)
+            };
+            if let Some(to) = advance {
+                if occupied & get_square_bitboard(to) == EMPTY_BITBOARD {
+                    if mask & get_square_bitboard(to) != EMPTY_BITBOARD {
+                        add_pawn_moves(from, to, add_move);
                     }
-                }
-            }
-        } else {
-            let mut is_secondary = false;
-            for local_list in &other_team_threat_map.all_pinned {
-                if local_list.contains(piece_position) {
-                    is_secondary = true;
-                    break;
-                }
-            }
 
-            // place infront of king or capture
-            // but cant move if it will reveal the king
-            if !is_secondary {
-                for pos in all_positions {
-                    if !is_square_color(game, &pos, is_white)
-                        && other_team_threat_map.all_king_threats.contains(&pos)
+                    // if the pawn has not moved
+                    let double_to = if is_white {
+                        to.checked_sub(BOARD_SIZE)
+                    } else {
+                        Some(to + BOARD_SIZE)
+                    };
+                    if let Some(double_to) =
+                        double_to.filter(|_| is_pawn_start(game, from, is_white))
                     {
-                        //println!("{}", print_position(pos));
-
-                        valid_positions.insert(pos);
+                        let double_bitboard = get_square_bitboard(double_to);
+                        if occupied & double_bitboard == EMPTY_BITBOARD
+                            && mask & double_bitboard != EMPTY_BITBOARD
+                        {
+                            add_move(from, double_to, Piece::None);
+                        }
                     }
                 }
             }
-        }
-
-        return valid_positions;
-    }
 
-    // 2. check if is secondary else normal
-    if piece_data.piece != Piece::King {
-        for local_list in &other_team_threat_map.all_pinned {
-            if local_list.contains(piece_position) {
-                for pos in all_positions {
-                    if local_list.contains(&pos) {
-                        valid_positions.insert(pos);
-                    }
-                }
-                return valid_positions;
+            // handle diagonal moves
+            let attacks = get_pawn_attacks(from, is_white);
+            let mut captures = attacks & other_pieces & mask;
+            while captures != EMPTY_BITBOARD {
+                add_pawn_moves(from, pop_square(&mut captures), add_move);
             }
-        }
-        for pos in all_positions {
-            valid_positions.insert(pos);
-        }
-    } else {
-        for pos in all_positions {
-            if !other_team_threat_map.all_threats.contains(&pos) {
-                // you cant capture a pinned piece with your king
-                let mut cant_capture = false;
-                if is_square_color(game, &pos, !is_white) {
-                    for local_list in &other_team_threat_map.all_pinned {
-                        if local_list.contains(&pos) {
-                            cant_capture = true;
-                            break;
-                        }
-                    }
-                }
-                if cant_capture {
-                    continue;
-                }
 
-                valid_positions.insert(pos);
+            // en passant is avalible
+            if let Some(en_passant_position) = game.en_passant_position {
+                let to = get_square_index(&en_passant_position);
+                if attacks & get_square_bitboard(to) != EMPTY_BITBOARD
+                    && occupied & get_square_bitboard(to) == EMPTY_BITBOARD
+                    && is_valid_en_passant(game, from, to, is_white)
+                {
+                    add_move(from, to, Piece::None);
+                }
             }
         }
-        return valid_positions;
+        Piece::King | Piece::None => {}
     }
-    return valid_positions;
 }
 
-fn remove_castle(game: &mut Game, pos: Position) {
-    let start_piece = game.board[pos.x][pos.y];
-
-    let is_white = start_piece.is_white;
+/** Every square except the first and last rank, where pawns can not be dropped */
+const PAWN_DROP_SQUARES: Bitboard = 0x00FF_FFFF_FFFF_FF00;
 
-    let player_index = if is_white { 0 } else { 1 };
-    let mut castle_status = game.castle[player_index];
-    if castle_status.queen_side_rook == pos {
-        castle_status.can_castle_queen_side = false;
-    } else if castle_status.king_side_rook == pos {
+/**
+Calls add_move for every legal drop of the player to move in Variant::Crazyhouse,
+a drop is given with the same from and to and the dropped piece as promotion
+*/
+fn generate_drop_moves(
+    game: &Game,
+    other_team_threat_map: &ThreatMap,
+    add_move: &mut impl FnMut(usize, usize, Piece),
+) {
+    // a drop can only block a single check, it can never reveal one
+    let targets = !get_occupied(game) & other_team_threat_map.check_mask;
+    let pocket = game.pockets[get_color_index(game.is_white_to_move)];
+    for (piece, count) in POCKET_PIECES.iter().zip(pocket.iter()) {
+        if *count == 0 {
+            continue;
+        }
+        let mut squares = if *piece == Piece::Pawn {
+            targets & PAWN_DROP_SQUARES
+        } else {
+            targets
+        };
+        while squares != EMPTY_BITBOARD {
+            let to = pop_square(&mut squares);
+            add_move(to, to, *piece);
+        }
+    }
+}
+
+/** True if the move drops a piece from the pocket in Variant::Crazyhouse */
+pub fn is_drop(game_move: &Move) -> bool {
+    game_move.from == game_move.to
+}
+
+/**
+Calls add_move with from, to and promotion for every legal move of the player to move.
+In Variant::Crazyhouse drops are given with the same from and to and the dropped piece as promotion
+*/
+pub(crate) fn generate_all_legal_moves(
+    game: &Game,
+    other_team_threat_map: &ThreatMap,
+    add_move: &mut impl FnMut(usize, usize, Piece),
+) {
+    // no moves are legal after the game is won by the rules of the variant
+    if get_variant_result(game).is_some() {
+        return;
+    }
+
+    if game.variant == Variant::Atomic {
+        let mut pieces = game.color_bitboards[get_color_index(game.is_white_to_move)];
+        while pieces != EMPTY_BITBOARD {
+            generate_atomic_piece_moves(game, pop_square(&mut pieces), add_move);
+        }
+        return;
+    }
+
+    if game.variant == Variant::Antichess {
+        let must_capture = has_antichess_capture(game);
+        let mut pieces = game.color_bitboards[get_color_index(game.is_white_to_move)];
+        while pieces != EMPTY_BITBOARD {
+            generate_antichess_piece_moves(game, pop_square(&mut pieces), must_capture, add_move);
+        }
+        return;
+    }
+
+    if game.variant == Variant::RacingKings {
+        let mut pieces = game.color_bitboards[get_color_index(game.is_white_to_move)];
+        while pieces != EMPTY_BITBOARD {
+            generate_racing_kings_piece_moves(
+                game,
+                other_team_threat_map,
+                pop_square(&mut pieces),
+                add_move,
+            );
+        }
+        return;
+    }
+
+    let mut pieces = game.color_bitboards[get_color_index(game.is_white_to_move)];
+
+    // only the king can move in a double check
+    if other_team_threat_map.all_king_threats.count_ones() > 1 {
+        pieces &= game.piece_bitboards[get_piece_index(Piece::King)];
+    }
+
+    while pieces != EMPTY_BITBOARD {
+        generate_piece_moves(
+            game,
+            other_team_threat_map,
+            pop_square(&mut pieces),
+            add_move,
+        );
+    }
+
+    if game.variant == Variant::Crazyhouse {
+        generate_drop_moves(game, other_team_threat_map, add_move);
+    }
+}
+
+/** All legal moves for the player to move using an already generated threat map of the other team */
+pub(crate) fn generate_legal_moves_with_threats(
+    game: &Game,
+    other_team_threat_map: &ThreatMap,
+) -> Vec<Move> {
+    let mut moves = Vec::new();
+    generate_all_legal_moves(game, other_team_threat_map, &mut |from, to, promotion| {
+        moves.push(create_move(
+            game,
+            get_square_position(from),
+            get_square_position(to),
+            promotion,
+        ));
+    });
+    moves
+}
+
+/** All legal moves for the player to move */
+pub(crate) fn generate_legal_moves(game: &Game) -> Vec<Move> {
+    let threat_map = generate_all_threats(game, !game.is_white_to_move);
+    generate_legal_moves_with_threats(game, &threat_map)
+}
+
+/**
+Returns every legal move for the player to move, including castling, en passant and drops in Variant::Crazyhouse.
+Every promotion choice is returned as its own move. If a promotion is pending no move is legal.
+*/
+pub fn legal_moves(game_board: &Gameboard) -> Vec<Move> {
+    if get_promotion_pawn(&game_board.game).is_some() {
+        return Vec::new();
+    }
+    generate_legal_moves(&game_board.game)
+}
+
+pub fn get_all_valid_moves(
+    game_board: &Gameboard,
+    other_team_threat_map: &ThreatMap,
+    piece_position: &Position,
+) -> HashSet<Position> {
+    return generate_valid_moves(&game_board.game, other_team_threat_map, piece_position);
+}
+
+pub(crate) fn generate_valid_moves(
+    game: &Game,
+    other_team_threat_map: &ThreatMap,
+    piece_position: &Position,
+) -> HashSet<Position> {
+    let mut valid_positions: HashSet<Position> = HashSet::new();
+    if piece_position.x >= BOARD_SIZE
+        || piece_position.y >= BOARD_SIZE
+        || get_variant_result(game).is_some()
+    {
+        return valid_positions;
+    }
+
+    let from = get_square_index(piece_position);
+    let add_position = &mut |_, to, _| {
+        valid_positions.insert(get_square_position(to));
+    };
+    if game.variant == Variant::Atomic {
+        generate_atomic_piece_moves(game, from, add_position);
+        return valid_positions;
+    }
+    if game.variant == Variant::Antichess {
+        let must_capture = has_antichess_capture(game);
+        generate_antichess_piece_moves(game, from, must_capture, add_position);
+        return valid_positions;
+    }
+    if game.variant == Variant::RacingKings {
+        generate_racing_kings_piece_moves(game, other_team_threat_map, from, add_position);
+        return valid_positions;
+    }
+
+    // only the king can move in a double check
+    let piece_data = game.board[piece_position.x][piece_position.y];
+    if other_team_threat_map.all_king_threats.count_ones() > 1 && piece_data.piece != Piece::King {
+        return valid_positions;
+    }
+
+    generate_piece_moves(game, other_team_threat_map, from, add_position);
+    valid_positions
+}
+
+/** All squares the piece can be dropped on from the pocket of the player to move in Variant::Crazyhouse */
+pub fn get_valid_drops(
+    game_board: &Gameboard,
+    other_team_threat_map: &ThreatMap,
+    piece: Piece,
+) -> HashSet<Position> {
+    let mut valid_positions: HashSet<Position> = HashSet::new();
+    let game = &game_board.game;
+    if game.variant != Variant::Crazyhouse || get_promotion_pawn(game).is_some() {
+        return valid_positions;
+    }
+
+    generate_drop_moves(game, other_team_threat_map, &mut |_, to, drop_piece| {
+        if drop_piece == piece {
+            valid_positions.insert(get_square_position(to));
+        }
+    });
+    valid_positions
+}
+
+/** The threats used for pseudo legal moves, the moves the pieces could do if the king was never in danger */
+const UNCHECKED_THREAT_MAP: ThreatMap = ThreatMap {
+    all_threats: EMPTY_BITBOARD,
+    all_king_threats: EMPTY_BITBOARD,
+    check_mask: FULL_BITBOARD,
+    all_pinned: EMPTY_BITBOARD,
+};
+
+/**
+Why a pseudo legal move is not legal in Variant::Atomic, None if it is legal.
+The move is played, it is legal if the other king explodes or if the own king is left without a check
+*/
+fn get_atomic_illegal_reason(game: &Game, from: usize, to: usize) -> Option<IllegalMoveReason> {
+    let from_position = get_square_position(from);
+    let to_position = get_square_position(to);
+    let game_move = create_move(game, from_position, to_position, Piece::None);
+    let is_white = game_move.piece.is_white;
+    if game_move.piece.piece == Piece::King && game_move.captured.piece != Piece::None {
+        return Some(IllegalMoveReason::InvalidMovement);
+    }
+
+    let mut next_game = *game;
+    move_piece_unsafe(&mut next_game, from_position, to_position);
+    if get_pieces(&next_game, Piece::King, is_white) == EMPTY_BITBOARD {
+        Some(IllegalMoveReason::ExplodesOwnKing)
+    } else if get_pieces(&next_game, Piece::King, !is_white) == EMPTY_BITBOARD {
+        None
+    } else if is_king_checked(&next_game, is_white) {
+        Some(if is_king_checked(game, is_white) {
+            IllegalMoveReason::KingInCheck
+        } else {
+            IllegalMoveReason::MovesIntoCheck
+        })
+    } else {
+        None
+    }
+}
+
+/** Calls add_move for every legal move of the piece in Variant::Atomic */
+fn generate_atomic_piece_moves(
+    game: &Game,
+    from: usize,
+    add_move: &mut impl FnMut(usize, usize, Piece),
+) {
+    generate_piece_moves(
+        game,
+        &UNCHECKED_THREAT_MAP,
+        from,
+        &mut |from, to, promotion| {
+            if get_atomic_illegal_reason(game, from, to).is_none() {
+                add_move(from, to, promotion);
+            }
+        },
+    );
+}
+
+/** True if the player to move can capture anything in Variant::Antichess, then only captures are legal */
+fn has_antichess_capture(game: &Game) -> bool {
+    let is_white = game.is_white_to_move;
+    let other_pieces = game.color_bitboards[get_color_index(!is_white)];
+    if get_attacked_squares(game, get_occupied(game), is_white) & other_pieces != EMPTY_BITBOARD {
+        return true;
+    }
+
+    // a pawn that can take en passant stands where a pawn of the other player on the en passant square would attack
+    match game.en_passant_position {
+        Some(position) => {
+            let pawns = get_pieces(game, Piece::Pawn, is_white);
+            get_pawn_attacks(get_square_index(&position), !is_white) & pawns != EMPTY_BITBOARD
+        }
+        None => false,
+    }
+}
+
+/** True if the move captures a piece, en passant is the only capture that ends on an empty square */
+fn is_capture_move(game: &Game, from: usize, to: usize) -> bool {
+    let pawns = get_pieces(game, Piece::Pawn, game.is_white_to_move);
+    get_occupied(game) & get_square_bitboard(to) != EMPTY_BITBOARD
+        || (pawns & get_square_bitboard(from) != EMPTY_BITBOARD
+            && from % BOARD_SIZE != to % BOARD_SIZE)
+}
+
+/**
+Calls add_move for every legal move of the piece in Variant::Antichess.
+There is no check, so every pseudo legal move is legal unless the player has to capture.
+A pawn can also promote to a king, it is the last promotion choice
+*/
+fn generate_antichess_piece_moves(
+    game: &Game,
+    from: usize,
+    must_capture: bool,
+    add_move: &mut impl FnMut(usize, usize, Piece),
+) {
+    generate_piece_moves(
+        game,
+        &UNCHECKED_THREAT_MAP,
+        from,
+        &mut |from, to, promotion| {
+            if must_capture && !is_capture_move(game, from, to) {
+                return;
+            }
+            add_move(from, to, promotion);
+            if promotion == Piece::Knight {
+                add_move(from, to, Piece::King);
+            }
+        },
+    );
+}
+
+/** True if the move checks the king of the other player, the move has to be legal otherwise */
+fn gives_check(game: &Game, from: usize, to: usize) -> bool {
+    let mut next_game = *game;
+    move_piece_unsafe(
+        &mut next_game,
+        get_square_position(from),
+        get_square_position(to),
+    );
+    is_king_checked(&next_game, !game.is_white_to_move)
+}
+
+/** Calls add_move for every legal move of the piece in Variant::RacingKings, the moves that gives check are left out */
+fn generate_racing_kings_piece_moves(
+    game: &Game,
+    other_team_threat_map: &ThreatMap,
+    from: usize,
+    add_move: &mut impl FnMut(usize, usize, Piece),
+) {
+    generate_piece_moves(
+        game,
+        other_team_threat_map,
+        from,
+        &mut |from, to, promotion| {
+            if !gives_check(game, from, to) {
+                add_move(from, to, promotion);
+            }
+        },
+    );
+}
+
+/** Finds out why a move that is not in the valid moves was refused, the basic checks must already be done */
+fn get_illegal_move_reason(
+    game: &Game,
+    other_team_threat_map: &ThreatMap,
+    move_start: &Position,
+    move_end: &Position,
+) -> IllegalMoveReason {
+    let from = get_square_index(move_start);
+    let target = get_square_index(move_end);
+    let mut can_reach = false;
+    generate_piece_moves(game, &UNCHECKED_THREAT_MAP, from, &mut |_, to, _| {
+        if to == target {
+            can_reach = true;
+        }
+    });
+
+    if !can_reach {
+        return IllegalMoveReason::InvalidMovement;
+    }
+
+    if game.variant == Variant::Atomic {
+        return get_atomic_illegal_reason(game, from, target)
+            .unwrap_or(IllegalMoveReason::InvalidMovement);
+    }
+    // the king can not be checked, so the move is only refused when there is a capture to make
+    if game.variant == Variant::Antichess {
+        return IllegalMoveReason::CaptureRequired;
+    }
+
+    // the move would be legal in standard chess
+    if game.variant == Variant::RacingKings {
+        let mut is_legal = false;
+        generate_piece_moves(game, other_team_threat_map, from, &mut |_, to, _| {
+            if to == target {
+                is_legal = true;
+            }
+        });
+        if is_legal {
+            return IllegalMoveReason::GivesCheck;
+        }
+    }
+
+    let piece_data = game.board[move_start.x][move_start.y];
+    let is_checked = other_team_threat_map.all_king_threats != EMPTY_BITBOARD;
+    if piece_data.piece == Piece::King {
+        // castling out of check
+        let is_castle = get_castle_rook(&game.castle, piece_data, *move_start, *move_end).is_some();
+        return if is_castle && is_checked {
+            IllegalMoveReason::KingInCheck
+        } else {
+            IllegalMoveReason::MovesIntoCheck
+        };
+    }
+
+    if other_team_threat_map.all_pinned & get_square_bitboard(from) != EMPTY_BITBOARD {
+        IllegalMoveReason::PiecePinned
+    } else if is_checked {
+        IllegalMoveReason::KingInCheck
+    } else {
+        IllegalMoveReason::MovesIntoCheck
+    }
+}
+
+/** Finds out why a drop that is not in the legal moves was refused */
+fn get_illegal_drop_reason(game: &Game, piece: Piece, position: &Position) -> IllegalMoveReason {
+    let pocket = game.pockets[get_color_index(game.is_white_to_move)];
+    let count = match POCKET_PIECES
+        .iter()
+        .position(|&pocket_piece| pocket_piece == piece)
+    {
+        Some(index) => pocket[index],
+        None => 0,
+    };
+
+    if position.x >= BOARD_SIZE || position.y >= BOARD_SIZE {
+        IllegalMoveReason::OutsideBoard
+    } else if count == 0 {
+        IllegalMoveReason::NotInPocket
+    } else if game.board[position.x][position.y].piece != Piece::None
+        || (piece == Piece::Pawn && (position.y == BLACK_SPAWN || position.y == WHITE_SPAWN))
+    {
+        IllegalMoveReason::InvalidDrop
+    } else {
+        // the only drops left are the ones that does not block a check
+        IllegalMoveReason::KingInCheck
+    }
+}
+
+/** the square is already changed when this is called, so the color of the rook has to be passed */
+fn remove_castle(game: &mut Game, pos: Position, is_white: bool) {
+    let player_index = if is_white { 0 } else { 1 };
+    let mut castle_status = game.castle[player_index];
+    if castle_status.queen_side_rook == pos {
+        castle_status.can_castle_queen_side = false;
+    } else if castle_status.king_side_rook == pos {
         castle_status.can_castle_king_side = false;
     }
     game.castle[player_index] = castle_status;
@@ -659,137 +959,710 @@ Will return true if move is done
 pub(crate) fn move_piece_unsafe(game: &mut Game, move_start: Position, move_end: Position) -> bool {
     let start_piece = game.board[move_start.x][move_start.y];
 
-    if start_piece.piece == Piece::None {
+    if start_piece.piece == Piece::None {
+        return false;
+    }
+
+    let is_white = start_piece.is_white;
+    let mut half_move_clock = game.half_move_clock + 1;
+    let mut en_passant_position: Option<Position> = None;
+
+    // the pieces are hashed by set_square, the rest is replaced at the end
+    let old_state_key = get_state_key(game);
+
+    let castle_rook = get_castle_rook(&game.castle, start_piece, move_start, move_end);
+    // the king standing on its own rook when castling is not a capture
+    let capture_piece = if castle_rook.is_some() {
+        EMPTY_PEICE
+    } else {
+        game.board[move_end.x][move_end.y]
+    };
+    let mut is_capture = capture_piece.piece != Piece::None;
+    // reset on any capture
+    if is_capture || start_piece.piece == Piece::Pawn {
+        half_move_clock = 0;
+    }
+
+    if start_piece.piece != Piece::King && start_piece.piece != Piece::Pawn {
+        set_square(game, move_end, start_piece);
+        set_square(game, move_start, EMPTY_PEICE);
+    } else if start_piece.piece == Piece::Pawn {
+        let move_direction: i8 = if is_white { -1 } else { 1 };
+        let pawn_y = if is_white { WHITE_PAWN_Y } else { BLACK_PAWN_Y };
+
+        // en passant
+        if game.board[move_end.x][move_end.y].piece == Piece::None && (move_end.x != move_start.x) {
+            let real_capture_unchecked = get_position(
+                &move_end,
+                &Vector2 {
+                    x: 0,
+                    y: -move_direction,
+                },
+            );
+
+            // captures the real pawn and not just air
+            if let Some(real_capture) = real_capture_unchecked {
+                let real_capture_piece_data = game.board[real_capture.x][real_capture.y];
+                if real_capture_piece_data.is_white != is_white
+                    && real_capture_piece_data.piece == Piece::Pawn
+                {
+                    set_square(game, real_capture, EMPTY_PEICE);
+                    is_capture = true;
+                }
+            }
+        } else if move_end.y as i8 - move_start.y as i8 == move_direction * 2
+            && move_start.y == pawn_y
+        {
+            // if move twice, it means that it can be en passanted
+            // but not when it moves from the first rank in Variant::Horde
+            en_passant_position = get_position(
+                &move_start,
+                &Vector2 {
+                    x: 0,
+                    y: move_direction,
+                },
+            )
+        }
+
+        set_square(game, move_end, start_piece);
+        set_square(game, move_start, EMPTY_PEICE);
+    } else if start_piece.piece == Piece::King {
+        let player_index = if is_white { 0 } else { 1 };
+        let mut castle_state = game.castle[player_index];
+        castle_state.can_castle_king_side = false;
+        castle_state.can_castle_queen_side = false;
+        game.castle[player_index] = castle_state;
+
+        match castle_rook {
+            None => {
+                set_square(game, move_end, start_piece);
+                set_square(game, move_start, EMPTY_PEICE);
+            }
+            Some(rook_position) => {
+                let (king_end, rook_end) = get_castle_ends(move_start, rook_position);
+                let rook_data = game.board[rook_position.x][rook_position.y];
+
+                // clears old first as the king and the rook can end on each others squares in Chess960
+                set_square(game, rook_position, EMPTY_PEICE);
+                set_square(game, move_start, EMPTY_PEICE);
+
+                set_square(game, rook_end, rook_data);
+                set_square(game, king_end, start_piece);
+            }
+        }
+    }
+
+    let full_move_clock = if is_white {
+        game.full_move_clock
+    } else {
+        game.full_move_clock + 1
+    };
+
+    if start_piece.piece == Piece::Rook {
+        remove_castle(game, move_start, is_white);
+    }
+
+    if capture_piece.piece == Piece::Rook {
+        remove_castle(game, move_end, capture_piece.is_white);
+    }
+
+    if is_capture && game.variant == Variant::Atomic {
+        explode(game, move_end);
+    }
+
+    // the captured piece goes to the pocket, a promoted piece is captured as a pawn
+    if game.variant == Variant::Crazyhouse {
+        let from_bitboard = get_square_bitboard(get_square_index(&move_start));
+        let to_bitboard = get_square_bitboard(get_square_index(&move_end));
+        if is_capture {
+            // en passant leaves the end square empty
+            let pocket_piece = if capture_piece.piece == Piece::None
+                || game.promoted & to_bitboard != EMPTY_BITBOARD
+            {
+                Piece::Pawn
+            } else {
+                capture_piece.piece
+            };
+            game.pockets[get_color_index(is_white)][get_piece_index(pocket_piece)] += 1;
+        }
+
+        let was_promoted = game.promoted & from_bitboard != EMPTY_BITBOARD;
+        game.promoted &= !(from_bitboard | to_bitboard);
+        if was_promoted {
+            game.promoted |= to_bitboard;
+        }
+    }
+
+    game.half_move_clock = half_move_clock;
+    game.full_move_clock = full_move_clock;
+    game.is_white_to_move = !is_white;
+    game.en_passant_position = en_passant_position;
+    count_check(game, is_white);
+    game.hash ^= old_state_key ^ get_state_key(game);
+    return true;
+}
+
+/** Drops a piece from the pocket in Variant::Crazyhouse, like move_piece_unsafe the drop has to be checked beforehand */
+fn drop_piece_unsafe(game: &mut Game, piece_data: PieceData, position: Position) -> bool {
+    let is_white = piece_data.is_white;
+    let pocket_index = match POCKET_PIECES
+        .iter()
+        .position(|&piece| piece == piece_data.piece)
+    {
+        Some(index) if game.pockets[get_color_index(is_white)][index] > 0 => index,
+        _ => return false,
+    };
+
+    let old_state_key = get_state_key(game);
+    game.pockets[get_color_index(is_white)][pocket_index] -= 1;
+    set_square(game, position, piece_data);
+
+    // a drop is neither a capture nor a pawn move, so it counts for the fifty-move rule
+    game.half_move_clock += 1;
+    if !is_white {
+        game.full_move_clock += 1;
+    }
+    game.is_white_to_move = !is_white;
+    game.en_passant_position = None;
+    count_check(game, is_white);
+    game.hash ^= old_state_key ^ get_state_key(game);
+    true
+}
+
+/**
+Removes the capturing piece and every piece that is not a pawn next to the capture in Variant::Atomic,
+the castle rights of the exploded rooks and kings are lost
+*/
+fn explode(game: &mut Game, position: Position) {
+    set_square(game, position, EMPTY_PEICE);
+
+    let pawns = get_pieces(game, Piece::Pawn, true) | get_pieces(game, Piece::Pawn, false);
+    let mut exploded = get_king_attacks(get_square_index(&position)) & get_occupied(game) & !pawns;
+    while exploded != EMPTY_BITBOARD {
+        let exploded_position = get_square_position(pop_square(&mut exploded));
+        let piece_data = game.board[exploded_position.x][exploded_position.y];
+        set_square(game, exploded_position, EMPTY_PEICE);
+        match piece_data.piece {
+            Piece::Rook => remove_castle(game, exploded_position, piece_data.is_white),
+            Piece::King => {
+                let castle_status = &mut game.castle[get_color_index(piece_data.is_white)];
+                castle_status.can_castle_king_side = false;
+                castle_status.can_castle_queen_side = false;
+            }
+            _ => {}
+        }
+    }
+}
+
+/**
+True if the king of the player would be attacked on the square with the occupancy.
+In Variant::Atomic a king next to the other king can not be attacked as the capture would explode both kings
+*/
+fn is_king_square_attacked(game: &Game, square: usize, occupied: Bitboard, is_white: bool) -> bool {
+    if game.variant == Variant::Atomic
+        && get_king_attacks(square) & get_pieces(game, Piece::King, !is_white) != EMPTY_BITBOARD
+    {
+        return false;
+    }
+    get_attackers(game, square, occupied, !is_white) != EMPTY_BITBOARD
+}
+
+/** True if the king of the player is attacked, false if the player has no king or in Variant::Antichess where there is no check */
+pub(crate) fn is_king_checked(game: &Game, is_white: bool) -> bool {
+    let king = get_pieces(game, Piece::King, is_white);
+    if king == EMPTY_BITBOARD || game.variant == Variant::Antichess {
+        return false;
+    }
+    is_king_square_attacked(
+        game,
+        king.trailing_zeros() as usize,
+        get_occupied(game),
+        is_white,
+    )
+}
+
+/**
+True if the player to move is in check, the threat map is not used in Variant::Atomic as it has no pseudo legal threats
+and not in Variant::Antichess where the king is an ordinary piece
+*/
+pub(crate) fn is_in_check(game: &Game, other_team_threat_map: &ThreatMap) -> bool {
+    if game.variant == Variant::Atomic || game.variant == Variant::Antichess {
+        return is_king_checked(game, game.is_white_to_move);
+    }
+    other_team_threat_map.all_king_threats != EMPTY_BITBOARD
+}
+
+/** Counts a check given by the player in Variant::ThreeCheck, the hash has to be updated by the caller */
+fn count_check(game: &mut Game, is_white: bool) {
+    if game.variant == Variant::ThreeCheck && is_king_checked(game, !is_white) {
+        game.checks[get_color_index(is_white)] += 1;
+    }
+}
+
+/** The number of checks that wins in Variant::ThreeCheck */
+pub const THREE_CHECK_WIN: u8 = 3;
+
+/** d5, e5, d4 and e4, the squares the king wins on in Variant::KingOfTheHill */
+const CENTER_SQUARES: Bitboard = (1 << 27) | (1 << 28) | (1 << 35) | (1 << 36);
+
+/** The eighth rank, the kings race to it in Variant::RacingKings */
+const GOAL_SQUARES: Bitboard = 0xFF;
+
+/** True if the black king can move to the eighth rank, when white got there first that draws in Variant::RacingKings */
+fn can_black_king_reach_goal(game: &Game) -> bool {
+    let king = get_pieces(game, Piece::King, false);
+    if king == EMPTY_BITBOARD || game.is_white_to_move {
+        return false;
+    }
+
+    let threat_map = generate_all_threats(game, true);
+    let mut can_reach = false;
+    generate_racing_kings_piece_moves(
+        game,
+        &threat_map,
+        king.trailing_zeros() as usize,
+        &mut |_, to, _| {
+            if get_square_bitboard(to) & GOAL_SQUARES != EMPTY_BITBOARD {
+                can_reach = true;
+            }
+        },
+    );
+    can_reach
+}
+
+/**
+The result in Variant::RacingKings, the first king on the eighth rank wins.
+Black moves after white, so when the white king gets there first the game only ends if black can not follow
+*/
+fn get_racing_kings_result(game: &Game) -> Option<GameResult> {
+    let in_goal =
+        |is_white: bool| get_pieces(game, Piece::King, is_white) & GOAL_SQUARES != EMPTY_BITBOARD;
+    match (in_goal(true), in_goal(false)) {
+        (true, true) => Some(get_tie(GameEndReason::KingInGoal)),
+        (false, true) => Some(get_win(false, GameEndReason::KingInGoal)),
+        (true, false) if can_black_king_reach_goal(game) => None,
+        (true, false) => Some(get_win(true, GameEndReason::KingInGoal)),
+        (false, false) => None,
+    }
+}
+
+/**
+The win by the rules of the variant, like the third check. None if the variant has not ended the game.
+In Variant::RacingKings it can also be a tie when both kings reached the eighth rank
+*/
+pub fn get_variant_result(game: &Game) -> Option<GameResult> {
+    if game.variant == Variant::RacingKings {
+        return get_racing_kings_result(game);
+    }
+
+    let winner = match game.variant {
+        // the player to move wins in Variant::Antichess, that is found by get_game_state as there are no legal moves
+        Variant::Standard | Variant::Crazyhouse | Variant::Antichess | Variant::RacingKings => None,
+        Variant::ThreeCheck => [true, false]
+            .iter()
+            .copied()
+            .find(|&is_white| game.checks[get_color_index(is_white)] >= THREE_CHECK_WIN),
+        Variant::KingOfTheHill => [true, false].iter().copied().find(|&is_white| {
+            get_pieces(game, Piece::King, is_white) & CENTER_SQUARES != EMPTY_BITBOARD
+        }),
+        // a move that explodes the own king is never legal
+        Variant::Atomic => [true, false]
+            .iter()
+            .copied()
+            .find(|&is_white| get_pieces(game, Piece::King, !is_white) == EMPTY_BITBOARD),
+        // black wins when every white piece is captured
+        Variant::Horde => Some(false).filter(|_| game.color_bitboards[0] == EMPTY_BITBOARD),
+    };
+    let reason = match game.variant {
+        Variant::KingOfTheHill => GameEndReason::KingInCenter,
+        Variant::Atomic => GameEndReason::KingCaptured,
+        Variant::Horde => GameEndReason::AllPiecesLost,
+        _ => GameEndReason::ThirdCheck,
+    };
+    winner.map(|is_white| get_win(is_white, reason))
+}
+
+/**
+True if the player can not checkmate the other king with any series of legal moves, used when the other player runs out of time.
+That is a lone king, a single knight when the other player has nothing that can block except queens
+or only bishops on one color when there are no pawns or knights on the board.
+In Variant::ThreeCheck only a lone king can not win, in Variant::KingOfTheHill there is always enough
+and Variant::Atomic has its own rules in has_atomic_insufficient_material.
+In Variant::Crazyhouse the pieces never leave the game, so only a single knight or bishop left is not enough for either player.
+In Variant::Antichess a player can only not win when nothing but bishops on other square colors than the other player are left,
+as they can never capture each other. In Variant::Horde black can always capture the rest of the white pieces
+and in Variant::RacingKings the king can always race to the eighth rank
+*/
+pub fn has_insufficient_material(game: &Game, is_white: bool) -> bool {
+    let team_pieces = game.color_bitboards[get_color_index(is_white)];
+    let other_pieces = game.color_bitboards[get_color_index(!is_white)];
+    let get_all = |piece: Piece| get_pieces(game, piece, true) | get_pieces(game, piece, false);
+
+    match game.variant {
+        Variant::Standard => {}
+        // any piece can give the checks, but a lone king can not
+        Variant::ThreeCheck => return team_pieces & !get_all(Piece::King) == EMPTY_BITBOARD,
+        // the king can always walk to the center
+        Variant::KingOfTheHill | Variant::RacingKings => return false,
+        Variant::Horde if !is_white => return false,
+        Variant::Horde => {}
+        Variant::Atomic => return has_atomic_insufficient_material(game, is_white),
+        Variant::Antichess => {
+            return get_occupied(game) == get_all(Piece::Bishop)
+                && has_bishops_on_other_colors(game);
+        }
+        Variant::Crazyhouse => {
+            // a promoted piece goes back to the pocket as a pawn when it is captured
+            let pieces = get_occupied(game) & !get_all(Piece::King);
+            let minor_pieces = (get_all(Piece::Knight) | get_all(Piece::Bishop)) & !game.promoted;
+            let pocket_pieces: u32 = game.pockets.iter().flatten().map(|&n| n as u32).sum();
+            let pocket_minor_pieces: u32 = game
+                .pockets
+                .iter()
+                .map(|pocket| (pocket[1] + pocket[2]) as u32)
+                .sum();
+            return pieces.count_ones() + pocket_pieces <= 1
+                && pieces & !minor_pieces == EMPTY_BITBOARD
+                && pocket_pieces == pocket_minor_pieces;
+        }
+    }
+
+    if team_pieces & (get_all(Piece::Pawn) | get_all(Piece::Rook) | get_all(Piece::Queen))
+        != EMPTY_BITBOARD
+    {
+        return false;
+    }
+
+    if team_pieces & get_all(Piece::Knight) != EMPTY_BITBOARD {
+        let blockers = other_pieces & !get_all(Piece::King) & !get_all(Piece::Queen);
+        return team_pieces.count_ones() <= 2 && blockers == EMPTY_BITBOARD;
+    }
+
+    let mut bishops = get_all(Piece::Bishop);
+    if team_pieces & bishops != EMPTY_BITBOARD {
+        let mut square_colors = [false; 2];
+        while bishops != EMPTY_BITBOARD {
+            let square = pop_square(&mut bishops);
+            square_colors[(square / BOARD_SIZE + square % BOARD_SIZE) % 2] = true;
+        }
+        // a rook or queen of the other player can block the escape square of its own king
+        let blockers = get_all(Piece::Pawn)
+            | get_all(Piece::Knight)
+            | (other_pieces & (get_all(Piece::Rook) | get_all(Piece::Queen)));
+        return !(square_colors[0] && square_colors[1]) && blockers == EMPTY_BITBOARD;
+    }
+
+    true
+}
+
+/**
+In Variant::Atomic the other king can be exploded by its own pieces, so only a bare king can be safe.
+A lone king, a single minor piece or rook or two knights can not explode a bare king,
+neither can bishops that are on other square colors than the bishops of the other player
+*/
+fn has_atomic_insufficient_material(game: &Game, is_white: bool) -> bool {
+    let team_pieces = game.color_bitboards[get_color_index(is_white)];
+    let other_pieces = game.color_bitboards[get_color_index(!is_white)];
+    let get_all = |piece: Piece| get_pieces(game, piece, true) | get_pieces(game, piece, false);
+    let kings = get_all(Piece::King);
+
+    if team_pieces & !kings == EMPTY_BITBOARD {
+        return true;
+    }
+
+    if other_pieces & !kings != EMPTY_BITBOARD {
+        if get_occupied(game) != get_all(Piece::Bishop) | kings {
+            return false;
+        }
+        return has_bishops_on_other_colors(game);
+    }
+
+    // a queen or a pawn that can become one can explode a bare king
+    if get_all(Piece::Queen) | get_all(Piece::Pawn) != EMPTY_BITBOARD {
+        return false;
+    }
+    let knights = get_all(Piece::Knight);
+    let pieces = knights | get_all(Piece::Bishop) | get_all(Piece::Rook);
+    pieces.count_ones() == 1 || (pieces == knights && knights.count_ones() <= 2)
+}
+
+/** True if the bishops of one player are all on light squares and the bishops of the other player on dark squares */
+fn has_bishops_on_other_colors(game: &Game) -> bool {
+    let light_squares: Bitboard = 0xAA55_AA55_AA55_AA55;
+    let bishops = get_pieces(game, Piece::Bishop, true) | get_pieces(game, Piece::Bishop, false);
+    let white_bishops = bishops & game.color_bitboards[0];
+    let black_bishops = bishops & game.color_bitboards[1];
+    (white_bishops & !light_squares == EMPTY_BITBOARD
+        && black_bishops & light_squares == EMPTY_BITBOARD)
+        || (white_bishops & light_squares == EMPTY_BITBOARD
+            && black_bishops & !light_squares == EMPTY_BITBOARD)
+}
+
+fn get_win(is_white: bool, reason: GameEndReason) -> GameResult {
+    GameResult {
+        outcome: if is_white {
+            WinStatus::WhiteWon
+        } else {
+            WinStatus::BlackWon
+        },
+        reason: Some(reason),
+    }
+}
+
+fn get_tie(reason: GameEndReason) -> GameResult {
+    GameResult {
+        outcome: WinStatus::Tie,
+        reason: Some(reason),
+    }
+}
+
+/**
+True if only kings and pawns are left and the pawns are locked, so only the kings can ever move.
+Every pawn has to be blocked by a pawn of the other player, no pawn can capture
+and no king can walk to a pawn of the other player that is not defended by another pawn
+*/
+fn is_locked_pawn_position(game: &Game) -> bool {
+    let pawns = [
+        get_pieces(game, Piece::Pawn, true),
+        get_pieces(game, Piece::Pawn, false),
+    ];
+    let kings = [
+        get_pieces(game, Piece::King, true),
+        get_pieces(game, Piece::King, false),
+    ];
+    let all_pawns = pawns[0] | pawns[1];
+    if all_pawns == EMPTY_BITBOARD || get_occupied(game) != all_pawns | kings[0] | kings[1] {
         return false;
     }
 
-    let is_white = start_piece.is_white;
-    let mut half_move_clock = game.half_move_clock + 1;
-    let mut en_passant_position: Option<Position> = None;
-
-    let capture_piece = game.board[move_end.x][move_end.y];
-    // reset on any capture
-    if capture_piece.piece != Piece::None || start_piece.piece == Piece::Pawn {
-        half_move_clock = 0;
+    let mut pawn_attacks = [EMPTY_BITBOARD; 2];
+    for is_white in [true, false] {
+        let index = get_color_index(is_white);
+        let mut team_pawns = pawns[index];
+        while team_pawns != EMPTY_BITBOARD {
+            let square = pop_square(&mut team_pawns);
+            // a pawn waiting to be promoted is not locked
+            if square / BOARD_SIZE == get_promotion_y(is_white) {
+                return false;
+            }
+            let forward = if is_white {
+                square - BOARD_SIZE
+            } else {
+                square + BOARD_SIZE
+            };
+            if pawns[1 - index] & get_square_bitboard(forward) == EMPTY_BITBOARD {
+                return false;
+            }
+            pawn_attacks[index] |= get_pawn_attacks(square, is_white);
+        }
     }
 
-    if start_piece.piece != Piece::King && start_piece.piece != Piece::Pawn {
-        game.board[move_end.x][move_end.y] = start_piece;
-        game.board[move_start.x][move_start.y] = EMPTY_PEICE;
-    } else if start_piece.piece == Piece::Pawn {
-        let move_direction: i8 = if is_white { -1 } else { 1 };
+    // no pawn can capture, not even en passant, and no king is in check
+    if let Some(en_passant_position) = game.en_passant_position {
+        let en_passant = get_square_bitboard(get_square_index(&en_passant_position));
+        if pawn_attacks[get_color_index(game.is_white_to_move)] & en_passant != EMPTY_BITBOARD {
+            return false;
+        }
+    }
+    for index in 0..2 {
+        if pawn_attacks[index] & (pawns[1 - index] | kings[1 - index]) != EMPTY_BITBOARD {
+            return false;
+        }
+    }
 
-        // en passant
-        if game.board[move_end.x][move_end.y].piece == Piece::None && (move_end.x != move_start.x) {
-            let real_capture_unchecked = get_position(
-                &move_end,
-                &Vector2 {
-                    x: 0,
-                    y: -move_direction,
-                },
-            );
+    // every square the king can reach without walking into an attack from a pawn
+    for index in 0..2 {
+        let blocked = pawns[index] | pawn_attacks[1 - index];
+        let mut reached = kings[index];
+        loop {
+            let mut next = reached;
+            let mut squares = reached;
+            while squares != EMPTY_BITBOARD {
+                next |= get_king_attacks(pop_square(&mut squares));
+            }
+            next &= !blocked;
 
-            // captures the real pawn and not just air
-            if real_capture_unchecked.is_some() {
-                let real_capture = real_capture_unchecked.unwrap();
-                let real_capture_piece_data = game.board[real_capture.x][real_capture.y];
-                if real_capture_piece_data.is_white != is_white
-                    && real_capture_piece_data.piece == Piece::Pawn
-                {
-                    game.board[real_capture.x][real_capture.y] = EMPTY_PEICE;
-                }
+            if next & pawns[1 - index] != EMPTY_BITBOARD {
+                return false;
             }
-        } else if move_end.y as i8 - move_start.y as i8 == move_direction * 2 {
-            // if move twice, it means that it can be en passanted
-            en_passant_position = get_position(
-                &move_start,
-                &Vector2 {
-                    x: 0,
-                    y: move_direction,
-                },
-            )
+            if next == reached {
+                break;
+            }
+            reached = next;
         }
+    }
 
-        game.board[move_end.x][move_end.y] = start_piece;
-        game.board[move_start.x][move_start.y] = EMPTY_PEICE;
-    } else if start_piece.piece == Piece::King {
-        let offset_x = move_end.x as i8 - move_start.x as i8;
-        let move_distance = i8::abs(offset_x); //i8::abs(move_start.y as i8 - move_end.y as i8) + i8::abs(offset_x);
+    true
+}
 
-        let player_index = if is_white { 0 } else { 1 };
-        let mut castle_state = game.castle[player_index];
-        castle_state.can_castle_king_side = false;
-        castle_state.can_castle_queen_side = false;
-        game.castle[player_index] = castle_state;
+/**
+True if no series of legal moves can end in checkmate, then the game is a draw.
+That is when neither player has enough material to checkmate, like bishops that are all on one color,
+or when only the kings can move because the pawns are locked
+*/
+pub fn is_dead_position(game: &Game) -> bool {
+    // a king that can not reach the other pawns might still reach the center or the eighth rank
+    if game.variant == Variant::KingOfTheHill || game.variant == Variant::RacingKings {
+        return false;
+    }
+    // a piece in a pocket can be dropped to break up the pawns
+    // and in Variant::Antichess the kings can walk into the pawns to be captured
+    (has_insufficient_material(game, true) && has_insufficient_material(game, false))
+        || (is_locked_pawn_position(game)
+            && game.pockets == [[0; 5]; 2]
+            && game.variant != Variant::Antichess)
+}
 
-        if move_distance == 1 || move_distance == 0 {
-            // if only move 1 square then it is a normal move
-            game.board[move_end.x][move_end.y] = start_piece;
-            game.board[move_start.x][move_start.y] = EMPTY_PEICE;
-        } else if move_distance == 2 {
-            // if moves 2 squares it means that it is doing castling
-            // rook position = king end move -movedirection x
-            let new_rook_position = match get_position(
-                &move_end,
-                &Vector2 {
-                    x: -i8::signum(offset_x),
-                    y: 0,
-                },
-            ) {
-                Some(pos) => pos,
-                None => return false,
-            };
+/** 50 moves by each player, after that a draw can be claimed */
+pub const FIFTY_MOVE_RULE_HALF_MOVES: u16 = 100;
+/** 75 moves by each player, after that the game is a draw without a claim */
+pub const SEVENTY_FIVE_MOVE_RULE_HALF_MOVES: u16 = 150;
 
-            let is_king_side = offset_x > 1;
+/** True if the player to move can claim a draw because there has been no capture or pawn move in the last 50 moves */
+pub fn can_claim_fifty_move_draw(game_board: &Gameboard) -> bool {
+    game_board.game.half_move_clock >= FIFTY_MOVE_RULE_HALF_MOVES
+}
 
-            let caste = &game.castle[if is_white { 0 } else { 1 }];
+/**
+Ends the game as a draw by the fifty-move rule, it is up to the players to claim it
+as the game only ends by itself after 75 moves. Returns ChessError::NoDrawToClaim if the 50 moves has not been played
+*/
+pub fn claim_fifty_move_draw(game_board: &mut Gameboard) -> Result<(), ChessError> {
+    if game_board.result.is_some() || !can_claim_fifty_move_draw(game_board) {
+        return Err(ChessError::NoDrawToClaim);
+    }
+    game_board.result = Some(get_tie(GameEndReason::FiftyMoveRule));
+    Ok(())
+}
 
-            let rook_position = if is_king_side {
-                caste.king_side_rook
-            } else {
-                caste.queen_side_rook
-            };
+/** The draw the player to move can claim, threefold repetition of the current position or the fifty-move rule */
+pub fn get_claimable_draw(game_board: &Gameboard) -> Option<GameEndReason> {
+    let repetitions = game_board
+        .same_board
+        .get(&get_repetition_key(&game_board.game))
+        .copied()
+        .unwrap_or(0);
+    if repetitions >= 3 {
+        Some(GameEndReason::ThreefoldRepetition)
+    } else if can_claim_fifty_move_draw(game_board) {
+        Some(GameEndReason::FiftyMoveRule)
+    } else {
+        None
+    }
+}
 
-            let rook_data = game.board[rook_position.x][rook_position.y];
-            // moves rook
-            game.board[new_rook_position.x][new_rook_position.y] = rook_data;
+/** Ends the game as a draw if the player to move can claim one, returns the reason of the draw */
+pub fn claim_draw(game_board: &mut Gameboard) -> Result<GameEndReason, ChessError> {
+    if game_board.result.is_some() {
+        return Err(ChessError::GameOver);
+    }
+    let reason = get_claimable_draw(game_board).ok_or(ChessError::NoDrawToClaim)?;
+    game_board.result = Some(get_tie(reason));
+    game_board.draw_offer = None;
+    Ok(reason)
+}
 
-            // moves king
-            game.board[move_end.x][move_end.y] = game.board[move_start.x][move_start.y];
+/**
+Claims a draw with a move that creates the threefold repetition or the 50th move, the move is played and the game ends.
+Nothing is changed if the move is illegal or if there is no draw to claim after it
+*/
+pub fn claim_draw_with_move(
+    game_board: &mut Gameboard,
+    game_move: Move,
+) -> Result<GameEndReason, ChessError> {
+    if game_board.result.is_some() {
+        return Err(ChessError::GameOver);
+    }
+    if !legal_moves(game_board).contains(&game_move) {
+        let game = &game_board.game;
+        let reason = if is_drop(&game_move) {
+            get_illegal_drop_reason(game, game_move.piece.piece, &game_move.to)
+        } else {
+            let threats = get_threats(game_board);
+            get_illegal_move_reason(game, &threats, &game_move.from, &game_move.to)
+        };
+        return Err(ChessError::IllegalMove(reason));
+    }
 
-            // clears old
-            game.board[rook_position.x][rook_position.y] = EMPTY_PEICE;
-            game.board[move_start.x][move_start.y] = EMPTY_PEICE;
-        }
+    let mut next_game_board = game_board.clone();
+    if !play_move(&mut next_game_board, game_move) {
+        return Err(ChessError::IllegalMove(IllegalMoveReason::NoPiece));
     }
+    next_game_board.redo_history.clear();
+    let reason = claim_draw(&mut next_game_board)?;
+    *game_board = next_game_board;
+    Ok(reason)
+}
 
-    let full_move_clock = if is_white {
-        game.full_move_clock
-    } else {
-        game.full_move_clock + 1
-    };
+/** The player gives up and the other player wins, fails if the game is already over */
+pub fn resign(game_board: &mut Gameboard, is_white: bool) -> Result<(), ChessError> {
+    let threats = get_threats(game_board);
+    if get_game_state(game_board, &threats, false).outcome != WinStatus::Nothing {
+        return Err(ChessError::GameOver);
+    }
+    game_board.result = Some(get_win(!is_white, GameEndReason::Resignation));
+    game_board.draw_offer = None;
+    Ok(())
+}
 
-    if start_piece.piece == Piece::Rook {
-        remove_castle(game, move_start);
+/**
+Offers the other player a draw, it can be accepted until they play a move.
+If the other player has already offered a draw then the offer is accepted
+*/
+pub fn offer_draw(game_board: &mut Gameboard, is_white: bool) -> Result<(), ChessError> {
+    if game_board.result.is_some() {
+        return Err(ChessError::GameOver);
     }
+    if game_board.draw_offer == Some(!is_white) {
+        return accept_draw(game_board, is_white);
+    }
+    game_board.draw_offer = Some(is_white);
+    Ok(())
+}
 
-    if capture_piece.piece == Piece::Rook {
-        remove_castle(game, move_end);
+/** Accepts the draw offered by the other player and ends the game as a draw by agreement */
+pub fn accept_draw(game_board: &mut Gameboard, is_white: bool) -> Result<(), ChessError> {
+    if game_board.result.is_some() {
+        return Err(ChessError::GameOver);
+    }
+    if game_board.draw_offer != Some(!is_white) {
+        return Err(ChessError::NoDrawOffer);
     }
+    game_board.draw_offer = None;
+    game_board.result = Some(get_tie(GameEndReason::Agreement));
+    Ok(())
+}
 
-    game.half_move_clock = half_move_clock;
-    game.full_move_clock = full_move_clock;
-    game.is_white_to_move = !is_white;
-    game.en_passant_position = en_passant_position;
-    return true;
+/** Declines the draw offered by the other player, playing a move also declines it */
+pub fn decline_draw(game_board: &mut Gameboard, is_white: bool) -> Result<(), ChessError> {
+    if game_board.draw_offer != Some(!is_white) {
+        return Err(ChessError::NoDrawOffer);
+    }
+    game_board.draw_offer = None;
+    Ok(())
 }
 
+/**
+Returns how the game ended and why, or GAME_ONGOING if it is still being played.
+A result set on the game board like a resignation is returned first.
+force_claimable_tie ends the game on threefold repetition and the fifty-move rule as if a player had claimed the draw,
+else only fivefold repetition and the seventy-five-move rule ends it
+*/
 pub fn get_game_state(
     game_board: &Gameboard,
     other_team_threat_map: &ThreatMap,
-    force_3_fold_tie: bool,
-) -> WinStatus {
+    force_claimable_tie: bool,
+) -> GameResult {
+    if let Some(result) = game_board.result {
+        return result;
+    }
+
+    if let Some(result) = get_variant_result(&game_board.game) {
+        return result;
+    }
+
     // first checks that both players has kings
+    // in Variant::Antichess the king is an ordinary piece and in Variant::Horde white has no king
     let mut has_white_king = false;
     let mut has_black_king = false;
     for y in 0..BOARD_SIZE {
@@ -805,47 +1678,39 @@ pub fn get_game_state(
         }
     }
 
-    if has_black_king && !has_white_king {
-        return WinStatus::BlackWon;
-    } else if !has_black_king && has_white_king {
-        return WinStatus::WhiteWon;
-    } else if !has_black_king && !has_white_king {
-        return WinStatus::Tie;
-    }
-
-    let mut can_move_anything = false;
-    for y in 0..BOARD_SIZE {
-        // fuck goto
-        if can_move_anything {
-            break;
-        }
-        for x in 0..BOARD_SIZE {
-            let piece_data = game_board.game.board[x][y];
-            if piece_data.is_white == game_board.game.is_white_to_move {
-                let moves = generate_valid_moves(
-                    &game_board.game,
-                    other_team_threat_map,
-                    &Position { x, y },
-                );
-                if moves.len() > 0 {
-                    can_move_anything = true;
-                    break;
-                }
-            }
+    let is_antichess = game_board.game.variant == Variant::Antichess;
+    if !is_antichess && game_board.game.variant != Variant::Horde {
+        if has_black_king && !has_white_king {
+            return get_win(false, GameEndReason::KingCaptured);
+        } else if !has_black_king && has_white_king {
+            return get_win(true, GameEndReason::KingCaptured);
+        } else if !has_black_king && !has_white_king {
+            return get_tie(GameEndReason::KingCaptured);
         }
     }
 
+    let can_move_anything =
+        !generate_legal_moves_with_threats(&game_board.game, other_team_threat_map).is_empty();
+
     if !can_move_anything {
-        // if cant move anything and king is threatend
-        if other_team_threat_map.all_king_threats.len() > 0 {
-            return if game_board.game.is_white_to_move {
-                WinStatus::BlackWon
+        // the player that can not move wins in Variant::Antichess, either all pieces are lost or it is a stalemate
+        if is_antichess {
+            let is_white_to_move = game_board.game.is_white_to_move;
+            let team_pieces = game_board.game.color_bitboards[get_color_index(is_white_to_move)];
+            let reason = if team_pieces == EMPTY_BITBOARD {
+                GameEndReason::AllPiecesLost
             } else {
-                WinStatus::WhiteWon
+                GameEndReason::Stalemate
             };
+            return get_win(is_white_to_move, reason);
+        }
+
+        // if cant move anything and king is threatend
+        if is_in_check(&game_board.game, other_team_threat_map) {
+            return get_win(!game_board.game.is_white_to_move, GameEndReason::Checkmate);
         } else {
             // if cant move anything and king is not threatend
-            return WinStatus::Tie;
+            return get_tie(GameEndReason::Stalemate);
         }
     }
 
@@ -853,8 +1718,12 @@ pub fn get_game_state(
     // and the side to move has at least one legal move, a draw score should be assigned to that node,
     // with appropriate protocol handling and game state transitions, if the node is already the root and there is no mate in one.
     // https://www.chessprogramming.org/Fifty-move_Rule
-    if game_board.game.half_move_clock >= 100 {
-        return WinStatus::Tie;
+    // like threefold repetition the fifty-move rule has to be claimed, after 75 moves it is a draw by itself
+    // checkmate on the last move is checked above and wins
+    if game_board.game.half_move_clock >= SEVENTY_FIVE_MOVE_RULE_HALF_MOVES {
+        return get_tie(GameEndReason::SeventyFiveMoveRule);
+    } else if force_claimable_tie && can_claim_fifty_move_draw(game_board) {
+        return get_tie(GameEndReason::FiftyMoveRule);
     }
 
     let mut max_repetitions = 0;
@@ -868,124 +1737,352 @@ pub fn get_game_state(
     // By contrast, the fivefold repetition rule requires the arbiter to
     // intervene and declare the game drawn if the same position occurs five times, and requires no claim by the players.
     // https://en.wikipedia.org/wiki/Threefold_repetition
-    if (max_repetitions >= 3 && force_3_fold_tie) || (max_repetitions >= 5) {
-        return WinStatus::Tie;
+    if max_repetitions >= 5 {
+        return get_tie(GameEndReason::FivefoldRepetition);
+    } else if max_repetitions >= 3 && force_claimable_tie {
+        return get_tie(GameEndReason::ThreefoldRepetition);
     }
 
     // https://www.chessprogramming.org/Draw_Evaluation
-    // fuck this
+    // https://en.wikipedia.org/wiki/Rules_of_chess#Dead_position
+    if has_insufficient_material(&game_board.game, true)
+        && has_insufficient_material(&game_board.game, false)
+    {
+        return get_tie(GameEndReason::InsufficientMaterial);
+    }
+    if is_dead_position(&game_board.game) {
+        return get_tie(GameEndReason::DeadPosition);
+    }
+
+    GAME_ONGOING
+}
+
+/**Used for debugging */
+pub(crate) fn move_piece_no_map(
+    game_board: &mut Gameboard,
+    move_start: Position,
+    move_end: Position,
+    auto_promote: bool,
+) -> Result<(), ChessError> {
+    let threatmap = generate_all_threats(&game_board.game, !game_board.game.is_white_to_move);
+    return move_piece(game_board, move_start, move_end, &threatmap, auto_promote);
+}
 
-    // if false if anyone has any Pawn Rook or Queen
-    let mut valid_draw = true;
+/**
+Creates a move from the current state of the game, promotion is Piece::None if there is none.
+A drop is created with the same from and to and the dropped piece as promotion, like the move generator gives it
+*/
+pub(crate) fn create_move(game: &Game, from: Position, to: Position, promotion: Piece) -> Move {
+    let (piece, promotion) = if from == to {
+        let piece = PieceData {
+            piece: promotion,
+            is_white: game.is_white_to_move,
+        };
+        (piece, Piece::None)
+    } else {
+        (game.board[from.x][from.y], promotion)
+    };
+    let mut captured = game.board[to.x][to.y];
 
-    let mut white_minor_pieces = 0u8;
-    let mut black_minor_pieces = 0u8;
+    // castling in Chess960 is the king taking its own rook
+    if captured.piece != Piece::None && captured.is_white == piece.is_white {
+        captured = EMPTY_PEICE;
+    }
 
-    let mut white_bishops: Vec<bool> = Vec::new();
-    let mut black_bishops: Vec<bool> = Vec::new();
+    // en passant captures the pawn next to the start square and not the end square
+    if piece.piece == Piece::Pawn && captured.piece == Piece::None && from.x != to.x {
+        captured = game.board[to.x][from.y];
+    }
 
-    for y in 0..BOARD_SIZE {
-        if !valid_draw {
-            break;
-        }
-        for x in 0..BOARD_SIZE {
-            let piece_data = game_board.game.board[x][y];
-            if piece_data.piece == Piece::Pawn
-                || piece_data.piece == Piece::Rook
-                || piece_data.piece == Piece::Queen
-            {
-                valid_draw = false;
+    let mut exploded = [EMPTY_PEICE; 8];
+    if game.variant == Variant::Atomic && captured.piece != Piece::None {
+        let mut squares = get_king_attacks(get_square_index(&to));
+        for piece_data in exploded.iter_mut() {
+            if squares == EMPTY_BITBOARD {
                 break;
-            } else if piece_data.piece == Piece::Knight || piece_data.piece == Piece::Bishop {
-                if piece_data.is_white {
-                    white_minor_pieces += 1;
-                } else {
-                    black_minor_pieces += 1;
-                }
+            }
+            let position = get_square_position(pop_square(&mut squares));
+            *piece_data = game.board[position.x][position.y];
+        }
+    }
 
-                if piece_data.piece == Piece::Bishop {
-                    let is_dark_square = (y + x) % 2 == 0;
-                    if piece_data.is_white {
-                        white_bishops.push(is_dark_square);
-                    } else {
-                        black_bishops.push(is_dark_square);
-                    }
+    Move {
+        from,
+        to,
+        promotion,
+        piece,
+        captured,
+        exploded,
+        castle: game.castle,
+        pockets: game.pockets,
+        promoted: game.promoted,
+        en_passant_position: game.en_passant_position,
+        half_move_clock: game.half_move_clock,
+        full_move_clock: game.full_move_clock,
+    }
+}
+
+/** Takes back a move done by move_piece_unsafe, the game must be in the state directly after the move */
+pub(crate) fn unmake_move(game: &mut Game, game_move: &Move) {
+    let from = game_move.from;
+    let to = game_move.to;
+    let is_white = game_move.piece.is_white;
+    // the check the move gave was counted when it was played
+    let gave_check = game.variant == Variant::ThreeCheck && is_king_checked(game, !is_white);
+    game.hash ^= get_state_key(game);
+
+    if is_drop(game_move) {
+        // the piece goes back to the pocket with the rest of the state
+        set_square(game, to, EMPTY_PEICE);
+    } else if let Some(rook_position) =
+        get_castle_rook(&game_move.castle, game_move.piece, from, to)
+    {
+        let (king_end, rook_end) = get_castle_ends(from, rook_position);
+        let rook_data = game.board[rook_end.x][rook_end.y];
+        set_square(game, king_end, EMPTY_PEICE);
+        set_square(game, rook_end, EMPTY_PEICE);
+
+        set_square(game, from, game_move.piece);
+        set_square(game, rook_position, rook_data);
+    } else {
+        // a promoted piece turns back into the pawn
+        set_square(game, to, EMPTY_PEICE);
+        set_square(game, from, game_move.piece);
+
+        if game_move.piece.piece == Piece::Pawn
+            && from.x != to.x
+            && game_move.en_passant_position == Some(to)
+        {
+            // en passant
+            set_square(game, Position { x: to.x, y: from.y }, game_move.captured);
+        } else {
+            set_square(game, to, game_move.captured);
+        }
+
+        // the pieces that was next to the capture are put back, the pawns never left
+        if game.variant == Variant::Atomic && game_move.captured.piece != Piece::None {
+            let mut squares = get_king_attacks(get_square_index(&to));
+            for piece_data in game_move.exploded {
+                if squares == EMPTY_BITBOARD {
+                    break;
                 }
+                set_square(
+                    game,
+                    get_square_position(pop_square(&mut squares)),
+                    piece_data,
+                );
             }
         }
     }
 
-    if valid_draw {
-        //One Side has a King and a Minor Piece against a bare King
-        if (black_minor_pieces <= 1 && white_minor_pieces == 0)
-            || (black_minor_pieces == 0 && white_minor_pieces <= 1)
-        {
-            return WinStatus::Tie;
+    game.castle = game_move.castle;
+    game.pockets = game_move.pockets;
+    game.promoted = game_move.promoted;
+    game.en_passant_position = game_move.en_passant_position;
+    game.half_move_clock = game_move.half_move_clock;
+    game.full_move_clock = game_move.full_move_clock;
+    game.is_white_to_move = is_white;
+    if gave_check {
+        game.checks[get_color_index(is_white)] -= 1;
+    }
+    game.hash ^= get_state_key(game);
+}
+
+/** True if the player to move has a legal en passant capture */
+fn can_capture_en_passant(game: &Game) -> bool {
+    let en_passant_position = match game.en_passant_position {
+        Some(position) => position,
+        None => return false,
+    };
+
+    // a pawn that can take stands where a pawn of the other player on the en passant square would attack
+    let square = get_square_index(&en_passant_position);
+    let pawns = get_pieces(game, Piece::Pawn, game.is_white_to_move);
+    if get_pawn_attacks(square, !game.is_white_to_move) & pawns == EMPTY_BITBOARD {
+        return false;
+    }
+
+    generate_legal_moves(game)
+        .iter()
+        .any(|m| m.piece.piece == Piece::Pawn && m.to == en_passant_position)
+}
+
+/**
+The key used to count repetitions, the Zobrist hash without the en passant file when no pawn can take en passant.
+Two positions are the same if they have the same pieces, player to move, castle rights and en passant captures
+*/
+pub fn get_repetition_key(game: &Game) -> u64 {
+    match game.en_passant_position {
+        Some(position) if !can_capture_en_passant(game) => {
+            game.hash ^ get_en_passant_key(position.x)
         }
+        _ => game.hash,
+    }
+}
 
-        //Both Sides have a King and a Bishop, the Bishops being the same Color
-        if black_minor_pieces == 1
-            && white_minor_pieces == 1
-            && white_bishops.len() == 1
-            && black_bishops.len() == 1
-            && (white_bishops[0] == black_bishops[0])
-        {
-            return WinStatus::Tie;
+fn add_repetition(game_board: &mut Gameboard) {
+    //adds 1 if found, else sets it to 1
+    *game_board
+        .same_board
+        .entry(get_repetition_key(&game_board.game))
+        .or_insert(0) += 1u8;
+}
+
+fn remove_repetition(game_board: &mut Gameboard) {
+    let hash = get_repetition_key(&game_board.game);
+    if let Some(repetitions) = game_board.same_board.get_mut(&hash) {
+        *repetitions -= 1;
+        if *repetitions == 0 {
+            game_board.same_board.remove(&hash);
         }
     }
+}
+
+/** Same as move_piece_unsafe but also promotes the pawn if the move is a promotion and plays drops */
+pub(crate) fn make_move(game: &mut Game, game_move: &Move) -> bool {
+    if is_drop(game_move) {
+        return drop_piece_unsafe(game, game_move.piece, game_move.to);
+    }
+
+    if !move_piece_unsafe(game, game_move.from, game_move.to) {
+        return false;
+    }
 
-    return WinStatus::Nothing;
+    // in Variant::Atomic a pawn that captures explodes before it is promoted
+    if game_move.promotion != Piece::None && get_promotion_pawn(game).is_some() {
+        return promote_game_pawn(game, game_move.promotion).is_ok();
+    }
+    true
 }
 
-/**Used for debugging */
-pub(crate) fn move_piece_no_map(
-    game_board: &mut Gameboard,
-    move_start: Position,
-    move_end: Position,
-    auto_promote: bool,
-) -> bool {
-    let threatmap = generate_all_threats(&game_board.game, !game_board.game.is_white_to_move);
-    return move_piece(game_board, move_start, move_end, &threatmap, auto_promote);
+/** Plays an already validated move and adds it to the history */
+pub(crate) fn play_move(game_board: &mut Gameboard, game_move: Move) -> bool {
+    let is_white = game_board.game.is_white_to_move;
+    if !make_move(&mut game_board.game, &game_move) {
+        return false;
+    }
+
+    // moving instead of answering declines the draw offer
+    if game_board.draw_offer == Some(!is_white) {
+        game_board.draw_offer = None;
+    }
+
+    game_board.history.push(game_move);
+
+    // if the promotion is pending then the position is counted by promote_pawn
+    if get_promotion_pawn(&game_board.game).is_none() {
+        add_repetition(game_board);
+    }
+
+    true
 }
 
+/** Plays the move if it is legal, the error tells why the move was refused */
 pub fn move_piece(
     game_board: &mut Gameboard,
     move_start: Position,
     move_end: Position,
     other_team_threat_map: &ThreatMap,
     auto_promote: bool,
-) -> bool {
-    let game = &mut game_board.game;
+) -> Result<(), ChessError> {
+    let game = &game_board.game;
 
     if get_promotion_pawn(game).is_some() {
-        return false;
+        return Err(ChessError::IllegalMove(IllegalMoveReason::PromotionPending));
     }
 
-    // basic check first
-    if !is_valid_move(game, &move_start, &move_end) {
-        return false;
+    // the game also ends without a checkmate, like by a resignation or by the 75-move rule
+    if get_game_state(game_board, other_team_threat_map, false).outcome != WinStatus::Nothing {
+        return Err(ChessError::GameOver);
     }
 
+    // castling can be given as the king taking its own rook
+    let move_end = get_castle_move_end(game, move_start, move_end);
+
+    // basic check first
+    is_valid_move(game, &move_start, &move_end)?;
+
     if !generate_valid_moves(game, other_team_threat_map, &move_start).contains(&move_end) {
-        return false;
+        let reason = get_illegal_move_reason(game, other_team_threat_map, &move_start, &move_end);
+        return Err(ChessError::IllegalMove(reason));
     }
 
     let start_piece = game.board[move_start.x][move_start.y];
+    let is_last_rank = move_end.y == BLACK_SPAWN || move_end.y == WHITE_SPAWN;
+    let promotion = if start_piece.piece == Piece::Pawn && is_last_rank && auto_promote {
+        Piece::Queen
+    } else {
+        Piece::None
+    };
 
-    if move_piece_unsafe(game, move_start, move_end) {
-        if start_piece.piece == Piece::Pawn && auto_promote {
-            promote_pawn(game, Piece::Queen);
-        }
+    let game_move = create_move(game, move_start, move_end, promotion);
+    if !play_move(game_board, game_move) {
+        return Err(ChessError::IllegalMove(IllegalMoveReason::NoPiece));
+    }
+    game_board.redo_history.clear();
+    Ok(())
+}
 
-        let fen = get_board_fen(game);
-        //this should always work
-        if fen.is_some() {
-            //adds 1 if found, else sets it to 1
-            *game_board.same_board.entry(fen.unwrap()).or_insert(0) += 1u8;
-        }
+/** Drops a piece from the pocket of the player to move in Variant::Crazyhouse, the error tells why the drop was refused */
+pub fn drop_piece(
+    game_board: &mut Gameboard,
+    piece: Piece,
+    position: Position,
+    other_team_threat_map: &ThreatMap,
+) -> Result<(), ChessError> {
+    if get_promotion_pawn(&game_board.game).is_some() {
+        return Err(ChessError::IllegalMove(IllegalMoveReason::PromotionPending));
+    }
 
-        return true;
-    } else {
-        return false;
+    if get_game_state(game_board, other_team_threat_map, false).outcome != WinStatus::Nothing {
+        return Err(ChessError::GameOver);
+    }
+
+    if !get_valid_drops(game_board, other_team_threat_map, piece).contains(&position) {
+        let reason = get_illegal_drop_reason(&game_board.game, piece, &position);
+        return Err(ChessError::IllegalMove(reason));
+    }
+
+    let game_move = create_move(&game_board.game, position, position, piece);
+    if !play_move(game_board, game_move) {
+        return Err(ChessError::IllegalMove(IllegalMoveReason::NotInPocket));
+    }
+    game_board.redo_history.clear();
+    Ok(())
+}
+
+/** Takes back the last move, fails if there is no move to take back or the game has been ended by a player */
+pub fn undo_move(game_board: &mut Gameboard) -> Result<(), ChessError> {
+    if game_board.result.is_some() {
+        return Err(ChessError::GameOver);
+    }
+    let game_move = match game_board.history.pop() {
+        Some(m) => m,
+        None => return Err(ChessError::NothingToUndo),
+    };
+
+    // the position after the move is no longer reached, a pending promotion was never counted
+    if get_promotion_pawn(&game_board.game).is_none() {
+        remove_repetition(game_board);
+    }
+
+    unmake_move(&mut game_board.game, &game_move);
+    game_board.redo_history.push(game_move);
+    Ok(())
+}
+
+/** Plays the last move taken back by undo_move again, fails if there is no move to redo or the game has been ended by a player */
+pub fn redo_move(game_board: &mut Gameboard) -> Result<(), ChessError> {
+    if game_board.result.is_some() {
+        return Err(ChessError::GameOver);
+    }
+    let game_move = match game_board.redo_history.pop() {
+        Some(m) => m,
+        None => return Err(ChessError::NothingToRedo),
+    };
+
+    if !play_move(game_board, game_move) {
+        return Err(ChessError::IllegalMove(IllegalMoveReason::NoPiece));
     }
+    Ok(())
 }
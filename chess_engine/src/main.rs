@@ -1,28 +1,60 @@
 use chess_engine::game_data::*;
 use chess_engine::logic::*;
 use chess_engine::parser::*;
+use chess_engine::perft::*;
 use chess_engine::render::*;
+use chess_engine::uci::run_uci;
+use std::io::{stdin, stdout, BufReader, Read};
+use std::sync::{Arc, Mutex};
 
 fn main() {
     //chcp 65001
 
+    // perft [DEPTH] [VARIANT] [FEN], prints the number of positions after every first move
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() >= 3 && args[1] == "perft" {
+        let depth = args[2].parse::<u32>().expect("invalid depth");
+        let mut fen_start = 3;
+        let variant = match args.get(3).map(|input| parse_variant(input)) {
+            Some(Ok(variant)) => {
+                fen_start += 1;
+                variant
+            }
+            _ => Variant::Standard,
+        };
+        let fen = if args.len() > fen_start {
+            args[fen_start..].join(" ")
+        } else {
+            get_start_fen(variant)
+        };
+        let game = get_variant_board(fen, variant).expect("invalid FEN");
+        print_perft_divide(&game, depth);
+        return;
+    }
+
+    // uci, used by chess GUIs and tournament managers
+    if args.len() >= 2 && args[1] == "uci" {
+        run_uci(BufReader::new(stdin()), Arc::new(Mutex::new(stdout())));
+        return;
+    }
+
     let mut game_board = init_game_board(STANDARD_BOARD.to_string()).unwrap();
     loop {
         render(&game_board.game);
 
         let threats = get_threats(&game_board);
-        let win_status = get_game_state(&game_board, &threats, true);
-        match win_status {
+        let result = get_game_state(&game_board, &threats, true);
+        match result.outcome {
             WinStatus::BlackWon => {
-                println!("Black won!");
+                println!("Black won by {}!", result.reason.unwrap());
                 break;
             }
             WinStatus::WhiteWon => {
-                println!("White won!");
+                println!("White won by {}!", result.reason.unwrap());
                 break;
             }
             WinStatus::Tie => {
-                println!("Tie!");
+                println!("Tie by {}!", result.reason.unwrap());
                 break;
             }
             WinStatus::Nothing => {
@@ -33,20 +65,29 @@ fn main() {
         loop {
             // for GUI you can use get_all_valid_moves(&game_board, &threats, piece_position) for a preview
             // you can directily use Position for GUI
-            let mut player_move = None;
-            while player_move.is_none() {
-                player_move = parse_move(&read_input());
-                if player_move.is_none() {
-                    println!("Invalid input")
-                }
+            let input = read_input();
+
+            // a GUI that starts the engine without arguments sends uci first
+            if input == "uci" {
+                run_uci(
+                    BufReader::new("uci\n".as_bytes().chain(stdin())),
+                    Arc::new(Mutex::new(stdout())),
+                );
+                return;
             }
-            let (move_start, move_end) = player_move.unwrap();
+
+            let (move_start, move_end) = match parse_move(&input) {
+                Ok(player_move) => player_move,
+                Err(error) => {
+                    println!("{}", error);
+                    continue;
+                }
+            };
 
             // use promote_pawn if auto_promote is set to false
-            if move_piece(&mut game_board, move_start, move_end, &threats, true) {
-                break;
-            } else {
-                println!("Invalid move")
+            match move_piece(&mut game_board, move_start, move_end, &threats, true) {
+                Ok(()) => break,
+                Err(error) => println!("{}", error),
             }
         }
     }
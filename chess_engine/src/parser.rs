@@ -1,28 +1,87 @@
 use std::collections::HashMap;
 
+use crate::bitboard::{
+    get_attackers, get_color_index, get_occupied, get_piece_index, get_pieces, get_square_bitboard,
+    get_square_index, update_bitboards, Bitboard, EMPTY_BITBOARD,
+};
 use crate::game_data::*;
+use crate::logic::{
+    generate_all_threats, generate_legal_moves, generate_legal_moves_with_threats,
+    get_castle_move_end, get_castle_rook, get_promotion_pieces, get_repetition_key, is_drop,
+    is_in_check, is_king_checked, make_move, play_move, THREE_CHECK_WIN,
+};
 pub const STANDARD_BOARD: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+pub const HORDE_BOARD: &str =
+    "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1";
+pub const RACING_KINGS_BOARD: &str = "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1";
 
-pub fn init_game_board(fen: String) -> Option<Gameboard> {
-    let game = match get_board(fen) {
-        Some(g) => g,
-        None => return None,
-    };
+pub fn init_game_board(fen: String) -> Result<Gameboard, ChessError> {
+    init_variant_game_board(fen, Variant::Standard)
+}
+
+/** Creates a game board that is played with the rules of the variant, the FEN is read as a FEN of the variant */
+pub fn init_variant_game_board(fen: String, variant: Variant) -> Result<Gameboard, ChessError> {
+    let game = get_variant_board(fen, variant)?;
 
     let mut map = HashMap::new();
     // inserts the standard board
-    map.insert(
-        match get_board_fen(&game) {
-            Some(g) => g,
-            None => return None,
-        },
-        1,
-    );
+    map.insert(get_repetition_key(&game), 1);
 
-    return Some(Gameboard {
+    Ok(Gameboard {
         game,
         same_board: map,
-    });
+        history: Vec::new(),
+        redo_history: Vec::new(),
+        result: None,
+        draw_offer: None,
+    })
+}
+
+/**
+Loads the game from the FEN of the variant and plays the moves in the e7e8q format, used to restore a game from a move list.
+The positions before the moves are counted for threefold repetition
+*/
+pub fn init_game_board_with_moves(
+    fen: String,
+    variant: Variant,
+    moves: &[&str],
+) -> Result<Gameboard, ChessError> {
+    let mut game_board = init_variant_game_board(fen, variant)?;
+    for input in moves {
+        play_long_move(&mut game_board, input)?;
+    }
+    Ok(game_board)
+}
+
+/** Plays the move in the e7e8q format and adds it to the history, fails if the move is not legal */
+pub(crate) fn play_long_move(game_board: &mut Gameboard, input: &str) -> Result<(), ChessError> {
+    let game_move = parse_long_move(&game_board.game, input)?;
+    if !play_move(game_board, game_move) {
+        return Err(ChessError::InvalidInput(format!(
+            "'{}' is not a legal move",
+            input
+        )));
+    }
+    Ok(())
+}
+
+/**
+Counts positions that was reached before the game was loaded for threefold repetition,
+used when a game is restored from a FEN in the middle of the game and the earlier positions are known
+*/
+pub fn seed_repetition_history(
+    game_board: &mut Gameboard,
+    fens: &[&str],
+) -> Result<(), ChessError> {
+    let mut keys = Vec::with_capacity(fens.len());
+    for fen in fens {
+        let game = get_variant_board(fen.to_string(), game_board.game.variant)?;
+        keys.push(get_repetition_key(&game));
+    }
+    for key in keys {
+        *game_board.same_board.entry(key).or_insert(0) += 1;
+    }
+    Ok(())
 }
 
 #[test]
@@ -81,9 +140,9 @@ fn parse_test_valid() {
 fn fen_test_no_castle() {
     let str = "rnbqk2r/pppp2pp/3b1n2/4pp2/4PP2/3B1N2/PPPP2PP/RNBQK2R w KQkq - 2 5";
     let board = get_board(str.to_string());
-    assert!(board.is_some());
+    assert!(board.is_ok());
     let board_string = get_fen(&board.unwrap());
-    assert!(board_string.is_some());
+    assert!(board_string.is_ok());
     let valid_board_string = board_string.unwrap();
     assert_eq!(str, valid_board_string);
 }
@@ -92,9 +151,9 @@ fn fen_test_no_castle() {
 fn fen_test_one_castle() {
     let str = "rnbqk2r/pppp2pp/3b1n2/4pp2/4PP2/3B1N2/PPPP2PP/RNBQ1RK1 b kq - 3 5";
     let board = get_board(str.to_string());
-    assert!(board.is_some());
+    assert!(board.is_ok());
     let board_string = get_fen(&board.unwrap());
-    assert!(board_string.is_some());
+    assert!(board_string.is_ok());
     let valid_board_string = board_string.unwrap();
     assert_eq!(str, valid_board_string);
 }
@@ -103,13 +162,258 @@ fn fen_test_one_castle() {
 fn fen_test_both_castle() {
     let str = "rnbq1rk1/pppp2pp/3b1n2/4pp2/4PP2/3B1N2/PPPP2PP/RNBQ1RK1 w - - 4 6";
     let board = get_board(str.to_string());
-    assert!(board.is_some());
+    assert!(board.is_ok());
     let board_string = get_fen(&board.unwrap());
-    assert!(board_string.is_some());
+    assert!(board_string.is_ok());
     let valid_board_string = board_string.unwrap();
     assert_eq!(str, valid_board_string);
 }
 
+#[test]
+fn fen_test_invalid() {
+    let invalid_field = |fen: &str| match get_board(fen.to_string()) {
+        Err(ChessError::InvalidFen { field, .. }) => Some(field),
+        _ => None,
+    };
+    assert_eq!(invalid_field("8/8/8/8/8/8/8/8 w - - 0"), Some(5));
+    assert_eq!(
+        invalid_field("rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+        Some(0)
+    );
+    assert_eq!(
+        invalid_field("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - a 1"),
+        Some(4)
+    );
+    assert_eq!(
+        invalid_field("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 -1"),
+        Some(5)
+    );
+    // malformed
+    assert_eq!(
+        invalid_field("rnbqkbnr/ppppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+        Some(0)
+    );
+    assert_eq!(
+        invalid_field("rnbqkbnr/pppppppp/44/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+        Some(0)
+    );
+    assert_eq!(
+        invalid_field("rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+        Some(0)
+    );
+    assert_eq!(
+        invalid_field("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1"),
+        Some(1)
+    );
+    assert_eq!(
+        invalid_field("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1"),
+        Some(2)
+    );
+    assert_eq!(
+        invalid_field("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KKkq - 0 1"),
+        Some(2)
+    );
+    assert_eq!(
+        invalid_field("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1"),
+        Some(3)
+    );
+
+    // impossible positions
+    assert_eq!(
+        invalid_field("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQQBNR w kq - 0 1"),
+        Some(0)
+    );
+    assert_eq!(
+        invalid_field("rnbqkbnP/pppppppp/8/8/8/8/PPPPPPP1/RNBQKBNR w KQq - 0 1"),
+        Some(0)
+    );
+    assert_eq!(
+        invalid_field("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/QNBQKBNR w Kkq - 0 1"),
+        Some(0)
+    );
+    assert_eq!(
+        invalid_field("rnbqkbnr/pppppppp/8/8/8/N7/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+        Some(0)
+    );
+    assert_eq!(invalid_field("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"), Some(1));
+    assert_eq!(invalid_field("4k3/8/8/8/8/8/8/4K3 w K - 0 1"), Some(2));
+    assert_eq!(invalid_field("4k3/8/8/8/8/8/8/4K1R1 w G - 0 1"), None);
+    assert_eq!(invalid_field("4k3/8/8/8/8/8/8/4K1R1 w D - 0 1"), Some(2));
+    assert_eq!(invalid_field("4k3/8/8/8/8/8/8/4K1R1 w KG - 0 1"), Some(2));
+    assert_eq!(
+        invalid_field("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e6 0 1"),
+        Some(3)
+    );
+    assert_eq!(
+        invalid_field("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 1 1"),
+        Some(4)
+    );
+    assert!(
+        get_board("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1".to_string())
+            .is_ok()
+    );
+
+    assert!(parse_move("e2e").is_err());
+    assert!(parse_move("e2e9").is_err());
+}
+
+#[test]
+fn chess960_fen_test() {
+    assert_eq!(
+        get_chess960_fen(518).unwrap(),
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1"
+    );
+    assert_eq!(
+        get_chess960_fen(0).unwrap(),
+        "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1"
+    );
+    assert!(get_chess960_fen(960).is_err());
+
+    // every start position can be loaded and written back
+    for index in 0..960 {
+        let fen = get_chess960_fen(index).unwrap();
+        let game = get_board(fen.clone()).unwrap();
+        assert!(game.is_chess960);
+        assert_eq!(get_fen(&game).unwrap(), fen);
+    }
+
+    // X-FEN uses KQkq for the outermost rooks
+    let game = get_board("rk2r3/8/8/8/8/8/8/RK2R3 w KQk - 0 1".to_string()).unwrap();
+    assert!(game.is_chess960);
+    assert_eq!(
+        get_fen(&game).unwrap(),
+        "rk2r3/8/8/8/8/8/8/RK2R3 w EAe - 0 1"
+    );
+    assert_eq!(game.castle[0].king_side_rook, Position { x: 4, y: 7 });
+
+    let game = get_board(STANDARD_BOARD.to_string()).unwrap();
+    assert!(!game.is_chess960);
+}
+
+#[test]
+fn crazyhouse_fen_test() {
+    let load = |fen: &str| get_variant_board(fen.to_string(), Variant::Crazyhouse);
+
+    // the pockets are written after the board with the white pieces first and promoted pieces end with ~
+    let fen = "r3k2r/8/8/8/8/8/8/R3KQ~1R[QBNPqbnpp] w KQkq - 0 1";
+    assert_eq!(get_fen(&load(fen).unwrap()).unwrap(), fen);
+    let game = load("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    assert_eq!(
+        get_fen(&game).unwrap(),
+        "r3k2r/8/8/8/8/8/8/R3K2R[] w KQkq - 0 1"
+    );
+
+    let invalid_field = |fen: &str| match load(fen) {
+        Err(ChessError::InvalidFen { field, .. }) => Some(field),
+        _ => None,
+    };
+    assert_eq!(invalid_field("4k3/8/8/8/8/8/8/4K3[K] w - - 0 1"), Some(0));
+    assert_eq!(invalid_field("4k3/8/8/8/8/8/8/4K3[Q w - - 0 1"), Some(0));
+    assert_eq!(invalid_field("4k3/8/8/8/8/8/8/4K3Q] w - - 0 1"), Some(0));
+    assert_eq!(invalid_field("4k3/8/8/8/8/8/8/4K~3[] w - - 0 1"), Some(0));
+    assert_eq!(invalid_field("4k3/8/8/8/8/8/8/4K3[QQq] w - - 0 1"), Some(0));
+    assert_eq!(
+        get_board("4k3/8/8/8/8/8/8/Q~3K3 w - - 0 1".to_string()).map(|_| ()),
+        Err(invalid_fen(
+            0,
+            "promoted pieces are only marked in Crazyhouse".to_string()
+        ))
+    );
+
+    // drops are written like N@f3 in both SAN and the long format
+    let game = load("4k3/8/8/8/8/8/8/4K3[NP] w - - 0 1").unwrap();
+    for game_move in generate_legal_moves(&game) {
+        assert_eq!(
+            parse_long_move(&game, &get_long_move(&game_move)),
+            Ok(game_move)
+        );
+        let san = get_san(&game, &game_move);
+        assert_eq!(parse_san(&game, &san), Ok(game_move), "{}", san);
+    }
+    let game_move = parse_san(&game, "@e4").unwrap();
+    assert_eq!(get_san(&game, &game_move), "P@e4");
+    assert_eq!(get_long_move(&game_move), "P@e4");
+    assert_eq!(
+        get_san(&game, &parse_long_move(&game, "n@d6").unwrap()),
+        "N@d6+"
+    );
+    assert!(parse_san(&game, "P@e8").is_err());
+    assert!(parse_san(&game, "B@e4").is_err());
+    assert!(parse_long_move(&game, "e4e4").is_err());
+}
+
+#[test]
+fn san_test() {
+    let game = get_board(
+        "r3k2r/pPppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q2/PPPBBPpP/R3K2R w KQkq - 0 1".to_string(),
+    )
+    .unwrap();
+
+    // every legal move has to be written and read back to the same move
+    for game_move in generate_legal_moves(&game) {
+        let san = get_san(&game, &game_move);
+        assert_eq!(parse_san(&game, &san), Ok(game_move), "{}", san);
+    }
+
+    let san_of = |game: &Game, input: &str| get_san(game, &parse_san(game, input).unwrap());
+    assert_eq!(san_of(&game, "O-O-O"), "O-O-O");
+    assert_eq!(san_of(&game, "Qxf6"), "Qxf6");
+    assert_eq!(san_of(&game, "bxa8=Q"), "bxa8=Q+");
+    assert_eq!(san_of(&game, "bxa8N!?"), "bxa8=N");
+    assert_eq!(san_of(&game, "Nxf7"), "Nxf7");
+    assert!(parse_san(&game, "bxa8").is_err());
+    assert!(parse_san(&game, "Ke3").is_err());
+    assert!(parse_san(&game, "Zz9").is_err());
+
+    let game = get_board("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1".to_string()).unwrap();
+    assert_eq!(san_of(&game, "0-0"), "O-O");
+    assert_eq!(san_of(&game, "Rxa8+"), "Rxa8+");
+
+    // both knights can move to d2, the file decides
+    let game = get_board("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1".to_string()).unwrap();
+    assert!(parse_san(&game, "Nd2").is_err());
+    assert_eq!(san_of(&game, "Nbd2"), "Nbd2");
+    assert_eq!(san_of(&game, "Nf1d2"), "Nfd2");
+
+    // same file so the rank decides
+    let game = get_board("4k3/8/8/8/R7/8/8/R3K3 w - - 0 1".to_string()).unwrap();
+    assert_eq!(san_of(&game, "R1a2"), "R1a2");
+    assert_eq!(san_of(&game, "R4a3"), "R4a3");
+
+    let game = get_board("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1".to_string()).unwrap();
+    assert_eq!(san_of(&game, "exd6 e.p."), "exd6");
+
+    let game =
+        get_board("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2".to_string())
+            .unwrap();
+    assert_eq!(san_of(&game, "Qh4#"), "Qh4#");
+
+    let game = get_board("8/1P2k3/8/8/8/8/8/4K3 w - - 0 1".to_string()).unwrap();
+    assert_eq!(san_of(&game, "b8=Q+"), "b8=Q");
+}
+
+#[test]
+fn long_move_test() {
+    let game = get_board("r3k3/1P6/8/8/8/8/8/4K2R w K - 0 1".to_string()).unwrap();
+    for game_move in generate_legal_moves(&game) {
+        assert_eq!(
+            parse_long_move(&game, &get_long_move(&game_move)),
+            Ok(game_move)
+        );
+    }
+    assert_eq!(
+        parse_long_move(&game, "b7a8n").unwrap().promotion,
+        Piece::Knight
+    );
+    assert!(parse_long_move(&game, "b7a8").is_err());
+    assert!(parse_long_move(&game, "b7a8k").is_err());
+    assert!(parse_long_move(&game, "e1e3").is_err());
+    assert_eq!(
+        parse_long_move(&game, "e1g1").unwrap().piece.piece,
+        Piece::King
+    );
+}
+
 /** Parse position in the e6 format */
 fn parse_position(input: &str) -> Option<Position> {
     let real_input = input.to_lowercase();
@@ -147,20 +451,308 @@ pub fn get_move(move_from: Position, move_to: Position) -> String {
     return format!("{}{}", str1, str2);
 }
 
+/** Move in the e7e8q format, the promotion is only added if the move is a promotion. Drops are written like N@f3 */
+pub fn get_long_move(game_move: &Move) -> String {
+    if is_drop(game_move) {
+        return get_drop(game_move);
+    }
+
+    let mut str = get_move(game_move.from, game_move.to);
+    let promotion = PieceData {
+        piece: game_move.promotion,
+        is_white: false,
+    };
+    if let Some(promotion_char) = get_piece(promotion) {
+        str.push(promotion_char);
+    }
+    str
+}
+
+/** A drop in Variant::Crazyhouse written like N@f3, the same in SAN and in the long format */
+fn get_drop(game_move: &Move) -> String {
+    let mut str = String::new();
+    let piece = PieceData {
+        piece: game_move.piece.piece,
+        is_white: true,
+    };
+    if let Some(piece_char) = get_piece(piece) {
+        str.push(piece_char);
+    }
+    str.push('@');
+    str.push_str(&get_position(game_move.to));
+    str
+}
+
+/** Parses a drop like N@f3, a pawn can be written both as P@e4 and @e4. The drop is looked up among the legal moves */
+fn parse_drop(game: &Game, input: &str) -> Result<Move, ChessError> {
+    let invalid_input = |reason: &str| ChessError::InvalidInput(format!("'{}' {}", input, reason));
+    let (piece_input, square) = input
+        .split_once('@')
+        .ok_or_else(|| invalid_input("is not a drop like N@f3"))?;
+
+    let mut piece_chars = piece_input.chars();
+    let piece = match (piece_chars.next(), piece_chars.next()) {
+        (None, _) => Piece::Pawn,
+        (Some(c), None) => match parse_piece(c.to_ascii_uppercase()) {
+            Some(piece_data) if POCKET_PIECES.contains(&piece_data.piece) => piece_data.piece,
+            _ => return Err(invalid_input("has no piece that can be dropped")),
+        },
+        _ => return Err(invalid_input("is not a drop like N@f3")),
+    };
+    let to = parse_position(square).ok_or_else(|| invalid_input("has no valid square"))?;
+
+    generate_legal_moves(game)
+        .into_iter()
+        .find(|m| is_drop(m) && m.piece.piece == piece && m.to == to)
+        .ok_or_else(|| invalid_input("is not a legal move"))
+}
+
 /** Parse move in e6e3 format, result as from -> to */
-pub fn parse_move(input: &str) -> Option<(Position, Position)> {
-    if input.len() != 4 {
-        return None;
+pub fn parse_move(input: &str) -> Result<(Position, Position), ChessError> {
+    let invalid_input = || ChessError::InvalidInput(format!("'{}' is not a move like e2e4", input));
+    if input.len() != 4 || !input.is_char_boundary(2) {
+        return Err(invalid_input());
     }
 
     let split_input = input.split_at(2);
     let move_start = parse_position(split_input.0);
     let move_end = parse_position(split_input.1);
-    if move_start.is_none() || move_end.is_none() {
+    match (move_start, move_end) {
+        (Some(move_start), Some(move_end)) => Ok((move_start, move_end)),
+        _ => Err(invalid_input()),
+    }
+}
+
+/**
+Parses a move in the long algebraic e7e8q format used by UCI, the promotion is the fifth character.
+The move is looked up among the legal moves in the game, castling can be written both as the king move e1g1
+and as the king taking its own rook e1h1 like UCI does for Chess960. Drops are written like N@f3
+*/
+pub fn parse_long_move(game: &Game, input: &str) -> Result<Move, ChessError> {
+    if input.contains('@') {
+        return parse_drop(game, input);
+    }
+
+    let (move_input, promotion_input) = if input.len() == 5 && input.is_char_boundary(4) {
+        input.split_at(4)
+    } else {
+        (input, "")
+    };
+    let (from, to) = parse_move(move_input)?;
+    let to = get_castle_move_end(game, from, to);
+
+    let promotion = match promotion_input.chars().next() {
+        Some(c) => match parse_piece(c.to_ascii_lowercase()) {
+            Some(piece_data) if get_promotion_pieces(game.variant).contains(&piece_data.piece) => {
+                piece_data.piece
+            }
+            _ => {
+                return Err(ChessError::InvalidInput(format!(
+                    "'{}' is not a promotion piece",
+                    c
+                )))
+            }
+        },
+        None => Piece::None,
+    };
+
+    generate_legal_moves(game)
+        .into_iter()
+        .find(|m| m.from == from && m.to == to && m.promotion == promotion && !is_drop(m))
+        .ok_or_else(|| ChessError::InvalidInput(format!("'{}' is not a legal move", input)))
+}
+
+/** The uppercase letter used for the piece in SAN, pawns and empty squares have none */
+fn get_san_piece(piece: Piece) -> Option<char> {
+    if piece == Piece::Pawn {
         return None;
     }
+    get_piece(PieceData {
+        piece,
+        is_white: true,
+    })
+}
+
+/** "+" if the move checks and "#" if it is checkmate */
+fn get_check_suffix(game: &Game, game_move: &Move) -> &'static str {
+    let mut next_game = *game;
+    make_move(&mut next_game, game_move);
 
-    Some((move_start.unwrap(), move_end.unwrap()))
+    let threat_map = generate_all_threats(&next_game, !next_game.is_white_to_move);
+    if !is_in_check(&next_game, &threat_map) {
+        ""
+    } else if generate_legal_moves_with_threats(&next_game, &threat_map).is_empty() {
+        "#"
+    } else {
+        "+"
+    }
+}
+
+/**
+Standard Algebraic Notation https://en.wikipedia.org/wiki/Algebraic_notation_(chess)
+The move has to be a legal move in the game, like the ones from legal_moves
+*/
+pub fn get_san(game: &Game, game_move: &Move) -> String {
+    let from = game_move.from;
+    let to = game_move.to;
+    let piece = game_move.piece.piece;
+
+    let mut output = String::new();
+    if is_drop(game_move) {
+        output.push_str(&get_drop(game_move));
+        output.push_str(get_check_suffix(game, game_move));
+        return output;
+    }
+
+    if let Some(rook_position) = get_castle_rook(&game_move.castle, game_move.piece, from, to) {
+        output.push_str(if rook_position.x > from.x {
+            "O-O"
+        } else {
+            "O-O-O"
+        });
+        output.push_str(get_check_suffix(game, game_move));
+        return output;
+    }
+
+    let is_capture = game_move.captured.piece != Piece::None;
+    match get_san_piece(piece) {
+        Some(piece_char) => {
+            output.push(piece_char);
+
+            // other pieces of the same type that can move to the same square
+            let others: Vec<Position> = generate_legal_moves(game)
+                .iter()
+                .filter(|m| m.piece.piece == piece && m.to == to && m.from != from && !is_drop(m))
+                .map(|m| m.from)
+                .collect();
+            if !others.is_empty() {
+                if others.iter().all(|other| other.x != from.x) {
+                    output.push(BOARD_X_INPUT[from.x]);
+                } else if others.iter().all(|other| other.y != from.y) {
+                    output.push(BOARD_Y_INPUT[from.y]);
+                } else {
+                    output.push_str(&get_position(from));
+                }
+            }
+        }
+        None => {
+            // pawns captures are written with the file they came from
+            if is_capture {
+                output.push(BOARD_X_INPUT[from.x]);
+            }
+        }
+    }
+
+    if is_capture {
+        output.push('x');
+    }
+    output.push_str(&get_position(to));
+
+    if let Some(promotion_char) = get_san_piece(game_move.promotion) {
+        output.push('=');
+        output.push(promotion_char);
+    }
+
+    output.push_str(get_check_suffix(game, game_move));
+    output
+}
+
+/**
+Parses a move in Standard Algebraic Notation like Nbd7, exd6 e.p., O-O-O, e8=Q+, Qh4# or the drop N@f3
+The move is looked up among the legal moves, so it is refused if it is illegal or ambiguous
+*/
+pub fn parse_san(game: &Game, input: &str) -> Result<Move, ChessError> {
+    let invalid_input = |reason: &str| ChessError::InvalidInput(format!("'{}' {}", input, reason));
+
+    // annotations and check marks are not needed to find the move
+    let mut san = input.trim();
+    san = san.strip_suffix("e.p.").unwrap_or(san).trim_end();
+    san = san.trim_end_matches(['+', '#', '!', '?']);
+
+    if san.contains('@') {
+        return parse_drop(game, san);
+    }
+
+    let moves = generate_legal_moves(game);
+
+    let castle_side = match san {
+        "O-O" | "0-0" => Some(true),
+        "O-O-O" | "0-0-0" => Some(false),
+        _ => None,
+    };
+    if let Some(is_king_side) = castle_side {
+        return moves
+            .into_iter()
+            .find(
+                |m| match get_castle_rook(&m.castle, m.piece, m.from, m.to) {
+                    Some(rook_position) => (rook_position.x > m.from.x) == is_king_side,
+                    None => false,
+                },
+            )
+            .ok_or_else(|| invalid_input("castling is not legal"));
+    }
+
+    let mut chars: Vec<char> = san.chars().collect();
+
+    let piece = match chars.first().and_then(|&c| parse_piece(c)) {
+        Some(piece_data) if piece_data.is_white && piece_data.piece != Piece::Pawn => {
+            chars.remove(0);
+            piece_data.piece
+        }
+        _ => Piece::Pawn,
+    };
+
+    // the promotion is written as e8=Q, but e8Q is also accepted
+    let mut promotion = Piece::None;
+    if piece == Piece::Pawn {
+        if let Some(promotion_data) = chars.last().and_then(|&c| parse_piece(c)) {
+            if promotion_data.is_white {
+                promotion = promotion_data.piece;
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+            }
+        }
+    }
+
+    let is_capture = chars.contains(&'x');
+    chars.retain(|&c| c != 'x' && c != '-');
+
+    if chars.len() < 2 || chars.len() > 4 {
+        return Err(invalid_input("is not a move in SAN"));
+    }
+    let square: String = chars.split_off(chars.len() - 2).into_iter().collect();
+    let to = parse_position(&square).ok_or_else(|| invalid_input("has no valid end square"))?;
+
+    // what is left is the file and/or rank of the start square
+    let mut from_x = None;
+    let mut from_y = None;
+    for c in chars {
+        if let Some(x) = BOARD_X_INPUT.iter().position(|&x| x == c) {
+            from_x = Some(x);
+        } else if let Some(y) = BOARD_Y_INPUT.iter().position(|&y| y == c) {
+            from_y = Some(y);
+        } else {
+            return Err(invalid_input("is not a move in SAN"));
+        }
+    }
+
+    let mut matches = moves.into_iter().filter(|m| {
+        !is_drop(m)
+            && m.piece.piece == piece
+            && m.to == to
+            && m.promotion == promotion
+            && (from_x.is_none() || from_x == Some(m.from.x))
+            && (from_y.is_none() || from_y == Some(m.from.y))
+            && (!is_capture || m.captured.piece != Piece::None)
+    });
+
+    match (matches.next(), matches.next()) {
+        (Some(game_move), None) => Ok(game_move),
+        (Some(_), Some(_)) => Err(invalid_input("is ambiguous")),
+        (None, _) => Err(invalid_input("is not a legal move")),
+    }
 }
 
 const CHAR_TO_PIECE_MAP: [(char, PieceData); 12] = [
@@ -268,7 +860,7 @@ fn parse_piece(input: char) -> Option<PieceData> {
     None
 }
 
-pub(crate) fn get_board_fen(game: &Game) -> Option<String> {
+pub(crate) fn get_board_fen(game: &Game) -> Result<String, ChessError> {
     let mut output: String = String::new();
     // generate board
     for y in 0..BOARD_SIZE {
@@ -283,7 +875,16 @@ pub(crate) fn get_board_fen(game: &Game) -> Option<String> {
                 }
                 match get_piece(piece_data) {
                     Some(s_char) => output.push(s_char),
-                    None => return None,
+                    None => {
+                        return Err(ChessError::InvalidFen {
+                            field: 0,
+                            reason: format!("{:?} has no FEN character", piece_data),
+                        })
+                    }
+                }
+                let square = get_square_index(&Position { x, y });
+                if game.promoted & get_square_bitboard(square) != EMPTY_BITBOARD {
+                    output.push('~');
                 }
                 last_piece = 0
             }
@@ -296,6 +897,25 @@ pub(crate) fn get_board_fen(game: &Game) -> Option<String> {
         }
     }
 
+    // the pockets with the white pieces first like [QNnp]
+    if game.variant == Variant::Crazyhouse {
+        output.push('[');
+        for is_white in [true, false] {
+            let pocket = game.pockets[get_color_index(is_white)];
+            for (piece, count) in POCKET_PIECES.iter().zip(pocket.iter()).rev() {
+                if let Some(piece_char) = get_piece(PieceData {
+                    piece: *piece,
+                    is_white,
+                }) {
+                    for _ in 0..*count {
+                        output.push(piece_char);
+                    }
+                }
+            }
+        }
+        output.push(']');
+    }
+
     // white/black to move
     output.push(' ');
     output.push(if game.is_white_to_move { 'w' } else { 'b' });
@@ -303,26 +923,39 @@ pub(crate) fn get_board_fen(game: &Game) -> Option<String> {
 
     let mut cant_castle = 0;
 
-    // casteling
-    if !game.castle[0].can_castle_king_side && !game.castle[0].can_castle_queen_side {
-        cant_castle += 1;
-    } else {
-        if game.castle[0].can_castle_king_side {
-            output.push('K');
-        }
-        if game.castle[0].can_castle_queen_side {
-            output.push('Q');
+    // casteling, Chess960 uses the files of the rooks like HAha
+    for (index, castle_status) in game.castle.iter().enumerate() {
+        if !castle_status.can_castle_king_side && !castle_status.can_castle_queen_side {
+            cant_castle += 1;
+            continue;
         }
-    }
 
-    if !game.castle[1].can_castle_king_side && !game.castle[1].can_castle_queen_side {
-        cant_castle += 1;
-    } else {
-        if game.castle[1].can_castle_king_side {
-            output.push('k');
-        }
-        if game.castle[1].can_castle_queen_side {
-            output.push('q');
+        let sides = [
+            (
+                castle_status.can_castle_king_side,
+                castle_status.king_side_rook,
+                'k',
+            ),
+            (
+                castle_status.can_castle_queen_side,
+                castle_status.queen_side_rook,
+                'q',
+            ),
+        ];
+        for (can_castle, rook_position, side_char) in sides {
+            if !can_castle {
+                continue;
+            }
+            let castle_char = if game.is_chess960 {
+                BOARD_X_INPUT[rook_position.x]
+            } else {
+                side_char
+            };
+            output.push(if index == 0 {
+                castle_char.to_ascii_uppercase()
+            } else {
+                castle_char
+            });
         }
     }
 
@@ -335,148 +968,756 @@ pub(crate) fn get_board_fen(game: &Game) -> Option<String> {
         output.push(' ');
     }
 
-    if game.en_passant_position.is_some() {
-        let en_passant_position = game.en_passant_position.unwrap();
+    if let Some(en_passant_position) = game.en_passant_position {
         output.push(BOARD_X_INPUT[en_passant_position.x]);
         output.push(BOARD_Y_INPUT[en_passant_position.y]);
     } else {
         output.push('-');
     }
 
-    return Some(output);
+    Ok(output)
 }
 
 /** Forsyth–Edwards Notation https://en.wikipedia.org/wiki/Forsyth%E2%80%93Edwards_Notation
 start board for standard chess is rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1
 */
-pub fn get_fen(game: &Game) -> Option<String> {
-    let mut output = match get_board_fen(game) {
-        Some(fen) => fen,
-        None => return None,
-    };
+pub fn get_fen(game: &Game) -> Result<String, ChessError> {
+    let mut output = get_board_fen(game)?;
     output.push(' ');
 
     output += &game.half_move_clock.to_string();
     output.push(' ');
     output += &game.full_move_clock.to_string();
 
-    Some(output)
+    // the checks given by white and black
+    if game.variant == Variant::ThreeCheck {
+        output += &format!(" +{}+{}", game.checks[0], game.checks[1]);
+    }
+
+    Ok(output)
 }
 
-/** Forsyth–Edwards Notation https://en.wikipedia.org/wiki/Forsyth%E2%80%93Edwards_Notation
-start board for standard chess is rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1
-*/
-pub fn get_board(fen_string: String) -> Option<Game> {
-    let split: Vec<String> = fen_string
-        .split_whitespace()
-        .map(|s| s.to_string())
-        .collect();
+/** The FEN of the start position of the variant */
+pub fn get_start_fen(variant: Variant) -> String {
+    match variant {
+        Variant::Standard | Variant::KingOfTheHill | Variant::Atomic => STANDARD_BOARD.to_string(),
+        Variant::ThreeCheck => format!("{} +0+0", STANDARD_BOARD),
+        Variant::Crazyhouse => STANDARD_BOARD.replacen(' ', "[] ", 1),
+        Variant::Antichess => STANDARD_BOARD.replacen("KQkq", "-", 1),
+        Variant::Horde => HORDE_BOARD.to_string(),
+        Variant::RacingKings => RACING_KINGS_BOARD.to_string(),
+    }
+}
 
-    // cant parse, invalid format
-    if split.len() != 6 {
-        return None;
+/** Reads the name of a variant like "Three-check", case, spaces and dashes are ignored */
+pub fn parse_variant(input: &str) -> Result<Variant, ChessError> {
+    let simplify = |name: &str| -> String {
+        name.chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.to_ascii_lowercase())
+            .collect()
+    };
+    let name = simplify(input);
+    let variant = match name.as_str() {
+        "3check" => Some(Variant::ThreeCheck),
+        "koth" => Some(Variant::KingOfTheHill),
+        _ => VARIANTS
+            .iter()
+            .copied()
+            .find(|variant| simplify(&variant.to_string()) == name),
+    };
+    variant.ok_or_else(|| ChessError::InvalidInput(format!("'{}' is not a variant", input)))
+}
+
+fn invalid_fen(field: usize, reason: String) -> ChessError {
+    ChessError::InvalidFen { field, reason }
+}
+
+/** The checks given by white and black in Variant::ThreeCheck, written like +2+1 */
+fn parse_checks(input: &str) -> Result<[u8; 2], ChessError> {
+    let counts: Vec<&str> = input.split('+').collect();
+    // the leading + leaves an empty string first
+    if counts.len() != 3 || !counts[0].is_empty() {
+        return Err(invalid_fen(
+            6,
+            format!("expected checks like +2+1 but found '{}'", input),
+        ));
     }
 
-    // get board
+    let mut checks = [0; 2];
+    for (index, count) in counts[1..].iter().enumerate() {
+        checks[index] = match count.parse::<u8>() {
+            Ok(n) if n <= THREE_CHECK_WIN => n,
+            _ => {
+                return Err(invalid_fen(
+                    6,
+                    format!("'{}' is not a number of checks", count),
+                ))
+            }
+        };
+    }
+
+    if checks[0] >= THREE_CHECK_WIN && checks[1] >= THREE_CHECK_WIN {
+        return Err(invalid_fen(
+            6,
+            "both players has given three checks".to_string(),
+        ));
+    }
+    Ok(checks)
+}
+
+/** The pockets of Variant::Crazyhouse written after the board like [QNnp], white pieces are uppercase */
+fn parse_pockets(input: &str) -> Result<[[u8; 5]; 2], ChessError> {
+    let mut pockets = [[0; 5]; 2];
+    for char in input.chars() {
+        let piece_data = match parse_piece(char) {
+            Some(piece_data) if POCKET_PIECES.contains(&piece_data.piece) => piece_data,
+            _ => return Err(invalid_fen(0, format!("'{}' can not be in a pocket", char))),
+        };
+        pockets[get_color_index(piece_data.is_white)][get_piece_index(piece_data.piece)] += 1;
+    }
+    Ok(pockets)
+}
+
+/**
+Parses the piece placement, every rank has to be exactly BOARD_SIZE squares.
+Also returns the pieces marked as promoted with a ~ after them like Q~
+*/
+fn parse_fen_board(
+    input: &str,
+) -> Result<([[PieceData; BOARD_SIZE]; BOARD_SIZE], Bitboard), ChessError> {
     let mut board = [[EMPTY_PEICE; BOARD_SIZE]; BOARD_SIZE];
-    let mut board_x = 0usize;
-    let mut board_y = 0usize;
-    for char in split[0].chars() {
-        if char == '/' || board_x >= BOARD_SIZE {
-            board_y += 1;
-            board_x = 0;
+    let mut promoted = EMPTY_BITBOARD;
+
+    let ranks: Vec<&str> = input.split('/').collect();
+    if ranks.len() != BOARD_SIZE {
+        return Err(invalid_fen(
+            0,
+            format!("expected {} ranks but found {}", BOARD_SIZE, ranks.len()),
+        ));
+    }
+
+    for (board_y, rank) in ranks.iter().enumerate() {
+        let mut board_x = 0usize;
+        let mut last_was_number = false;
+        let mut last_was_piece = false;
+        for char in rank.chars() {
+            // marks the piece before it as promoted, pawns and kings are never promoted
+            if char == '~' {
+                let piece = board[board_x.saturating_sub(1)][board_y].piece;
+                if !last_was_piece || piece == Piece::Pawn || piece == Piece::King {
+                    return Err(invalid_fen(0, "'~' has no promoted piece".to_string()));
+                }
+                let position = Position {
+                    x: board_x - 1,
+                    y: board_y,
+                };
+                promoted |= get_square_bitboard(get_square_index(&position));
+                last_was_piece = false;
+                continue;
+            }
+
+            if board_x >= BOARD_SIZE {
+                return Err(invalid_fen(
+                    0,
+                    format!(
+                        "rank {} is longer than {} squares",
+                        BOARD_Y_INPUT[board_y], BOARD_SIZE
+                    ),
+                ));
+            }
+
+            if let Some(piece) = parse_piece(char) {
+                board[board_x][board_y] = piece;
+                board_x += 1;
+                last_was_number = false;
+                last_was_piece = true;
+                continue;
+            }
+
+            // empty squares, two numbers in a row is not allowed as 44 should be written as 8
+            let number = match char.to_digit(10) {
+                Some(n) if n >= 1 && n <= BOARD_SIZE as u32 && !last_was_number => n as usize,
+                _ => return Err(invalid_fen(0, format!("invalid character '{}'", char))),
+            };
+            board_x += number;
+            last_was_number = true;
+            last_was_piece = false;
+        }
+
+        if board_x != BOARD_SIZE {
+            return Err(invalid_fen(
+                0,
+                format!(
+                    "rank {} has {} squares instead of {}",
+                    BOARD_Y_INPUT[board_y], board_x, BOARD_SIZE
+                ),
+            ));
+        }
+    }
+
+    Ok((board, promoted))
+}
+
+/**
+In Variant::Crazyhouse the pieces change color when they are captured,
+so only the number of each piece in the game is checked, on the board and in the pockets
+*/
+fn validate_crazyhouse_pieces(game: &Game) -> Result<(), ChessError> {
+    // a promoted piece is a pawn that is still in the game
+    let mut pawns = game.promoted.count_ones();
+    for (index, piece) in POCKET_PIECES.iter().enumerate() {
+        let on_board =
+            (get_pieces(game, *piece, true) | get_pieces(game, *piece, false)) & !game.promoted;
+        let count =
+            on_board.count_ones() + game.pockets[0][index] as u32 + game.pockets[1][index] as u32;
+        let max_count = match piece {
+            Piece::Pawn => {
+                pawns += count;
+                continue;
+            }
+            Piece::Queen => 2,
+            _ => 4,
+        };
+        if count > max_count {
+            return Err(invalid_fen(
+                0,
+                format!("there are {} {:?} pieces", count, piece),
+            ));
+        }
+    }
+
+    if pawns > 2 * BOARD_SIZE as u32 {
+        return Err(invalid_fen(
+            0,
+            format!("there are {} pawns and promoted pieces", pawns),
+        ));
+    }
+    Ok(())
+}
+
+/** Rejects piece placements that can never happen in a game */
+fn validate_pieces(game: &Game) -> Result<(), ChessError> {
+    for is_white in [true, false] {
+        let color = if is_white { "white" } else { "black" };
+        let count = |piece: Piece| get_pieces(game, piece, is_white).count_ones();
+
+        // white has no king in Variant::Horde and in Variant::Antichess the king can be captured and promoted to
+        let is_horde_white = game.variant == Variant::Horde && is_white;
+        let kings = if is_horde_white { 0 } else { 1 };
+        if count(Piece::King) != kings && game.variant != Variant::Antichess {
+            return Err(invalid_fen(
+                0,
+                format!("{} has {} kings", color, count(Piece::King)),
+            ));
+        }
+
+        if game.variant == Variant::RacingKings && count(Piece::Pawn) > 0 {
+            return Err(invalid_fen(
+                0,
+                "there are no pawns in Racing Kings".to_string(),
+            ));
+        }
+
+        // white starts with 36 pawns in Variant::Horde
+        if game.variant == Variant::Crazyhouse || is_horde_white {
             continue;
         }
-        if board_y >= BOARD_SIZE {
-            // this should not happend, invalid input?
-            break;
+
+        let pieces = game.color_bitboards[get_color_index(is_white)].count_ones();
+        if pieces > 2 * BOARD_SIZE as u32 {
+            return Err(invalid_fen(0, format!("{} has {} pieces", color, pieces)));
+        }
+
+        let pawns = count(Piece::Pawn);
+        if pawns > BOARD_SIZE as u32 {
+            return Err(invalid_fen(0, format!("{} has {} pawns", color, pawns)));
         }
 
-        let piece = parse_piece(char);
+        // every piece more than the start pieces has to be a promoted pawn
+        let promoted = count(Piece::King).saturating_sub(1)
+            + count(Piece::Queen).saturating_sub(1)
+            + count(Piece::Rook).saturating_sub(2)
+            + count(Piece::Bishop).saturating_sub(2)
+            + count(Piece::Knight).saturating_sub(2);
+        if promoted + pawns > BOARD_SIZE as u32 {
+            return Err(invalid_fen(
+                0,
+                format!("{} has more promoted pieces than missing pawns", color),
+            ));
+        }
+    }
 
-        if piece.is_none() {
-            // is number
-            let number: Option<u32> = char.to_digit(10);
-            if number.is_none() {
-                // invalid input
-                return None;
+    if game.variant == Variant::Crazyhouse {
+        validate_crazyhouse_pieces(game)?;
+    }
+
+    for x in 0..BOARD_SIZE {
+        for y in [BLACK_SPAWN, WHITE_SPAWN] {
+            let piece_data = game.board[x][y];
+            // the white pawns can start on the first rank in Variant::Horde
+            let is_horde_start =
+                game.variant == Variant::Horde && piece_data.is_white && y == WHITE_SPAWN;
+            if piece_data.piece == Piece::Pawn && !is_horde_start {
+                return Err(invalid_fen(
+                    0,
+                    format!("pawn on {}", get_position(Position { x, y })),
+                ));
             }
-            board_x += number.unwrap() as usize;
-        } else {
-            // is piece
-            board[board_x][board_y] = piece.unwrap();
-            board_x += 1;
         }
     }
 
-    // who to move
-    let is_white_to_move = split[1] == "w";
+    Ok(())
+}
 
-    //castle
-    let casle_chars: Vec<char> = split[2].chars().collect(); //.chars();
+/**
+The player that just moved can not have left the king in check, there is no check in Variant::Antichess
+and in Variant::RacingKings no king can be in check
+*/
+fn validate_checks(game: &Game) -> Result<(), ChessError> {
+    if game.variant == Variant::Antichess {
+        return Ok(());
+    }
 
-    let mut white_castle = Castle {
-        can_castle_king_side: false,
-        can_castle_queen_side: false,
-        queen_side_rook: Position {
-            x: 0,
-            y: BOARD_SIZE - 1,
-        },
-        king_side_rook: Position {
-            x: BOARD_SIZE - 1,
-            y: BOARD_SIZE - 1,
-        },
+    let occupied = get_occupied(game);
+    let is_white = game.is_white_to_move;
+
+    if is_king_checked(game, !is_white) {
+        return Err(invalid_fen(
+            1,
+            "the side not to move is in check".to_string(),
+        ));
+    }
+
+    if game.variant == Variant::RacingKings && is_king_checked(game, is_white) {
+        return Err(invalid_fen(
+            1,
+            "no king can be in check in Racing Kings".to_string(),
+        ));
+    }
+
+    // no move can give check with more than two pieces, white has no king in Variant::Horde
+    let king = get_pieces(game, Piece::King, is_white);
+    if king == EMPTY_BITBOARD {
+        return Ok(());
+    }
+    let king = king.trailing_zeros() as usize;
+    if get_attackers(game, king, occupied, !is_white).count_ones() > 2 {
+        return Err(invalid_fen(
+            1,
+            "the side to move is checked by more than two pieces".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/** The king and the rook has to stand on their start squares for the player to be able to castle */
+fn validate_castle(game: &Game) -> Result<(), ChessError> {
+    for (index, castle_status) in game.castle.iter().enumerate() {
+        if (game.variant == Variant::Antichess || game.variant == Variant::RacingKings)
+            && (castle_status.can_castle_king_side || castle_status.can_castle_queen_side)
+        {
+            return Err(invalid_fen(
+                2,
+                format!("there is no castling in {}", game.variant),
+            ));
+        }
+
+        let is_white = index == 0;
+        let spawn_y = if is_white { WHITE_SPAWN } else { BLACK_SPAWN };
+        let king = PieceData {
+            piece: Piece::King,
+            is_white,
+        };
+        let rook = PieceData {
+            piece: Piece::Rook,
+            is_white,
+        };
+
+        let sides = [
+            (
+                castle_status.can_castle_king_side,
+                castle_status.king_side_rook,
+                true,
+            ),
+            (
+                castle_status.can_castle_queen_side,
+                castle_status.queen_side_rook,
+                false,
+            ),
+        ];
+        for (can_castle, rook_position, is_king_side) in sides {
+            if !can_castle {
+                continue;
+            }
+
+            // the king is between the rooks on the first rank, on the e file unless it is Chess960
+            let king_x = match (0..BOARD_SIZE).find(|&x| game.board[x][spawn_y] == king) {
+                Some(x) if game.is_chess960 || x == 4 => x,
+                _ => {
+                    return Err(invalid_fen(
+                        2,
+                        format!(
+                            "the king is not on {}",
+                            get_position(Position { x: 4, y: spawn_y })
+                        ),
+                    ))
+                }
+            };
+
+            if game.board[rook_position.x][rook_position.y] != rook
+                || (rook_position.x > king_x) != is_king_side
+            {
+                return Err(invalid_fen(
+                    2,
+                    format!("the rook is not on {}", get_position(rook_position)),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/** A pawn of the other player has to have moved two squares past the en passant square */
+fn validate_en_passant(game: &Game) -> Result<(), ChessError> {
+    let en_passant_position = match game.en_passant_position {
+        Some(position) => position,
+        None => return Ok(()),
+    };
+
+    let is_white = game.is_white_to_move;
+    // the square the pawn moved over and the squares it moved from and to
+    let (expected_y, start_y, pawn_y) = if is_white {
+        (BLACK_PAWN_Y + 1, BLACK_PAWN_Y, BLACK_PAWN_Y + 2)
+    } else {
+        (WHITE_PAWN_Y - 1, WHITE_PAWN_Y, WHITE_PAWN_Y - 2)
+    };
+
+    let x = en_passant_position.x;
+    let pawn = PieceData {
+        piece: Piece::Pawn,
+        is_white: !is_white,
+    };
+    if en_passant_position.y != expected_y
+        || game.board[x][expected_y].piece != Piece::None
+        || game.board[x][start_y].piece != Piece::None
+        || game.board[x][pawn_y] != pawn
+    {
+        return Err(invalid_fen(
+            3,
+            format!(
+                "no pawn can have moved past {}",
+                get_position(en_passant_position)
+            ),
+        ));
+    }
+
+    if game.half_move_clock != 0 {
+        return Err(invalid_fen(
+            4,
+            "the halfmove clock has to be 0 after a pawn move".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/**
+The start position of Chess960 from its number 0-959, 518 is the start position of standard chess.
+https://en.wikipedia.org/wiki/Fischer_random_chess_numbering_scheme
+*/
+pub fn get_chess960_fen(index: usize) -> Result<String, ChessError> {
+    if index >= 960 {
+        return Err(ChessError::InvalidInput(format!(
+            "{} is not a Chess960 position, they are numbered 0-959",
+            index
+        )));
+    }
+
+    // the knights are placed on two of the five squares left after the bishops and the queen
+    const KNIGHT_PLACES: [(usize, usize); 10] = [
+        (0, 1),
+        (0, 2),
+        (0, 3),
+        (0, 4),
+        (1, 2),
+        (1, 3),
+        (1, 4),
+        (2, 3),
+        (2, 4),
+        (3, 4),
+    ];
+
+    let mut pieces = [None; BOARD_SIZE];
+    let mut rest = index;
+    // light squared bishop on b, d, f or h and dark squared bishop on a, c, e or g
+    pieces[(rest % 4) * 2 + 1] = Some('b');
+    rest /= 4;
+    pieces[(rest % 4) * 2] = Some('b');
+    rest /= 4;
+
+    let empty = |pieces: &[Option<char>; BOARD_SIZE]| -> Vec<usize> {
+        (0..BOARD_SIZE).filter(|&x| pieces[x].is_none()).collect()
     };
-    let mut black_castle = Castle {
+    pieces[empty(&pieces)[rest % 6]] = Some('q');
+    rest /= 6;
+
+    let (first, second) = KNIGHT_PLACES[rest];
+    let knight_squares = empty(&pieces);
+    pieces[knight_squares[first]] = Some('n');
+    pieces[knight_squares[second]] = Some('n');
+
+    // the king is always between the rooks
+    for (x, piece) in empty(&pieces).into_iter().zip(['r', 'k', 'r']) {
+        pieces[x] = Some(piece);
+    }
+
+    let black: String = pieces.iter().flatten().collect();
+    let white = black.to_ascii_uppercase();
+    let rook_files: Vec<char> = (0..BOARD_SIZE)
+        .rev()
+        .filter(|&x| pieces[x] == Some('r'))
+        .map(|x| BOARD_X_INPUT[x])
+        .collect();
+    let castle: String = rook_files.iter().map(|c| c.to_ascii_uppercase()).collect();
+
+    Ok(format!(
+        "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w {}{} - 0 1",
+        black,
+        white,
+        castle,
+        rook_files.iter().collect::<String>()
+    ))
+}
+
+/**
+The castling field is KQkq, the files of the rooks like HAha (Shredder-FEN)
+or a mix where KQkq is the outermost rook and the file is only used for the inner rook (X-FEN).
+Returns true as well if it has to be a Chess960 game
+*/
+fn parse_castle(
+    board: &[[PieceData; BOARD_SIZE]; BOARD_SIZE],
+    input: &str,
+) -> Result<([Castle; 2], bool), ChessError> {
+    let mut castle = [WHITE_SPAWN, BLACK_SPAWN].map(|y| Castle {
         can_castle_king_side: false,
         can_castle_queen_side: false,
-        queen_side_rook: Position { x: 0, y: 0 },
+        queen_side_rook: Position { x: 0, y },
         king_side_rook: Position {
             x: BOARD_SIZE - 1,
-            y: 0,
+            y,
         },
-    };
+    });
+    let mut is_chess960 = false;
+    if input == "-" {
+        return Ok((castle, is_chess960));
+    }
 
-    for casle_char in casle_chars {
-        match casle_char {
-            'K' => {
-                white_castle.can_castle_king_side = true;
-            }
-            'k' => {
-                black_castle.can_castle_king_side = true;
-            }
-            'Q' => {
-                white_castle.can_castle_queen_side = true;
+    for castle_char in input.chars() {
+        let is_white = castle_char.is_ascii_uppercase();
+        let y = if is_white { WHITE_SPAWN } else { BLACK_SPAWN };
+        let is_rook = |x: usize| {
+            board[x][y]
+                == PieceData {
+                    piece: Piece::Rook,
+                    is_white,
+                }
+        };
+        let king_x = (0..BOARD_SIZE).find(|&x| {
+            board[x][y]
+                == PieceData {
+                    piece: Piece::King,
+                    is_white,
+                }
+        });
+
+        // the rook is searched for from the corner, if there is no king validate_castle will refuse it
+        let (is_king_side, rook_x) = match castle_char.to_ascii_lowercase() {
+            'k' => (
+                true,
+                (king_x.unwrap_or(0)..BOARD_SIZE)
+                    .rev()
+                    .find(|&x| is_rook(x))
+                    .unwrap_or(BOARD_SIZE - 1),
+            ),
+            'q' => (
+                false,
+                (0..king_x.unwrap_or(BOARD_SIZE))
+                    .find(|&x| is_rook(x))
+                    .unwrap_or(0),
+            ),
+            file @ 'a'..='h' => {
+                let rook_x = file as usize - 'a' as usize;
+                is_chess960 = true;
+                match king_x {
+                    Some(king_x) => (rook_x > king_x, rook_x),
+                    None => {
+                        return Err(invalid_fen(
+                            2,
+                            format!("'{}' has no king to castle with", castle_char),
+                        ))
+                    }
+                }
             }
-            'q' => {
-                black_castle.can_castle_queen_side = true;
+            _ => {
+                return Err(invalid_fen(
+                    2,
+                    format!("invalid character '{}'", castle_char),
+                ))
             }
-            _ => {}
         };
+
+        let castle_status = &mut castle[if is_white { 0 } else { 1 }];
+        let (can_castle, rook_position) = if is_king_side {
+            (
+                &mut castle_status.can_castle_king_side,
+                &mut castle_status.king_side_rook,
+            )
+        } else {
+            (
+                &mut castle_status.can_castle_queen_side,
+                &mut castle_status.queen_side_rook,
+            )
+        };
+        if *can_castle {
+            return Err(invalid_fen(
+                2,
+                format!(
+                    "'{}' castles to a side that is already written",
+                    castle_char
+                ),
+            ));
+        }
+        *can_castle = true;
+        rook_position.x = rook_x;
+
+        // the king or the rooks are not on the squares of standard chess
+        let corner_x = if is_king_side { BOARD_SIZE - 1 } else { 0 };
+        if king_x.is_some_and(|x| x != 4) || rook_x != corner_x {
+            is_chess960 = true;
+        }
     }
 
-    let en_passant_position = parse_position(&split[3]);
+    Ok((castle, is_chess960))
+}
+
+/** Forsyth–Edwards Notation https://en.wikipedia.org/wiki/Forsyth%E2%80%93Edwards_Notation
+start board for standard chess is rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1
+Positions that can not be reached in a game are refused, the error tells which field is wrong
+*/
+pub fn get_board(fen_string: String) -> Result<Game, ChessError> {
+    get_variant_board(fen_string, Variant::Standard)
+}
+
+/**
+Same as get_board but the game is played with the rules of the variant.
+Variant::ThreeCheck can have a seventh field with the checks given by white and black like +2+1.
+Variant::Crazyhouse has the pockets after the board like [QNnp] and the promoted pieces marked like Q~
+*/
+pub fn get_variant_board(fen_string: String, variant: Variant) -> Result<Game, ChessError> {
+    let split: Vec<String> = fen_string
+        .split_whitespace()
+        .map(|s| s.to_string())
+        .collect();
+
+    // cant parse, invalid format
+    let max_fields = if variant == Variant::ThreeCheck { 7 } else { 6 };
+    if split.len() < 6 || split.len() > max_fields {
+        return Err(ChessError::InvalidFen {
+            field: split.len().min(max_fields),
+            reason: format!("expected {} fields but found {}", max_fields, split.len()),
+        });
+    }
+
+    // the pockets are optional as they are empty at the start
+    let (board_input, pockets) = match split[0].strip_suffix(']') {
+        Some(input) if variant == Variant::Crazyhouse => match input.split_once('[') {
+            Some((board_input, pocket_input)) => (board_input, parse_pockets(pocket_input)?),
+            None => return Err(invalid_fen(0, "the pockets has no '['".to_string())),
+        },
+        _ => (split[0].as_str(), [[0; 5]; 2]),
+    };
+
+    // get board
+    let (board, promoted) = parse_fen_board(board_input)?;
+    if promoted != EMPTY_BITBOARD && variant != Variant::Crazyhouse {
+        return Err(invalid_fen(
+            0,
+            "promoted pieces are only marked in Crazyhouse".to_string(),
+        ));
+    }
+
+    // who to move
+    let is_white_to_move = match split[1].as_str() {
+        "w" => true,
+        "b" => false,
+        side => {
+            return Err(invalid_fen(
+                1,
+                format!("expected w or b but found '{}'", side),
+            ))
+        }
+    };
+
+    //castle
+    let (castle, is_chess960) = parse_castle(&board, &split[2])?;
+
+    let en_passant_position = if split[3] == "-" {
+        None
+    } else {
+        match parse_position(&split[3]) {
+            Some(position) => Some(position),
+            None => return Err(invalid_fen(3, format!("'{}' is not a square", split[3]))),
+        }
+    };
 
     let half_move_clock = split[4].parse::<u16>();
     if half_move_clock.is_err() {
         // invalid input
-        return None;
+        return Err(ChessError::InvalidFen {
+            field: 4,
+            reason: format!("'{}' is not a number", split[4]),
+        });
     }
 
     let full_move_clock = split[5].parse::<u16>();
     if full_move_clock.is_err() {
         // invalid input
-        return None;
+        return Err(ChessError::InvalidFen {
+            field: 5,
+            reason: format!("'{}' is not a number", split[5]),
+        });
     }
 
-    let game = Game {
+    let checks = match split.get(6) {
+        Some(input) => parse_checks(input)?,
+        None => [0; 2],
+    };
+
+    let mut game = Game {
         board,
-        castle: [white_castle, black_castle],
+        castle,
         is_white_to_move,
+        is_chess960,
+        variant,
+        checks,
+        pockets,
+        promoted,
         en_passant_position,
         half_move_clock: half_move_clock.unwrap(),
         full_move_clock: full_move_clock.unwrap(),
+        piece_bitboards: [EMPTY_BITBOARD; 6],
+        color_bitboards: [EMPTY_BITBOARD; 2],
+        hash: 0,
     };
+    update_bitboards(&mut game);
+
+    validate_pieces(&game)?;
+    validate_checks(&game)?;
+    validate_castle(&game)?;
+    validate_en_passant(&game)?;
 
-    Some(game)
+    Ok(game)
 }
@@ -0,0 +1,73 @@
+use crate::bitboard::get_square_position;
+use crate::game_data::*;
+use crate::logic::{
+    create_move, generate_all_legal_moves, generate_all_threats, generate_legal_moves, make_move,
+};
+use crate::parser::get_long_move;
+
+/**
+Counts all positions that can be reached after depth moves, used to find bugs in the move generation.
+Known results can be found at https://www.chessprogramming.org/Perft_Results
+*/
+pub fn perft(game: &Game, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let threat_map = generate_all_threats(game, !game.is_white_to_move);
+
+    // the last moves only have to be counted and not played
+    let mut nodes = 0u64;
+    if depth == 1 {
+        generate_all_legal_moves(game, &threat_map, &mut |_, _, _| nodes += 1);
+        return nodes;
+    }
+
+    let mut moves = Vec::new();
+    generate_all_legal_moves(game, &threat_map, &mut |from, to, promotion| {
+        moves.push((from, to, promotion))
+    });
+
+    for (from, to, promotion) in moves {
+        let game_move = create_move(
+            game,
+            get_square_position(from),
+            get_square_position(to),
+            promotion,
+        );
+        let mut next_game = *game;
+        make_move(&mut next_game, &game_move);
+        nodes += perft(&next_game, depth - 1);
+    }
+    nodes
+}
+
+/** Same as perft but the result is split up by the first move, compare it with another engine to find a bug */
+pub fn perft_divide(game: &Game, depth: u32) -> Vec<(Move, u64)> {
+    let mut result = Vec::new();
+    if depth == 0 {
+        return result;
+    }
+
+    for game_move in generate_legal_moves(game) {
+        let mut next_game = *game;
+        make_move(&mut next_game, &game_move);
+        result.push((game_move, perft(&next_game, depth - 1)));
+    }
+    result
+}
+
+/** Prints perft_divide in the same format as most engines, "e2e4: 20" and the total at the end */
+pub fn print_perft_divide(game: &Game, depth: u32) -> u64 {
+    let mut divide = perft_divide(game, depth);
+    divide.sort_by_key(|(game_move, _)| get_long_move(game_move));
+
+    let mut total = 0;
+    for (game_move, nodes) in divide {
+        println!("{}: {}", get_long_move(&game_move), nodes);
+        total += nodes;
+    }
+    println!();
+    println!("Nodes searched: {}", total);
+    total
+}
@@ -0,0 +1,572 @@
+use crate::game_data::*;
+use crate::logic::{get_game_state, get_threats, make_move, play_move, unmake_move};
+use crate::parser::{
+    get_fen, get_san, get_start_fen, init_variant_game_board, parse_san, parse_variant,
+};
+
+/** Portable Game Notation https://en.wikipedia.org/wiki/Portable_Game_Notation */
+pub const SEVEN_TAG_ROSTER: [&str; 7] =
+    ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+/** The value of the Variant tag for Chess960 games */
+const CHESS960_VARIANT: &str = "Chess960";
+
+/** The PGN standard says that no line should be longer than this */
+const MAX_LINE_LENGTH: usize = 79;
+
+/** A game read from a PGN file */
+pub struct PgnGame {
+    /** the tag pairs in the order they were written */
+    pub tags: Vec<(String, String)>,
+    /** the main line is played, undo_move can be used to step back through the game */
+    pub game_board: Gameboard,
+    /** comments and how many moves that was played before the comment */
+    pub comments: Vec<(usize, String)>,
+}
+
+impl PgnGame {
+    pub fn get_tag(&self, name: &str) -> Option<&str> {
+        get_tag(&self.tags, name)
+    }
+}
+
+fn get_tag<'a>(tags: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    tags.iter()
+        .find(|(tag_name, _)| tag_name == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/** The game before any of the moves in the history was played */
+fn get_start_game(game_board: &Gameboard) -> Game {
+    let mut game = game_board.game;
+    for game_move in game_board.history.iter().rev() {
+        unmake_move(&mut game, game_move);
+    }
+    game
+}
+
+fn get_result_text(outcome: WinStatus) -> &'static str {
+    match outcome {
+        WinStatus::WhiteWon => "1-0",
+        WinStatus::BlackWon => "0-1",
+        WinStatus::Tie => "1/2-1/2",
+        WinStatus::Nothing => "*",
+    }
+}
+
+fn get_game_result(game_board: &Gameboard) -> GameResult {
+    let threats = get_threats(game_board);
+    get_game_state(game_board, &threats, true)
+}
+
+fn get_result(game_board: &Gameboard) -> &'static str {
+    get_result_text(get_game_result(game_board).outcome)
+}
+
+/** Written like "White won by checkmate" or "Game drawn by stalemate" */
+fn get_termination(result: GameResult) -> Option<String> {
+    let reason = result.reason?;
+    match result.outcome {
+        WinStatus::WhiteWon => Some(format!("White won by {}", reason)),
+        WinStatus::BlackWon => Some(format!("Black won by {}", reason)),
+        WinStatus::Tie => Some(format!("Game drawn by {}", reason)),
+        WinStatus::Nothing => None,
+    }
+}
+
+/** Reads the result back from the Result and Termination tags */
+fn parse_termination(result: &str, termination: &str) -> Option<GameResult> {
+    let outcome = match result {
+        "1-0" => WinStatus::WhiteWon,
+        "0-1" => WinStatus::BlackWon,
+        "1/2-1/2" => WinStatus::Tie,
+        _ => return None,
+    };
+    let (_, reason) = termination.rsplit_once(" by ")?;
+    let reason = GAME_END_REASONS
+        .iter()
+        .copied()
+        .find(|game_end_reason| reason.eq_ignore_ascii_case(&game_end_reason.to_string()))?;
+    Some(GameResult {
+        outcome,
+        reason: Some(reason),
+    })
+}
+
+fn get_default_tag(name: &str) -> &'static str {
+    match name {
+        "Date" => "????.??.??",
+        _ => "?",
+    }
+}
+
+fn push_tag(output: &mut String, name: &str, value: &str) {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    output.push_str(&format!("[{} \"{}\"]\n", name, value));
+}
+
+/**
+Writes the game as PGN with the Seven Tag Roster first, tags that are not given are written as unknown.
+The Result tag is taken from the game state if it is not given, and SetUp and FEN are added if the game did not start from the standard board.
+The Termination tag tells why the game ended, like "Black won by resignation", if it is not given and the result agrees with the game state
+*/
+pub fn get_pgn(game_board: &Gameboard, tags: &[(String, String)]) -> String {
+    let mut game = get_start_game(game_board);
+    let start_fen = get_fen(&game).unwrap_or_default();
+    let result = get_tag(tags, "Result").unwrap_or_else(|| get_result(game_board));
+
+    let mut output = String::new();
+    for name in SEVEN_TAG_ROSTER {
+        let value = match name {
+            "Result" => result,
+            _ => get_tag(tags, name).unwrap_or_else(|| get_default_tag(name)),
+        };
+        push_tag(&mut output, name, value);
+    }
+
+    if get_tag(tags, "Variant").is_none() {
+        if game.variant != Variant::Standard {
+            push_tag(&mut output, "Variant", &game.variant.to_string());
+        } else if game.is_chess960 {
+            push_tag(&mut output, "Variant", CHESS960_VARIANT);
+        }
+    }
+    let has_setup = get_tag(tags, "FEN").is_some();
+    if !has_setup && start_fen != get_start_fen(game.variant) {
+        push_tag(&mut output, "SetUp", "1");
+        push_tag(&mut output, "FEN", &start_fen);
+    }
+    let game_result = get_game_result(game_board);
+    if get_tag(tags, "Termination").is_none() && get_result_text(game_result.outcome) == result {
+        if let Some(termination) = get_termination(game_result) {
+            push_tag(&mut output, "Termination", &termination);
+        }
+    }
+    for (name, value) in tags {
+        if !SEVEN_TAG_ROSTER.contains(&name.as_str()) {
+            push_tag(&mut output, name, value);
+        }
+    }
+    output.push('\n');
+
+    let mut tokens = Vec::new();
+    for (index, game_move) in game_board.history.iter().enumerate() {
+        if game.is_white_to_move {
+            tokens.push(format!("{}.", game.full_move_clock));
+        } else if index == 0 {
+            tokens.push(format!("{}...", game.full_move_clock));
+        }
+        tokens.push(get_san(&game, game_move));
+        make_move(&mut game, game_move);
+    }
+    tokens.push(result.to_string());
+
+    let mut line_length = 0;
+    for token in tokens {
+        if line_length != 0 && line_length + 1 + token.len() > MAX_LINE_LENGTH {
+            output.push('\n');
+            line_length = 0;
+        } else if line_length != 0 {
+            output.push(' ');
+            line_length += 1;
+        }
+        line_length += token.len();
+        output.push_str(&token);
+    }
+    output.push('\n');
+
+    output
+}
+
+/** Reads characters from the input, used to read the PGN one game at a time */
+struct PgnReader {
+    chars: Vec<char>,
+    index: usize,
+}
+
+impl PgnReader {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.index += 1;
+        c
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.index += 1;
+        }
+    }
+
+    fn read_until(&mut self, end: char) -> String {
+        let mut output = String::new();
+        while let Some(c) = self.next_char() {
+            if c == end {
+                break;
+            }
+            output.push(c);
+        }
+        output
+    }
+
+    /** [Name "value"], the value can contain escaped quotes and backslashes */
+    fn read_tag(&mut self) -> Result<(String, String), String> {
+        let name = self.read_until('"').trim().to_string();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("invalid tag name '{}'", name));
+        }
+
+        let mut value = String::new();
+        loop {
+            match self.next_char() {
+                Some('\\') => value.extend(self.next_char()),
+                Some('"') => break,
+                Some(c) => value.push(c),
+                None => return Err(format!("the value of {} has no end", name)),
+            }
+        }
+
+        if !self.read_until(']').trim().is_empty() {
+            return Err(format!("invalid tag {}", name));
+        }
+        Ok((name, value))
+    }
+
+    /** variations are skipped, they can contain more variations and comments */
+    fn skip_variation(&mut self) {
+        let mut depth = 1;
+        while let Some(c) = self.next_char() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                '{' => {
+                    self.read_until('}');
+                }
+                ';' => {
+                    self.read_until('\n');
+                }
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+        }
+    }
+
+    fn read_token(&mut self) -> String {
+        let mut token = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || "{}();[".contains(c) {
+                break;
+            }
+            token.push(c);
+            self.index += 1;
+        }
+        token
+    }
+
+    fn read_game(&mut self) -> Result<PgnGame, String> {
+        let mut tags = Vec::new();
+        self.skip_whitespace();
+        while self.peek() == Some('[') {
+            self.index += 1;
+            tags.push(self.read_tag()?);
+            self.skip_whitespace();
+        }
+
+        // games that does not start from the standard board has the start in the FEN tag
+        // Chess960 is played with the standard rules and is not a Variant
+        let variant = match get_tag(&tags, "Variant") {
+            Some(name) if name.eq_ignore_ascii_case(CHESS960_VARIANT) => Variant::Standard,
+            Some(name) => parse_variant(name).map_err(|e| e.to_string())?,
+            None => Variant::Standard,
+        };
+        let start_fen = get_start_fen(variant);
+        let fen = get_tag(&tags, "FEN").unwrap_or(&start_fen);
+        let mut game_board =
+            init_variant_game_board(fen.to_string(), variant).map_err(|e| e.to_string())?;
+        if get_tag(&tags, "Variant")
+            .is_some_and(|variant| variant.eq_ignore_ascii_case(CHESS960_VARIANT))
+        {
+            game_board.game.is_chess960 = true;
+        }
+        let mut comments = Vec::new();
+        let mut result = None;
+
+        loop {
+            self.skip_whitespace();
+            let c = match self.peek() {
+                Some(c) => c,
+                None => break,
+            };
+
+            match c {
+                // the next game starts without a result
+                '[' => break,
+                '{' => {
+                    self.index += 1;
+                    let comment = self.read_until('}');
+                    comments.push((game_board.history.len(), comment.trim().to_string()));
+                }
+                ';' => {
+                    self.index += 1;
+                    let comment = self.read_until('\n');
+                    comments.push((game_board.history.len(), comment.trim().to_string()));
+                }
+                // escaped lines are for other programs
+                '%' => {
+                    self.read_until('\n');
+                }
+                '(' => {
+                    self.index += 1;
+                    self.skip_variation();
+                }
+                ')' => return Err("unexpected )".to_string()),
+                _ => {
+                    let token = self.read_token();
+                    if token.is_empty() {
+                        self.index += 1;
+                        continue;
+                    }
+
+                    if ["1-0", "0-1", "1/2-1/2", "*"].contains(&token.as_str()) {
+                        result = Some(token);
+                        break;
+                    }
+
+                    // NAGs like $1 and move numbers like 12. or 12...
+                    if token.starts_with('$') {
+                        continue;
+                    }
+                    let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                    if san.is_empty() || san.chars().all(|c| c == '!' || c == '?') {
+                        continue;
+                    }
+
+                    let move_number = game_board.game.full_move_clock;
+                    let game_move = parse_san(&game_board.game, san)
+                        .map_err(|e| format!("move {}: {}", move_number, e))?;
+                    if !play_move(&mut game_board, game_move) {
+                        return Err(format!("move {}: '{}' can not be played", move_number, san));
+                    }
+                }
+            }
+        }
+
+        if let Some(result) = result {
+            if get_tag(&tags, "Result").is_none() {
+                tags.push(("Result".to_string(), result));
+            }
+        }
+
+        // resignations, losses on time and agreed draws can not be seen on the board
+        if let (Some(result), Some(termination)) =
+            (get_tag(&tags, "Result"), get_tag(&tags, "Termination"))
+        {
+            if get_game_result(&game_board).reason.is_none() {
+                game_board.result = parse_termination(result, termination);
+            }
+        }
+
+        Ok(PgnGame {
+            tags,
+            game_board,
+            comments,
+        })
+    }
+}
+
+/**
+Reads every game in a PGN file, comments are kept, NAGs and variations are skipped and only the main line is played.
+Games with the SetUp and FEN tags start from that position
+*/
+pub fn parse_pgn(input: &str) -> Result<Vec<PgnGame>, ChessError> {
+    let mut reader = PgnReader {
+        chars: input.chars().collect(),
+        index: 0,
+    };
+
+    let mut games = Vec::new();
+    loop {
+        reader.skip_whitespace();
+        if reader.peek().is_none() {
+            break;
+        }
+
+        match reader.read_game() {
+            Ok(game) => games.push(game),
+            Err(reason) => {
+                return Err(ChessError::InvalidPgn {
+                    game: games.len() + 1,
+                    reason,
+                })
+            }
+        }
+    }
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::undo_move;
+    use crate::parser::{get_chess960_fen, get_fen, init_game_board, init_variant_game_board};
+
+    const GAMES: &str = r#"[Event "Internal match"]
+[Site "Stockholm"]
+[Date "2021.11.01"]
+[Round "1"]
+[White "Vincent"]
+[Black "Lag"]
+[Result "0-1"]
+
+1. f3 {weak} e5 $2 (1... e6 2. g4 (2. e4) Qh4#) 2. g4?? Qh4# 0-1
+
+[Event "?"]
+[SetUp "1"]
+[FEN "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1"]
+
+1. b8=Q+ Kd7 ; the king runs
+2. Qb5+ *
+"#;
+
+    #[test]
+    fn import_test() {
+        let games = parse_pgn(GAMES).unwrap();
+        assert_eq!(games.len(), 2);
+
+        let first = &games[0];
+        assert_eq!(first.get_tag("White"), Some("Vincent"));
+        assert_eq!(first.game_board.history.len(), 4);
+        assert_eq!(first.comments, vec![(1, "weak".to_string())]);
+        assert_eq!(get_result(&first.game_board), "0-1");
+
+        let second = &games[1];
+        assert_eq!(second.get_tag("Result"), Some("*"));
+        assert_eq!(second.comments, vec![(2, "the king runs".to_string())]);
+        assert_eq!(
+            get_fen(&second.game_board.game).unwrap(),
+            "8/3k4/8/1Q6/8/8/8/4K3 b - - 2 2"
+        );
+
+        // the games can be stepped back to the start
+        let mut game_board = parse_pgn(GAMES).unwrap().remove(1).game_board;
+        while undo_move(&mut game_board).is_ok() {}
+        assert_eq!(
+            get_fen(&game_board.game).unwrap(),
+            "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1"
+        );
+
+        let games = parse_pgn(r#"[White "Vincent \"V\" [A]"] *"#).unwrap();
+        assert_eq!(games[0].get_tag("White"), Some(r#"Vincent "V" [A]"#));
+        let pgn = get_pgn(&games[0].game_board, &games[0].tags);
+        assert!(pgn.contains(r#"[White "Vincent \"V\" [A]"]"#));
+
+        assert_eq!(
+            parse_pgn("1. e4 e5 2. Ke3 *").err(),
+            Some(ChessError::InvalidPgn {
+                game: 1,
+                reason: "move 2: invalid input: 'Ke3' is not a legal move".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn export_test() {
+        let games = parse_pgn(GAMES).unwrap();
+        let pgn = get_pgn(&games[0].game_board, &games[0].tags);
+        assert_eq!(
+            pgn,
+            "[Event \"Internal match\"]\n[Site \"Stockholm\"]\n[Date \"2021.11.01\"]\n[Round \"1\"]\n\
+             [White \"Vincent\"]\n[Black \"Lag\"]\n[Result \"0-1\"]\n\
+             [Termination \"Black won by checkmate\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n"
+        );
+
+        // the start position and result are added when they are missing
+        let pgn = get_pgn(&games[1].game_board, &[]);
+        assert!(pgn.contains("[Date \"????.??.??\"]\n"));
+        assert!(pgn.contains(
+            "[Result \"*\"]\n[SetUp \"1\"]\n[FEN \"4k3/1P6/8/8/8/8/8/4K3 w - - 0 1\"]\n"
+        ));
+        assert!(pgn.ends_with("\n\n1. b8=Q+ Kd7 2. Qb5+ *\n"));
+
+        // exported games can be read back
+        let imported = parse_pgn(&pgn).unwrap();
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].game_board.history, games[1].game_board.history);
+
+        // Chess960 games has the variant and castling is still O-O
+        let mut game_board = init_game_board(get_chess960_fen(0).unwrap()).unwrap();
+        for san in ["f4", "f5", "Rf3", "Rf6", "O-O"] {
+            let game_move = parse_san(&game_board.game, san).unwrap();
+            play_move(&mut game_board, game_move);
+        }
+        let pgn = get_pgn(&game_board, &[]);
+        assert!(pgn.contains(
+            "[Variant \"Chess960\"]\n[SetUp \"1\"]\n\
+             [FEN \"bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1\"]\n"
+        ));
+        assert!(pgn.ends_with("\n\n1. f4 f5 2. Rf3 Rf6 3. O-O *\n"));
+        let imported = parse_pgn(&pgn).unwrap();
+        assert!(imported[0].game_board.game.is_chess960);
+        assert_eq!(imported[0].game_board.history, game_board.history);
+    }
+
+    #[test]
+    fn termination_test() {
+        let mut game_board = parse_pgn(GAMES).unwrap().remove(1).game_board;
+        game_board.result = Some(GameResult {
+            outcome: WinStatus::BlackWon,
+            reason: Some(GameEndReason::Resignation),
+        });
+        let pgn = get_pgn(&game_board, &[]);
+        assert!(pgn.contains("[Result \"0-1\"]\n"));
+        assert!(pgn.contains("[Termination \"Black won by resignation\"]\n"));
+
+        // the result can not be seen on the board so it is read from the tags
+        let imported = parse_pgn(&pgn).unwrap().remove(0);
+        assert_eq!(imported.game_board.result, game_board.result);
+
+        // a result that is not supported by the game state gets no Termination
+        let pgn = get_pgn(
+            &imported.game_board,
+            &[("Result".to_string(), "*".to_string())],
+        );
+        assert!(!pgn.contains("Termination"));
+    }
+
+    #[test]
+    fn variant_test() {
+        let mut game_board =
+            init_variant_game_board(get_start_fen(Variant::ThreeCheck), Variant::ThreeCheck)
+                .unwrap();
+        for input in ["e4", "e5", "Bc4", "Nc6", "Bxf7+"] {
+            let game_move = parse_san(&game_board.game, input).unwrap();
+            play_move(&mut game_board, game_move);
+        }
+        let pgn = get_pgn(&game_board, &[]);
+        assert!(pgn.contains("[Variant \"Three-check\"]\n"));
+        // the start position of the variant needs no FEN
+        assert!(!pgn.contains("FEN"));
+
+        let imported = parse_pgn(&pgn).unwrap().remove(0);
+        assert_eq!(imported.game_board.game.variant, Variant::ThreeCheck);
+        assert_eq!(imported.game_board.game.checks, [1, 0]);
+        assert_eq!(
+            get_fen(&imported.game_board.game).unwrap(),
+            get_fen(&game_board.game).unwrap()
+        );
+
+        // a misspelled variant is not read as standard chess
+        assert_eq!(
+            parse_pgn("[Variant \"Crazyhose\"]\n\n1. e4 *").err(),
+            Some(ChessError::InvalidPgn {
+                game: 1,
+                reason: "invalid input: 'Crazyhose' is not a variant".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,624 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::bitboard::*;
+use crate::evaluation::evaluate;
+use crate::game_data::*;
+use crate::logic::{
+    create_move, generate_all_legal_moves, generate_all_threats, get_repetition_key,
+    get_variant_result, is_in_check, legal_moves, make_move, FIFTY_MOVE_RULE_HALF_MOVES,
+};
+
+/** The score of a checkmate on the board, a mate in n plies is MATE_SCORE - n */
+pub const MATE_SCORE: i32 = 30000;
+/** The longest line that can be searched */
+pub const MAX_PLY: usize = 64;
+/** Scores above this are a forced mate */
+pub const MATE_BOUND: i32 = MATE_SCORE - MAX_PLY as i32;
+const INFINITY: i32 = MATE_SCORE + 1;
+
+/** Number of entries in the transposition table, must be a power of two */
+const TABLE_SIZE: usize = 1 << 18;
+/** The clock and the stop flag are only checked this often as it is slow, must be a power of two */
+const CHECK_INTERVAL: u64 = 1024;
+
+/** Used to order captures, same order as piece_bitboards */
+const PIECE_VALUES: [i32; 6] = [100, 320, 330, 500, 900, 0];
+
+/** When to stop searching, the search runs until the stop flag is set if everything is None */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub time: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    /** None if there are no legal moves */
+    pub best_move: Option<Move>,
+    /** centipawns for the player to move, see get_mate_in for mate scores */
+    pub score: i32,
+    /** the depth of the last completed iteration */
+    pub depth: u32,
+    pub nodes: u64,
+    /** the line the search expects to be played, starting with best_move */
+    pub principal_variation: Vec<Move>,
+}
+
+/** Moves within the search are kept as from, to and promotion like the move generator gives them */
+type SearchMove = (usize, usize, Piece);
+
+#[derive(Clone, Copy, PartialEq)]
+enum Bound {
+    Exact,
+    /** the score is at least this, a beta cutoff */
+    Lower,
+    /** the score is at most this, no move raised alpha */
+    Upper,
+}
+
+#[derive(Clone, Copy)]
+struct TableEntry {
+    hash: u64,
+    depth: i32,
+    score: i32,
+    bound: Bound,
+    best_move: SearchMove,
+}
+
+/** The number of moves to a forced mate, negative if the player to move is getting mated */
+pub fn get_mate_in(score: i32) -> Option<i32> {
+    if score.abs() < MATE_BOUND {
+        return None;
+    }
+    let moves = (MATE_SCORE - score.abs() + 1) / 2;
+    Some(if score > 0 { moves } else { -moves })
+}
+
+/**
+The score of a game ended by the rules of the variant for the player to move.
+Mostly the player that just moved has won, but in Variant::RacingKings white can win after the last black move or it is a tie
+*/
+fn get_variant_score(game: &Game, result: GameResult, ply: usize) -> i32 {
+    let is_white_winner = match result.outcome {
+        WinStatus::WhiteWon => true,
+        WinStatus::BlackWon => false,
+        WinStatus::Tie | WinStatus::Nothing => return 0,
+    };
+    if is_white_winner == game.is_white_to_move {
+        MATE_SCORE - ply as i32
+    } else {
+        -MATE_SCORE + ply as i32
+    }
+}
+
+/** The evaluation from the view of the player to move */
+fn evaluate_for_player(game: &Game) -> i32 {
+    let score = evaluate(game);
+    if game.is_white_to_move {
+        score
+    } else {
+        -score
+    }
+}
+
+fn play_search_move(game: &Game, search_move: SearchMove) -> Game {
+    let (from, to, promotion) = search_move;
+    let game_move = create_move(
+        game,
+        get_square_position(from),
+        get_square_position(to),
+        promotion,
+    );
+    let mut next_game = *game;
+    make_move(&mut next_game, &game_move);
+    next_game
+}
+
+/** The piece the pawn is promoted to, drops in Variant::Crazyhouse give the dropped piece in its place */
+fn get_promotion(search_move: SearchMove) -> Piece {
+    let (from, to, promotion) = search_move;
+    if from == to {
+        Piece::None
+    } else {
+        promotion
+    }
+}
+
+/** The piece that is captured, en passant captures a pawn on an empty square */
+fn get_captured_piece(game: &Game, search_move: SearchMove) -> Piece {
+    let (from, to, _) = search_move;
+    let to_position = get_square_position(to);
+    let captured = game.board[to_position.x][to_position.y];
+    let from_position = get_square_position(from);
+    let piece = game.board[from_position.x][from_position.y];
+    if captured.piece == Piece::None
+        && piece.piece == Piece::Pawn
+        && from_position.x != to_position.x
+    {
+        return Piece::Pawn;
+    }
+
+    // castling in Chess960 is the king taking its own rook
+    if captured.is_white == piece.is_white {
+        return Piece::None;
+    }
+    captured.piece
+}
+
+/** Mate scores are stored from the position and not from the root, so they can be used at any ply */
+fn score_to_table(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_table(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+struct Searcher<'a> {
+    limits: SearchLimits,
+    stop: &'a AtomicBool,
+    start: Instant,
+    nodes: u64,
+    is_stopped: bool,
+    table: Vec<Option<TableEntry>>,
+    /** two quiet moves per ply that caused a beta cutoff */
+    killers: [[Option<SearchMove>; 2]; MAX_PLY],
+    /** how good quiet moves has been, indexed by color, from and to */
+    history: Vec<[[i32; BOARD_SIZE * BOARD_SIZE]; BOARD_SIZE * BOARD_SIZE]>,
+    /** positions that has been played in the game */
+    same_board: &'a HashMap<u64, u8>,
+    /** positions in the line that is searched right now */
+    path: Vec<u64>,
+}
+
+impl<'a> Searcher<'a> {
+    fn should_stop(&mut self) -> bool {
+        if self.is_stopped {
+            return true;
+        }
+
+        if let Some(nodes) = self.limits.nodes {
+            if self.nodes >= nodes {
+                self.is_stopped = true;
+            }
+        }
+
+        if self.nodes & (CHECK_INTERVAL - 1) == 0 {
+            if self.stop.load(Ordering::Relaxed) {
+                self.is_stopped = true;
+            }
+            if let Some(time) = self.limits.time {
+                if self.start.elapsed() >= time {
+                    self.is_stopped = true;
+                }
+            }
+        }
+        self.is_stopped
+    }
+
+    /** A position that is repeated once is counted as a draw, as the same moves can be played again */
+    fn is_repetition(&self, repetition_key: u64) -> bool {
+        self.path.contains(&repetition_key) || self.same_board.contains_key(&repetition_key)
+    }
+
+    fn probe_table(&self, hash: u64) -> Option<TableEntry> {
+        self.table[hash as usize & (TABLE_SIZE - 1)].filter(|entry| entry.hash == hash)
+    }
+
+    fn store_table(&mut self, entry: TableEntry) {
+        self.table[entry.hash as usize & (TABLE_SIZE - 1)] = Some(entry);
+    }
+
+    /** Sorts the moves so the best are searched first, which gives more cutoffs */
+    fn order_moves(
+        &self,
+        game: &Game,
+        moves: &mut Vec<SearchMove>,
+        table_move: Option<SearchMove>,
+        ply: usize,
+    ) {
+        let color_index = get_color_index(game.is_white_to_move);
+        let mut scored: Vec<(i32, SearchMove)> = moves
+            .iter()
+            .map(|&search_move| {
+                let (from, to, _) = search_move;
+                let promotion = get_promotion(search_move);
+                let captured = get_captured_piece(game, search_move);
+
+                let score = if Some(search_move) == table_move {
+                    1_000_000
+                } else if captured != Piece::None {
+                    // most valuable victim, least valuable attacker
+                    let from_position = get_square_position(from);
+                    let attacker = game.board[from_position.x][from_position.y].piece;
+                    100_000 + 10 * PIECE_VALUES[get_piece_index(captured)]
+                        - get_piece_index(attacker) as i32
+                } else if promotion == Piece::Queen {
+                    90_000
+                } else if promotion != Piece::None {
+                    -100_000
+                } else if self.killers[ply][0] == Some(search_move) {
+                    80_000
+                } else if self.killers[ply][1] == Some(search_move) {
+                    79_000
+                } else {
+                    self.history[color_index][from][to]
+                };
+                (score, search_move)
+            })
+            .collect();
+
+        scored.sort_by_key(|(score, _)| -score);
+        moves.clear();
+        moves.extend(scored.into_iter().map(|(_, search_move)| search_move));
+    }
+
+    fn add_cutoff(&mut self, game: &Game, search_move: SearchMove, depth: i32, ply: usize) {
+        if get_captured_piece(game, search_move) != Piece::None
+            || get_promotion(search_move) != Piece::None
+        {
+            return;
+        }
+
+        if self.killers[ply][0] != Some(search_move) {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = Some(search_move);
+        }
+
+        let (from, to, _) = search_move;
+        let history = &mut self.history[get_color_index(game.is_white_to_move)][from][to];
+        *history = (*history + depth * depth).min(70_000);
+    }
+
+    /** Only captures and promotions are searched so the evaluation is not done in the middle of a trade */
+    fn quiescence(&mut self, game: &Game, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        if self.should_stop() {
+            return 0;
+        }
+        self.nodes += 1;
+
+        if let Some(result) = get_variant_result(game) {
+            return get_variant_score(game, result, ply);
+        }
+
+        let stand_pat = evaluate_for_player(game);
+        if stand_pat >= beta || ply >= MAX_PLY - 1 {
+            return stand_pat;
+        }
+        if stand_pat > alpha {
+            alpha = stand_pat;
+        }
+
+        let threat_map = generate_all_threats(game, !game.is_white_to_move);
+        let mut moves = Vec::new();
+        generate_all_legal_moves(game, &threat_map, &mut |from, to, promotion| {
+            moves.push((from, to, promotion))
+        });
+        moves.retain(|&m| {
+            get_captured_piece(game, m) != Piece::None || get_promotion(m) == Piece::Queen
+        });
+        self.order_moves(game, &mut moves, None, ply);
+
+        for search_move in moves {
+            let next_game = play_search_move(game, search_move);
+            let score = -self.quiescence(&next_game, ply + 1, -beta, -alpha);
+            if self.is_stopped {
+                return 0;
+            }
+
+            if score >= beta {
+                return score;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+        }
+        alpha
+    }
+
+    fn negamax(
+        &mut self,
+        game: &Game,
+        depth: i32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+        principal_variation: &mut Vec<SearchMove>,
+    ) -> i32 {
+        principal_variation.clear();
+        if self.should_stop() {
+            return 0;
+        }
+
+        // the game can be won by the rules of the variant, like the third check
+        if let Some(result) = get_variant_result(game) {
+            self.nodes += 1;
+            return get_variant_score(game, result, ply);
+        }
+
+        // the engine claims the draw as soon as it can
+        let repetition_key = get_repetition_key(game);
+        if ply > 0
+            && (game.half_move_clock >= FIFTY_MOVE_RULE_HALF_MOVES
+                || self.is_repetition(repetition_key))
+        {
+            self.nodes += 1;
+            return 0;
+        }
+
+        let threat_map = generate_all_threats(game, !game.is_white_to_move);
+        let is_checked = is_in_check(game, &threat_map);
+
+        // a check is searched one ply deeper so that mates are not missed
+        let depth = if is_checked { depth + 1 } else { depth };
+        if depth <= 0 || ply >= MAX_PLY - 1 {
+            return self.quiescence(game, ply, alpha, beta);
+        }
+        self.nodes += 1;
+
+        let mut table_move = None;
+        if let Some(entry) = self.probe_table(game.hash) {
+            table_move = Some(entry.best_move);
+            let score = score_from_table(entry.score, ply);
+            if ply > 0
+                && entry.depth >= depth
+                && (entry.bound == Bound::Exact
+                    || (entry.bound == Bound::Lower && score >= beta)
+                    || (entry.bound == Bound::Upper && score <= alpha))
+            {
+                // the rest of the line is not stored, but the next move is known
+                if entry.bound == Bound::Exact {
+                    principal_variation.push(entry.best_move);
+                }
+                return score;
+            }
+        }
+
+        let mut moves = Vec::new();
+        generate_all_legal_moves(game, &threat_map, &mut |from, to, promotion| {
+            moves.push((from, to, promotion))
+        });
+        if moves.is_empty() {
+            // in Variant::Antichess the player that can not move wins
+            return if is_checked {
+                -MATE_SCORE + ply as i32
+            } else if game.variant == Variant::Antichess {
+                MATE_SCORE - ply as i32
+            } else {
+                0
+            };
+        }
+        self.order_moves(game, &mut moves, table_move, ply);
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = moves[0];
+        let mut child_variation = Vec::new();
+
+        self.path.push(repetition_key);
+        for search_move in moves {
+            let next_game = play_search_move(game, search_move);
+            let score = -self.negamax(
+                &next_game,
+                depth - 1,
+                ply + 1,
+                -beta,
+                -alpha,
+                &mut child_variation,
+            );
+            if self.is_stopped {
+                break;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = search_move;
+            }
+
+            if score > alpha {
+                alpha = score;
+                principal_variation.clear();
+                principal_variation.push(search_move);
+                principal_variation.extend_from_slice(&child_variation);
+            }
+
+            if alpha >= beta {
+                self.add_cutoff(game, search_move, depth, ply);
+                break;
+            }
+        }
+        self.path.pop();
+
+        if self.is_stopped {
+            return 0;
+        }
+
+        let bound = if best_score <= original_alpha {
+            Bound::Upper
+        } else if best_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.store_table(TableEntry {
+            hash: game.hash,
+            depth,
+            score: score_to_table(best_score, ply),
+            bound,
+            best_move,
+        });
+
+        best_score
+    }
+}
+
+/**
+Finds the best move for the player to move with iterative deepening alpha-beta search.
+on_iteration is called after every completed depth, the search stops at the limits or when stop is set.
+The best move is always one of the legal moves
+*/
+pub fn search(
+    game_board: &Gameboard,
+    limits: &SearchLimits,
+    stop: &AtomicBool,
+    on_iteration: &mut impl FnMut(&SearchResult),
+) -> SearchResult {
+    let game = &game_board.game;
+    let moves = legal_moves(game_board);
+
+    // used if not even the first depth has time to finish
+    let mut result = SearchResult {
+        best_move: moves.first().copied(),
+        score: 0,
+        depth: 0,
+        nodes: 0,
+        principal_variation: moves.first().copied().into_iter().collect(),
+    };
+    if moves.is_empty() {
+        return result;
+    }
+
+    let mut searcher = Searcher {
+        limits: *limits,
+        stop,
+        start: Instant::now(),
+        nodes: 0,
+        is_stopped: false,
+        table: vec![None; TABLE_SIZE],
+        killers: [[None; 2]; MAX_PLY],
+        history: vec![[[0; BOARD_SIZE * BOARD_SIZE]; BOARD_SIZE * BOARD_SIZE]; 2],
+        same_board: &game_board.same_board,
+        path: Vec::new(),
+    };
+
+    let max_depth = limits
+        .depth
+        .unwrap_or(MAX_PLY as u32)
+        .min(MAX_PLY as u32 - 1);
+    for depth in 1..=max_depth {
+        let mut principal_variation = Vec::new();
+        let score = searcher.negamax(
+            game,
+            depth as i32,
+            0,
+            -INFINITY,
+            INFINITY,
+            &mut principal_variation,
+        );
+        if searcher.is_stopped || principal_variation.is_empty() {
+            break;
+        }
+
+        let mut line_game = *game;
+        let mut line = Vec::new();
+        for (from, to, promotion) in principal_variation {
+            let game_move = create_move(
+                &line_game,
+                get_square_position(from),
+                get_square_position(to),
+                promotion,
+            );
+            make_move(&mut line_game, &game_move);
+            line.push(game_move);
+        }
+
+        result = SearchResult {
+            best_move: line.first().copied(),
+            score,
+            depth,
+            nodes: searcher.nodes,
+            principal_variation: line,
+        };
+        on_iteration(&result);
+
+        // a mate can not get any better
+        if get_mate_in(score).is_some() {
+            break;
+        }
+    }
+
+    result.nodes = searcher.nodes;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::init_game_board;
+
+    fn search_fen(fen: &str, limits: SearchLimits) -> SearchResult {
+        let game_board = init_game_board(fen.to_string()).unwrap();
+        search(&game_board, &limits, &AtomicBool::new(false), &mut |_| {})
+    }
+
+    fn depth(depth: u32) -> SearchLimits {
+        SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn mate_test() {
+        // back rank mate
+        let result = search_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", depth(4));
+        assert_eq!(get_mate_in(result.score), Some(1));
+        assert_eq!(
+            crate::parser::get_long_move(&result.best_move.unwrap()),
+            "a1a8"
+        );
+
+        // ladder mate with two rooks
+        let result = search_fen("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", depth(4));
+        assert_eq!(get_mate_in(result.score), Some(2));
+        assert_eq!(result.principal_variation.len(), 3);
+
+        let result = search_fen("6k1/5ppp/8/8/8/8/r7/1r4K1 w - - 0 1", depth(3));
+        assert_eq!(result.best_move, None);
+    }
+
+    #[test]
+    fn capture_test() {
+        // the queen is free but the rook is defended
+        let result = search_fen("4k3/8/2p5/3r4/8/5q2/8/3RKR2 w - - 0 1", depth(3));
+        assert_eq!(
+            crate::parser::get_long_move(&result.best_move.unwrap()),
+            "f1f3"
+        );
+        assert!(!result.principal_variation.is_empty());
+    }
+
+    #[test]
+    fn limits_test() {
+        let result = search_fen(
+            crate::parser::STANDARD_BOARD,
+            SearchLimits {
+                nodes: Some(500),
+                ..Default::default()
+            },
+        );
+        assert!(result.best_move.is_some());
+        assert!(result.nodes <= 501);
+
+        let stop = AtomicBool::new(true);
+        let game_board = init_game_board(crate::parser::STANDARD_BOARD.to_string()).unwrap();
+        let result = search(&game_board, &SearchLimits::default(), &stop, &mut |_| {});
+        assert_eq!(result.depth, 0);
+        assert!(result.best_move.is_some());
+    }
+}
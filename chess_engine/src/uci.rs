@@ -0,0 +1,342 @@
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::game_data::*;
+use crate::parser::{get_long_move, init_game_board, play_long_move, STANDARD_BOARD};
+use crate::search::{get_mate_in, search, SearchLimits, SearchResult};
+
+/** Universal Chess Interface http://wbec-ridderkerk.nl/html/UCIProtocol.html */
+pub const ENGINE_NAME: &str = "vinlag-chess";
+pub const ENGINE_AUTHOR: &str = "vinlag";
+
+/** The depth used when go is sent without any limits */
+const DEFAULT_DEPTH: u32 = 6;
+/** The clock is used as if there are this many moves left in the game */
+const MOVES_TO_GO: u32 = 30;
+
+pub type UciOutput = Arc<Mutex<dyn Write + Send>>;
+
+pub struct UciState {
+    /** the position set by the last position command */
+    pub game_board: Gameboard,
+    /** the search writes to the output from its own thread */
+    output: UciOutput,
+    stop: Arc<AtomicBool>,
+    search_thread: Option<JoinHandle<()>>,
+    /** set with the UCI_Chess960 option, castling is then sent as the king taking its own rook */
+    is_chess960: bool,
+}
+
+pub fn init_uci_state(output: UciOutput) -> UciState {
+    UciState {
+        game_board: init_game_board(STANDARD_BOARD.to_string()).unwrap(),
+        output,
+        stop: Arc::new(AtomicBool::new(false)),
+        search_thread: None,
+        is_chess960: false,
+    }
+}
+
+fn write_line(output: &UciOutput, line: &str) {
+    let mut output = output.lock().unwrap();
+    writeln!(output, "{}", line).ok();
+    output.flush().ok();
+}
+
+/** position startpos|fen FEN [moves e2e4 e7e5 ...] */
+fn parse_position_command(args: &[&str], is_chess960: bool) -> Result<Gameboard, ChessError> {
+    let moves_index = args
+        .iter()
+        .position(|&arg| arg == "moves")
+        .unwrap_or(args.len());
+
+    let mut game_board = match args.first() {
+        Some(&"startpos") => init_game_board(STANDARD_BOARD.to_string())?,
+        Some(&"fen") => init_game_board(args[1..moves_index].join(" "))?,
+        _ => {
+            return Err(ChessError::InvalidInput(
+                "expected startpos or fen".to_string(),
+            ))
+        }
+    };
+    // X-FEN can not tell Chess960 apart from standard chess if the pieces are on the standard squares
+    game_board.game.is_chess960 |= is_chess960;
+
+    for input in args.iter().skip(moves_index + 1) {
+        play_long_move(&mut game_board, input)?;
+    }
+    Ok(game_board)
+}
+
+/** go [depth N] [nodes N] [movetime MS] [wtime MS btime MS winc MS binc MS] [infinite], true if infinite */
+fn parse_go_command(args: &[&str], is_white_to_move: bool) -> (SearchLimits, bool) {
+    let mut limits = SearchLimits::default();
+    let mut is_infinite = false;
+    let mut time_left = None;
+    let mut increment = 0u64;
+
+    let mut index = 0;
+    while index < args.len() {
+        let value = args
+            .get(index + 1)
+            .and_then(|value| value.parse::<u64>().ok());
+        match (args[index], value) {
+            ("infinite", _) => is_infinite = true,
+            ("depth", Some(depth)) => limits.depth = Some(depth as u32),
+            ("nodes", Some(nodes)) => limits.nodes = Some(nodes),
+            ("movetime", Some(time)) => limits.time = Some(Duration::from_millis(time)),
+            ("wtime", Some(time)) if is_white_to_move => time_left = Some(time),
+            ("btime", Some(time)) if !is_white_to_move => time_left = Some(time),
+            ("winc", Some(time)) if is_white_to_move => increment = time,
+            ("binc", Some(time)) if !is_white_to_move => increment = time,
+            _ => {}
+        }
+        index += 1;
+    }
+
+    // a part of the time left, but never so much that the clock runs out
+    if let (None, Some(time_left)) = (limits.time, time_left) {
+        let time = (time_left / MOVES_TO_GO as u64 + increment / 2).min(time_left * 9 / 10);
+        limits.time = Some(Duration::from_millis(time.max(1)));
+    }
+
+    if !is_infinite && limits.depth.is_none() && limits.nodes.is_none() && limits.time.is_none() {
+        limits.depth = Some(DEFAULT_DEPTH);
+    }
+    (limits, is_infinite)
+}
+
+fn get_info(result: &SearchResult) -> String {
+    let score = match get_mate_in(result.score) {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", result.score),
+    };
+    let line: Vec<String> = result
+        .principal_variation
+        .iter()
+        .map(get_long_move)
+        .collect();
+    format!(
+        "info depth {} score {} nodes {} pv {}",
+        result.depth,
+        score,
+        result.nodes,
+        line.join(" ")
+    )
+}
+
+/** Stops the search and waits for it to send its move */
+fn stop_search(state: &mut UciState) {
+    if let Some(search_thread) = state.search_thread.take() {
+        state.stop.store(true, Ordering::Relaxed);
+        search_thread.join().ok();
+    }
+}
+
+fn start_search(state: &mut UciState, args: &[&str]) {
+    stop_search(state);
+
+    let (limits, is_infinite) = parse_go_command(args, state.game_board.game.is_white_to_move);
+    let game_board = state.game_board.clone();
+    let output = state.output.clone();
+    let stop = Arc::new(AtomicBool::new(false));
+    state.stop = stop.clone();
+
+    state.search_thread = Some(thread::spawn(move || {
+        let result = search(&game_board, &limits, &stop, &mut |result| {
+            write_line(&output, &get_info(result));
+        });
+
+        // the best move can not be sent before stop when searching forever
+        while is_infinite && !stop.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // 0000 is the null move, sent when the game is already over
+        let best_move = match result.best_move {
+            Some(game_move) => get_long_move(&game_move),
+            None => "0000".to_string(),
+        };
+        write_line(&output, &format!("bestmove {}", best_move));
+    }));
+}
+
+/**
+Handles one command from the GUI, the answer is written to the output of the state.
+Unknown commands are ignored as the protocol says, returns false when the engine should quit
+*/
+pub fn handle_uci_command(state: &mut UciState, line: &str) -> bool {
+    let args: Vec<&str> = line.split_whitespace().collect();
+    let (command, args) = match args.split_first() {
+        Some(split) => split,
+        None => return true,
+    };
+
+    match *command {
+        "uci" => {
+            write_line(&state.output, &format!("id name {}", ENGINE_NAME));
+            write_line(&state.output, &format!("id author {}", ENGINE_AUTHOR));
+            write_line(
+                &state.output,
+                "option name UCI_Chess960 type check default false",
+            );
+            write_line(&state.output, "uciok");
+        }
+        "isready" => write_line(&state.output, "readyok"),
+        "ucinewgame" => {
+            stop_search(state);
+            state.game_board = init_game_board(STANDARD_BOARD.to_string()).unwrap();
+        }
+        "position" => {
+            stop_search(state);
+            match parse_position_command(args, state.is_chess960) {
+                Ok(game_board) => state.game_board = game_board,
+                Err(error) => write_line(&state.output, &format!("info string {}", error)),
+            }
+        }
+        "setoption" => {
+            // setoption name UCI_Chess960 value true
+            if let ["name", "UCI_Chess960", "value", value] = args {
+                state.is_chess960 = *value == "true";
+            }
+        }
+        "go" => start_search(state, args),
+        "stop" => stop_search(state),
+        "quit" => {
+            stop_search(state);
+            return false;
+        }
+        _ => {}
+    }
+    true
+}
+
+/** Reads commands until quit or until the input is closed */
+pub fn run_uci(input: impl BufRead, output: UciOutput) {
+    let mut state = init_uci_state(output);
+    for line in input.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if !handle_uci_command(&mut state, &line) {
+            return;
+        }
+    }
+    // the GUI is gone if the input is closed
+    stop_search(&mut state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{get_fen, parse_long_move};
+
+    fn run_commands(commands: &str) -> (UciState, String) {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let mut state = init_uci_state(output.clone());
+        for line in commands.lines() {
+            if !handle_uci_command(&mut state, line) {
+                break;
+            }
+        }
+
+        // waits for the search to finish by itself
+        if let Some(search_thread) = state.search_thread.take() {
+            search_thread.join().unwrap();
+        }
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        (state, output)
+    }
+
+    #[test]
+    fn handshake_test() {
+        let (_, output) = run_commands("uci\nisready\nquit\nisready");
+        assert_eq!(
+            output,
+            "id name vinlag-chess\nid author vinlag\n\
+             option name UCI_Chess960 type check default false\nuciok\nreadyok\n"
+        );
+    }
+
+    #[test]
+    fn position_test() {
+        let (state, output) = run_commands("position startpos moves e2e4 e7e5 g1f3");
+        assert_eq!(output, "");
+        assert_eq!(
+            get_fen(&state.game_board.game).unwrap(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+
+        let (state, _) =
+            run_commands("position fen 4k3/1P6/8/8/8/8/8/4K2R w K - 0 1 moves e1g1 e8d7 b7b8n");
+        assert_eq!(
+            get_fen(&state.game_board.game).unwrap(),
+            "1N6/3k4/8/8/8/8/8/5RK1 b - - 0 2"
+        );
+
+        // an invalid position keeps the last one
+        let (state, output) =
+            run_commands("position startpos moves e2e4\nposition startpos moves e2e5");
+        assert!(output.starts_with("info string"));
+        assert_eq!(state.game_board.history.len(), 1);
+
+        // Chess960 castling is the king taking its own rook
+        let (state, _) = run_commands(
+            "setoption name UCI_Chess960 value true\n\
+             position startpos moves e2e4 e7e5 g1f3 b8c6 f1c4 g8f6 e1h1",
+        );
+        assert!(state.game_board.game.is_chess960);
+        assert_eq!(
+            get_fen(&state.game_board.game).unwrap(),
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQ1RK1 b ha - 5 4"
+        );
+
+        let (state, _) = run_commands("position startpos moves e2e4\nucinewgame");
+        assert!(state.game_board.history.is_empty());
+    }
+
+    #[test]
+    fn go_test() {
+        let (state, output) = run_commands("position startpos\ngo depth 2");
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("info depth 1 score cp "));
+        let best_move = lines[2].strip_prefix("bestmove ").unwrap();
+        assert!(parse_long_move(&state.game_board.game, best_move).is_ok());
+
+        let (_, output) =
+            run_commands("position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1\ngo movetime 1000");
+        assert!(output.contains("score mate 1 "));
+        assert!(output.ends_with("bestmove a1a8\n"));
+
+        // checkmate
+        let (_, output) = run_commands("position fen 4k3/8/8/8/8/8/r7/1r2K3 w - - 0 1\ngo");
+        assert_eq!(output, "bestmove 0000\n");
+
+        // the move is sent when the search is stopped
+        let (_, output) = run_commands("position startpos\ngo infinite\nstop");
+        assert!(output.ends_with("\n") && output.lines().last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn go_limits_test() {
+        let (limits, is_infinite) =
+            parse_go_command(&["wtime", "30000", "btime", "100", "winc", "1000"], true);
+        assert_eq!(limits.time, Some(Duration::from_millis(1500)));
+        assert!(!is_infinite);
+
+        let (limits, _) = parse_go_command(&["wtime", "30000", "btime", "100"], false);
+        assert_eq!(limits.time, Some(Duration::from_millis(3)));
+
+        let (limits, _) = parse_go_command(&[], true);
+        assert_eq!(limits.depth, Some(DEFAULT_DEPTH));
+
+        let (limits, is_infinite) = parse_go_command(&["infinite"], true);
+        assert_eq!(limits.depth, None);
+        assert!(is_infinite);
+    }
+}
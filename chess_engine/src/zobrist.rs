@@ -0,0 +1,190 @@
+use crate::bitboard::{get_color_index, get_piece_index};
+use crate::game_data::*;
+
+/** Zobrist hashing https://www.chessprogramming.org/Zobrist_Hashing */
+const BOARD_SQUARES: usize = BOARD_SIZE * BOARD_SIZE;
+
+/** 0 is the white player and 1 is the black player, then the same order as piece_bitboards */
+static PIECE_KEYS: [[[u64; BOARD_SQUARES]; 6]; 2] = generate_piece_keys();
+/** white king side, white queen side, black king side, black queen side */
+static CASTLE_KEYS: [u64; 4] = generate_keys::<4>(1);
+static EN_PASSANT_KEYS: [u64; BOARD_SIZE] = generate_keys::<BOARD_SIZE>(2);
+static BLACK_TO_MOVE_KEY: u64 = generate_keys::<1>(3)[0];
+/** 1 to 3 checks given by white and then by black, no checks has no key so the other variants are not changed */
+static CHECK_KEYS: [u64; 6] = generate_keys::<6>(4);
+/** the most pieces of one type a pocket can hold, all 16 pawns */
+const MAX_POCKET_COUNT: usize = 2 * BOARD_SIZE;
+/** 1 to 16 pieces of each type in the pocket of white and then black, an empty pocket has no key */
+static POCKET_KEYS: [u64; 2 * 5 * MAX_POCKET_COUNT] =
+    generate_keys::<{ 2 * 5 * MAX_POCKET_COUNT }>(5);
+
+/** splitmix64, the keys only have to be random looking and the same every time */
+const fn next_random(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (state, z ^ (z >> 31))
+}
+
+const fn generate_keys<const N: usize>(seed: u64) -> [u64; N] {
+    let mut keys = [0u64; N];
+    let mut state = seed.wrapping_mul(0x2545_F491_4F6C_DD1D);
+    let mut index = 0;
+    while index < N {
+        let (next_state, key) = next_random(state);
+        keys[index] = key;
+        state = next_state;
+        index += 1;
+    }
+    keys
+}
+
+const fn generate_piece_keys() -> [[[u64; BOARD_SQUARES]; 6]; 2] {
+    let mut keys = [[[0u64; BOARD_SQUARES]; 6]; 2];
+    let mut state = 0u64;
+    let mut color = 0;
+    while color < 2 {
+        let mut piece = 0;
+        while piece < 6 {
+            let mut square = 0;
+            while square < BOARD_SQUARES {
+                let (next_state, key) = next_random(state);
+                keys[color][piece][square] = key;
+                state = next_state;
+                square += 1;
+            }
+            piece += 1;
+        }
+        color += 1;
+    }
+    keys
+}
+
+/** The key of a piece on a square, 0 for an empty square */
+pub(crate) fn get_piece_key(piece_data: PieceData, square: usize) -> u64 {
+    if piece_data.piece == Piece::None {
+        return 0;
+    }
+    PIECE_KEYS[get_color_index(piece_data.is_white)][get_piece_index(piece_data.piece)][square]
+}
+
+pub(crate) fn get_en_passant_key(file: usize) -> u64 {
+    EN_PASSANT_KEYS[file]
+}
+
+/** The key of everything except the pieces, the side to move, castle rights, en passant file, checks and pockets */
+pub(crate) fn get_state_key(game: &Game) -> u64 {
+    let mut key = 0;
+
+    for (index, castle_status) in game.castle.iter().enumerate() {
+        if castle_status.can_castle_king_side {
+            key ^= CASTLE_KEYS[index * 2];
+        }
+        if castle_status.can_castle_queen_side {
+            key ^= CASTLE_KEYS[index * 2 + 1];
+        }
+    }
+
+    if let Some(en_passant_position) = game.en_passant_position {
+        key ^= EN_PASSANT_KEYS[en_passant_position.x];
+    }
+
+    if !game.is_white_to_move {
+        key ^= BLACK_TO_MOVE_KEY;
+    }
+
+    for (index, checks) in game.checks.iter().enumerate() {
+        if *checks > 0 {
+            key ^= CHECK_KEYS[index * 3 + (*checks).min(3) as usize - 1];
+        }
+    }
+
+    for (color, pocket) in game.pockets.iter().enumerate() {
+        for (piece, count) in pocket.iter().enumerate() {
+            if *count > 0 {
+                let count = (*count as usize).min(MAX_POCKET_COUNT);
+                key ^= POCKET_KEYS[(color * 5 + piece) * MAX_POCKET_COUNT + count - 1];
+            }
+        }
+    }
+
+    key
+}
+
+/** Generates the hash from scratch, the hash in Game is updated for every move so this is only needed when loading */
+pub fn get_hash(game: &Game) -> u64 {
+    let mut hash = get_state_key(game);
+    for x in 0..BOARD_SIZE {
+        for y in 0..BOARD_SIZE {
+            hash ^= get_piece_key(game.board[x][y], y * BOARD_SIZE + x);
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::*;
+    use crate::parser::*;
+
+    fn play_moves(fen: &str, moves: &[&str]) -> Gameboard {
+        let mut game_board = init_game_board(fen.to_string()).unwrap();
+        for input in moves {
+            let (move_start, move_end) = parse_move(input).unwrap();
+            let threats = get_threats(&game_board);
+            move_piece(&mut game_board, move_start, move_end, &threats, true).unwrap();
+        }
+        game_board
+    }
+
+    #[test]
+    fn transposition_test() {
+        let first = play_moves(STANDARD_BOARD, &["g1f3", "g8f6", "b1c3", "b8c6"]);
+        let second = play_moves(STANDARD_BOARD, &["b1c3", "b8c6", "g1f3", "g8f6"]);
+        assert_eq!(first.game.hash, second.game.hash);
+
+        // same pieces but the castle rights are lost
+        let third = play_moves(
+            STANDARD_BOARD,
+            &[
+                "g1f3", "g8f6", "b1c3", "b8c6", "h1g1", "h8g8", "g1h1", "g8h8",
+            ],
+        );
+        assert_ne!(first.game.hash, third.game.hash);
+
+        // back to the start position
+        let fourth = play_moves(STANDARD_BOARD, &["g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert_eq!(
+            fourth.game.hash,
+            get_board(STANDARD_BOARD.to_string()).unwrap().hash
+        );
+    }
+
+    fn check_hash(game_board: &mut Gameboard, depth: u32) {
+        assert_eq!(game_board.game.hash, get_hash(&game_board.game));
+        if depth == 0 {
+            return;
+        }
+
+        for game_move in legal_moves(game_board) {
+            let hash = game_board.game.hash;
+            let threats = get_threats(game_board);
+            move_piece(game_board, game_move.from, game_move.to, &threats, true).unwrap();
+            check_hash(game_board, depth - 1);
+            undo_move(game_board).unwrap();
+            assert_eq!(hash, game_board.game.hash);
+        }
+    }
+
+    #[test]
+    fn incremental_hash_test() {
+        // castling, en passant and promotions
+        let mut game_board = init_game_board(
+            "r3k2r/pPppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q2/PPPBBPpP/R3K2R w KQkq - 0 1".to_string(),
+        )
+        .unwrap();
+        check_hash(&mut game_board, 3);
+    }
+}
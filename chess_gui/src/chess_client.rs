@@ -1,8 +1,10 @@
 use std::io::{BufRead, BufReader, ErrorKind, Write};
 use std::net::TcpStream;
+use std::time::{Duration, Instant};
 
-use chess_engine::game_data::WinStatus;
-use chess_engine::parser;
+use chess_engine::clock::{init_clock, parse_time_control, sync_clock};
+use chess_engine::game_data::{Variant, WinStatus, GAME_END_REASONS, GAME_ONGOING};
+use chess_engine::parser::{self, parse_variant};
 
 use crate::{get_loaded_game, MainState};
 
@@ -10,6 +12,8 @@ pub(crate) struct Client {
     stream: TcpStream,
     pub(crate) is_player: bool,
     pub(crate) ip: String,
+    // the rules the boards from the server are read with
+    variant: Variant,
 }
 
 impl Client {
@@ -39,6 +43,7 @@ pub(crate) fn start_client(mut ip: String) -> Option<Client> {
                 stream,
                 is_player: true,
                 ip,
+                variant: Variant::Standard,
             });
         }
     } else {
@@ -52,6 +57,7 @@ fn send_pending_move(main_state: &mut MainState) {
     if let Some((pending_move_from, pending_move_to, promotion)) =
         main_state.active_game.pending_move
     {
+        // a drop in crazyhouse is sent as the square twice with the dropped piece, like e4e4n
         let mut send_msg = "move:".to_string();
         send_msg.push_str(&parser::get_move(pending_move_from, pending_move_to));
         send_msg.push(match promotion {
@@ -59,7 +65,7 @@ fn send_pending_move(main_state: &mut MainState) {
             chess_engine::game_data::Piece::Rook => 'r',
             chess_engine::game_data::Piece::Queen => 'q',
             chess_engine::game_data::Piece::None => '-',
-            chess_engine::game_data::Piece::Pawn => '-',
+            chess_engine::game_data::Piece::Pawn => 'p',
             chess_engine::game_data::Piece::Knight => 'n',
             chess_engine::game_data::Piece::King => '-',
         });
@@ -75,6 +81,11 @@ fn send_pending_move(main_state: &mut MainState) {
     }
 }
 
+/** Tells the server that the player gives up, returns false if the message could not be sent */
+pub(crate) fn send_resign(client: &mut Client) -> bool {
+    client.stream.write_all(b"resign:;").is_ok()
+}
+
 fn handle_message(main_state: &mut MainState, msg: String) {
     let split: Vec<String> = msg.split(":").map(|s| s.to_string()).collect();
     if split.len() != 2 {
@@ -83,8 +94,18 @@ fn handle_message(main_state: &mut MainState, msg: String) {
 
     let action = &split[0];
     let input = &split[1];
+    let variant = match &main_state.client {
+        Some(client) => client.variant,
+        None => Variant::Standard,
+    };
 
     match &action[..] {
+        // variant:[NAME], sent before the board
+        "variant" => {
+            if let (Some(client), Ok(variant)) = (&mut main_state.client, parse_variant(input)) {
+                client.variant = variant;
+            }
+        }
         "playertype" => {
             if let Some(client) = &mut main_state.client {
                 match input.chars().nth(0) {
@@ -96,8 +117,8 @@ fn handle_message(main_state: &mut MainState, msg: String) {
             }
         }
         "board" => {
-            if let Some((game, threats)) = get_loaded_game(input.to_string()) {
-                main_state.active_game.win_status = WinStatus::Nothing;
+            if let Some((game, threats)) = get_loaded_game(input.to_string(), variant) {
+                main_state.active_game.result = GAME_ONGOING;
                 main_state.active_game.game = game;
                 main_state.active_game.active_threats = threats;
             }
@@ -111,13 +132,51 @@ fn handle_message(main_state: &mut MainState, msg: String) {
                 _ => WinStatus::Nothing,
             };
 
-            main_state.active_game.win_status = win_status;
+            main_state.active_game.result.outcome = win_status;
+            main_state.active_game.result.reason = None;
 
-            if let Some((game, threats)) = get_loaded_game(input[1..].to_string()) {
+            if let Some((game, threats)) = get_loaded_game(input[1..].to_string(), variant) {
                 main_state.active_game.game = game;
                 main_state.active_game.active_threats = threats;
             }
         }
+        // reason:[REASON], sent after end
+        "reason" => {
+            main_state.active_game.result.reason = GAME_END_REASONS
+                .iter()
+                .copied()
+                .find(|reason| reason.to_string() == *input);
+        }
+        // clock:[TIME CONTROL] [WHITE MS] [BLACK MS] [RUNNING 0/1], always sent after the board
+        "clock" => {
+            let split: Vec<&str> = input.split(' ').collect();
+            if split.len() != 4 {
+                return;
+            }
+
+            if let (Ok(time_control), Ok(white_time), Ok(black_time)) = (
+                parse_time_control(split[0]),
+                split[1].parse::<u64>(),
+                split[2].parse::<u64>(),
+            ) {
+                let is_white_to_move = main_state.active_game.game.game.is_white_to_move;
+                let clock = main_state
+                    .active_game
+                    .clock
+                    .get_or_insert_with(|| init_clock(time_control.clone(), is_white_to_move));
+                clock.time_control = time_control;
+                sync_clock(
+                    clock,
+                    [
+                        Duration::from_millis(white_time),
+                        Duration::from_millis(black_time),
+                    ],
+                    is_white_to_move,
+                    split[3] == "1",
+                    Instant::now(),
+                );
+            }
+        }
         _ => (),
     }
 }
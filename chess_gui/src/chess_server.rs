@@ -2,12 +2,14 @@ use std::io::{BufRead, BufReader, ErrorKind, Write};
 use std::net::{SocketAddr, TcpListener, TcpStream};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread;
+use std::time::Instant;
 
-use chess_engine::game_data::{Gameboard, Piece, WinStatus};
-use chess_engine::logic::get_game_state;
+use chess_engine::clock::{get_time_left, is_clock_running};
+use chess_engine::game_data::{GameResult, Piece, WinStatus};
+use chess_engine::logic::{get_game_state, resign};
 use chess_engine::parser;
 
-use crate::{move_piece_with_state, MainState};
+use crate::{move_piece_with_state, ActiveGame, MainState};
 
 const IP_PORT: u16 = 1337;
 
@@ -68,31 +70,50 @@ pub(crate) fn start_server() -> Option<Server> {
     None
 }
 
-fn get_state_msg(gameboard: &Gameboard, win_status: WinStatus) -> Option<String> {
-    let fen = &parser::get_fen(&gameboard.game);
+/**
+The board or end message after variant:[NAME], the end message is followed by reason:[REASON] if it is known
+and clock:[TIME CONTROL] [WHITE MS] [BLACK MS] [RUNNING 0/1] is added if the game is timed
+*/
+fn get_state_msg(active_game: &ActiveGame, result: GameResult) -> Option<String> {
+    let fen = &parser::get_fen(&active_game.game.game).ok();
     if fen.is_none() {
         return None;
     }
-    let mut win_status_prefix = match win_status {
+    let mut win_status_prefix = format!("variant:{};", active_game.game.game.variant);
+    win_status_prefix.push_str(match result.outcome {
         WinStatus::WhiteWon => "end:w",
         WinStatus::BlackWon => "end:b",
         WinStatus::Tie => "end:-",
         WinStatus::Nothing => "board:",
-    }
-    .to_string();
+    });
     win_status_prefix.push_str(&fen.as_ref().unwrap());
 
+    if let Some(reason) = result.reason {
+        win_status_prefix.push_str(&format!(";reason:{}", reason));
+    }
+
+    if let Some(clock) = &active_game.clock {
+        let now = Instant::now();
+        win_status_prefix.push_str(&format!(
+            ";clock:{} {} {} {}",
+            clock.time_control,
+            get_time_left(clock, true, now).as_millis(),
+            get_time_left(clock, false, now).as_millis(),
+            if is_clock_running(clock) { 1 } else { 0 }
+        ));
+    }
+
     return Some(win_status_prefix);
 }
 
 fn get_status_msg(main_state: &mut MainState) -> Option<String> {
-    let win_status = get_game_state(
+    let result = get_game_state(
         &main_state.active_game.game,
         &main_state.active_game.active_threats,
         true,
     );
 
-    get_state_msg(&main_state.active_game.game, win_status)
+    get_state_msg(&main_state.active_game, result)
 }
 
 fn connect_clients(main_state: &mut MainState) {
@@ -246,8 +267,11 @@ fn handle_message(main_state: &mut MainState, msg: String, addr: SocketAddr) {
                     }
                 } else {
                     let (move_from, move_to) = match parser::parse_move(&input[0..4].to_string()) {
-                        Some(t) => t,
-                        None => return,
+                        Ok(t) => t,
+                        Err(error) => {
+                            println!("{}", error);
+                            return;
+                        }
                     };
 
                     let promotion = match input.chars().nth(4) {
@@ -255,6 +279,7 @@ fn handle_message(main_state: &mut MainState, msg: String, addr: SocketAddr) {
                         Some('r') => Piece::Rook,
                         Some('b') => Piece::Bishop,
                         Some('n') => Piece::Knight,
+                        Some('p') => Piece::Pawn,
                         None => Piece::Queen,
                         _ => Piece::Queen,
                     };
@@ -262,14 +287,17 @@ fn handle_message(main_state: &mut MainState, msg: String, addr: SocketAddr) {
                     let piece_data =
                         main_state.active_game.game.game.board[move_from.x][move_from.y];
 
+                    // a drop is the same square twice, the client can only drop when black is to move
+                    let is_drop =
+                        move_from == move_to && !main_state.active_game.game.game.is_white_to_move;
+
                     //validates move and retuns state
-                    if !piece_data.is_white && piece_data.piece != Piece::None {
-                        let win_status =
+                    if is_drop || (!piece_data.is_white && piece_data.piece != Piece::None) {
+                        let result =
                             move_piece_with_state(main_state, move_from, move_to, promotion);
-                        main_state.active_game.win_status = win_status;
+                        main_state.active_game.result = result;
 
-                        if let Some(state) = get_state_msg(&main_state.active_game.game, win_status)
-                        {
+                        if let Some(state) = get_state_msg(&main_state.active_game, result) {
                             send_msg = state;
                         } else {
                             send_msg = INVALID_STATE_MSG.to_string()
@@ -285,6 +313,35 @@ fn handle_message(main_state: &mut MainState, msg: String, addr: SocketAddr) {
                 }
             }
         }
+        // resign:; the client playing black gives up
+        "resign" => {
+            if let Some(server) = &main_state.server {
+                let is_invalid_client = Some(addr) != server.move_client;
+                let resign_error = if is_invalid_client {
+                    Some("err:invalid_client")
+                } else if resign(&mut main_state.active_game.game, false).is_err() {
+                    Some("err:game_over")
+                } else {
+                    None
+                };
+
+                if let Some(error) = resign_error {
+                    send_to_all = false;
+                    if let Some(state) = get_status_msg(main_state) {
+                        send_msg = state + ";" + error;
+                    } else {
+                        send_msg = INVALID_STATE_MSG.to_string()
+                    }
+                } else {
+                    main_state.active_game.result = main_state.active_game.game.result.unwrap();
+                    if let Some(state) = get_status_msg(main_state) {
+                        send_msg = state;
+                    } else {
+                        send_msg = INVALID_STATE_MSG.to_string()
+                    }
+                }
+            }
+        }
         _ => return,
     }
 
@@ -309,17 +366,12 @@ fn handle_message(main_state: &mut MainState, msg: String, addr: SocketAddr) {
 }
 
 fn send_pending_move(main_state: &mut MainState) {
-    if let Some(server) = &mut main_state.server {
-        if main_state.active_game.penging_send {
-            main_state.active_game.penging_send = false;
-            let win_status = get_game_state(
-                &main_state.active_game.game,
-                &main_state.active_game.active_threats,
-                true,
-            );
-            if let Some(mut send_msg) = get_state_msg(&main_state.active_game.game, win_status) {
-                send_msg.push(';');
-                let buff = send_msg.into_bytes();
+    if main_state.server.is_some() && main_state.active_game.penging_send {
+        main_state.active_game.penging_send = false;
+        if let Some(mut send_msg) = get_status_msg(main_state) {
+            send_msg.push(';');
+            let buff = send_msg.into_bytes();
+            if let Some(server) = &mut main_state.server {
                 for client in &mut server.clients {
                     let _write_error = client.write_all(&buff);
                 }
@@ -7,13 +7,21 @@ mod render;
 
 use std::collections::HashSet;
 use std::process::exit;
+use std::time::Instant;
 use std::{env, path};
 
-use chess_engine::game_data::{Gameboard, Piece, Position, ThreatMap, WinStatus};
+use chess_engine::clock::{
+    get_time_result, init_clock, parse_time_control, pause_clock, press_clock, start_clock, Clock,
+    TimeControl,
+};
+use chess_engine::game_data::{
+    self, GameEndReason, Gameboard, Piece, Position, ThreatMap, Variant, WinStatus, GAME_ONGOING,
+};
 use chess_engine::logic::{
-    get_all_valid_moves, get_game_state, get_threats, move_piece, promote_pawn,
+    drop_piece, get_all_valid_moves, get_game_state, get_threats, get_valid_drops, move_piece,
+    promote_pawn, resign,
 };
-use chess_engine::parser::STANDARD_BOARD;
+use chess_engine::parser::{get_start_fen, init_variant_game_board, parse_variant};
 use ggez::event;
 use ggez::event::MouseButton;
 use ggez::graphics::{self, Font, PxScale};
@@ -47,16 +55,19 @@ struct FontSet {
 struct ActiveGame {
     game: Gameboard,
     active_threats: ThreatMap,
-    win_status: WinStatus,
+    result: game_data::GameResult,
     selected_square: Option<Position>,
+    // the piece that is dragged from the pocket in crazyhouse
+    selected_drop: Option<Piece>,
     hover_position: Option<Vec2>,
     possible_moves: Option<HashSet<Position>>,
     penging_send: bool,
     pending_move: Option<(Position, Position, Piece)>,
+    clock: Option<Clock>,
 }
 
 struct Icons {
-    //surrender: graphics::Image,
+    surrender: graphics::Image,
     replay: graphics::Image,
     settings: graphics::Image,
     //arrow_back: graphics::Image,
@@ -73,6 +84,7 @@ enum Action {
     StartClient,
     Disconnect,
     Restart,
+    Resign,
     Quit,
     None,
 }
@@ -108,6 +120,10 @@ struct InputStatus {
 
 pub struct MainState {
     frame: u64,
+    // the time control of new local and hosted games, None for untimed games
+    time_control: Option<TimeControl>,
+    // the rules of new local and hosted games
+    variant: Variant,
     server: Option<chess_server::Server>,
     client: Option<chess_client::Client>,
     render_config: RenderConfig,
@@ -155,16 +171,23 @@ macro_rules! add_sprite_sheet {
     }};
 }
 
-fn get_loaded_game(board: String) -> Option<(Gameboard, ThreatMap)> {
-    if let Some(board) = chess_engine::parser::init_game_board(board) {
-        let threats = get_threats(&board);
-        return Some((board, threats));
+fn get_loaded_game(board: String, variant: Variant) -> Option<(Gameboard, ThreatMap)> {
+    match init_variant_game_board(board, variant) {
+        Ok(board) => {
+            let threats = get_threats(&board);
+            return Some((board, threats));
+        }
+        Err(error) => println!("Could not load game: {}", error),
     }
     return None;
 }
 
 impl MainState {
-    fn new(ctx: &mut Context) -> GameResult<MainState> {
+    fn new(
+        ctx: &mut Context,
+        time_control: Option<TimeControl>,
+        variant: Variant,
+    ) -> GameResult<MainState> {
         println!("Game Init...");
         // init sprites and fonts
 
@@ -186,7 +209,7 @@ impl MainState {
         };
 
         let icons = Icons {
-            //surrender: addpng!(ctx, "surrender"),
+            surrender: add_png!(ctx, "surrender"),
             replay: add_png!(ctx, "replay"),
             settings: add_png!(ctx, "settings"),
             //arrow_back: addpng!(ctx, "arrow_back"),
@@ -197,14 +220,20 @@ impl MainState {
             host: add_png!(ctx, "host"),
         };
 
-        let (game, threats) = get_loaded_game(STANDARD_BOARD.to_string()).unwrap();
+        let (game, threats) = get_loaded_game(get_start_fen(variant), variant).unwrap();
 
         let message = None;
 
+        let clock = time_control
+            .clone()
+            .map(|time_control| init_clock(time_control, game.game.is_white_to_move));
+
         let s = MainState {
             server: None,
             client: None,
             frame: 0,
+            time_control,
+            variant,
             render_config: RenderConfig {
                 spritesets: vec![regular_sprites, horsey_sprites, emoji_sprites],
                 fontsets: vec![regular_font, nice_font],
@@ -216,11 +245,13 @@ impl MainState {
                 game,
                 active_threats: threats,
                 selected_square: None,
+                selected_drop: None,
                 hover_position: None,
                 possible_moves: None,
                 penging_send: true,
-                win_status: WinStatus::Nothing,
+                result: GAME_ONGOING,
                 pending_move: None,
+                clock,
             },
             input_staus: InputStatus {
                 pos_x: 0.0,
@@ -237,31 +268,76 @@ impl MainState {
     }
 }
 
-/** Will move a piece, promote it and regenerate the threatmap and will return the winstatus */
+/**
+Will move a piece, promote it, regenerate the threatmap and press the clock and will return the result.
+A drop in crazyhouse is given as a move from and to the same square with the dropped piece as promotion
+*/
 fn move_piece_with_state(
     state: &mut MainState,
     move_from: Position,
     move_to: Position,
     promotion: Piece,
-) -> WinStatus {
-    if move_piece(
-        &mut state.active_game.game,
-        move_from,
-        move_to,
-        &state.active_game.active_threats,
-        false,
-    ) {
-        promote_pawn(&mut state.active_game.game.game, promotion);
+) -> game_data::GameResult {
+    let now = Instant::now();
+
+    // the game has ended without a move, like a resignation
+    if let Some(result) = state.active_game.game.result {
+        println!("Invalid move: the game is over");
+        return result;
+    }
 
-        let threats = get_threats(&state.active_game.game);
-        let win_status = get_game_state(&mut state.active_game.game, &threats, true);
-        state.active_game.active_threats = threats;
+    // a move can not be played after the flag has fallen, the client follows the clock of the server
+    if state.client.is_none() {
+        if let Some(clock) = &mut state.active_game.clock {
+            let result = get_time_result(clock, &state.active_game.game.game, now);
+            if result.outcome != WinStatus::Nothing {
+                println!("Invalid move: out of time");
+                state.active_game.game.result = Some(result);
+                return result;
+            }
+        }
+    }
 
-        return win_status;
+    let played = if move_from == move_to {
+        drop_piece(
+            &mut state.active_game.game,
+            promotion,
+            move_to,
+            &state.active_game.active_threats,
+        )
     } else {
-        println!("Invalid move")
+        move_piece(
+            &mut state.active_game.game,
+            move_from,
+            move_to,
+            &state.active_game.active_threats,
+            false,
+        )
+    };
+
+    match played {
+        Ok(()) => {
+            // fails if the move was not a promotion, then there is nothing to do
+            let _ = promote_pawn(&mut state.active_game.game, promotion);
+
+            let threats = get_threats(&state.active_game.game);
+            let result = get_game_state(&mut state.active_game.game, &threats, true);
+            state.active_game.active_threats = threats;
+
+            if let Some(clock) = &mut state.active_game.clock {
+                press_clock(clock, now);
+                if result.outcome == WinStatus::Nothing {
+                    start_clock(clock, now);
+                } else {
+                    pause_clock(clock, now);
+                }
+            }
+
+            return result;
+        }
+        Err(error) => println!("Invalid move: {}", error),
     }
-    return WinStatus::Nothing;
+    return GAME_ONGOING;
 }
 
 fn do_input_logic(main_state: &mut MainState) {
@@ -273,21 +349,25 @@ fn do_input_logic(main_state: &mut MainState) {
         let mouse_pos = Vec2::new(input.pos_x, input.pos_y);
         state.hover_position = Some(mouse_pos);
         if input.mouse_clicked {
+            let is_white_to_move = state.game.game.is_white_to_move;
+
+            // cant touch a piece if the user does not have controll over it (server and client)
+            // server is always white and cant move black pieces, reverse for client
+            // client can also only move pices when it is a player
+            let can_move = (main_state.server.is_none()
+                || (main_state.server.is_some() && is_white_to_move))
+                && (main_state.client.is_none()
+                    || (main_state.client.is_some()
+                        && !is_white_to_move
+                        && main_state.client.as_ref().unwrap().is_player));
+
+            state.selected_square = None;
+            state.selected_drop = None;
             if let Some(selected_square) = get_square_from_screen(mouse_pos) {
                 let piece_data = state.game.game.board[selected_square.x][selected_square.y];
-                let is_white_to_move = state.game.game.is_white_to_move;
-
-                // cant touch a piece if it is none or if the user does not have controll over it (server and client)
-                // server is always white and cant move black pieces, reverse for client
-                // client can also only move pices when it is a player
-                if piece_data.is_white == is_white_to_move
+                if can_move
+                    && piece_data.is_white == is_white_to_move
                     && piece_data.piece != Piece::None
-                    && (main_state.server.is_none()
-                        || (main_state.server.is_some() && is_white_to_move))
-                    && (main_state.client.is_none()
-                        || (main_state.client.is_some()
-                            && !is_white_to_move
-                            && main_state.client.as_ref().unwrap().is_player))
                 {
                     state.selected_square = Some(selected_square);
                     state.possible_moves = Some(get_all_valid_moves(
@@ -295,11 +375,14 @@ fn do_input_logic(main_state: &mut MainState) {
                         &state.active_threats,
                         &selected_square,
                     ));
-                } else {
-                    state.selected_square = None;
                 }
-            } else {
-                state.selected_square = None;
+            } else if let Some(piece) = get_pocket_piece_from_screen(mouse_pos, &state.game.game) {
+                // in crazyhouse a piece is dragged from the pocket to the board
+                if can_move {
+                    state.selected_drop = Some(piece);
+                    state.possible_moves =
+                        Some(get_valid_drops(&state.game, &state.active_threats, piece));
+                }
             }
         }
     } else {
@@ -308,7 +391,7 @@ fn do_input_logic(main_state: &mut MainState) {
 
             //check if move is valid, as all the moves have already been checked a simple contains marks it as valid
             if move_square.is_some()
-                && state.selected_square.is_some()
+                && (state.selected_square.is_some() || state.selected_drop.is_some())
                 && state.possible_moves.is_some()
                 && state
                     .possible_moves
@@ -317,10 +400,13 @@ fn do_input_logic(main_state: &mut MainState) {
                     .contains(&move_square.unwrap())
             {
                 let move_to = move_square.unwrap();
-                let move_from = state.selected_square.unwrap();
-                let win_status =
-                    move_piece_with_state(main_state, move_from, move_to, Piece::Queen);
-                main_state.active_game.win_status = win_status;
+                // a drop is played from and to the same square with the dropped piece
+                let (move_from, promotion) = match state.selected_drop {
+                    Some(piece) => (move_to, piece),
+                    None => (state.selected_square.unwrap(), Piece::Queen),
+                };
+                let result = move_piece_with_state(main_state, move_from, move_to, promotion);
+                main_state.active_game.result = result;
 
                 // if server is active ping all clients with an update
                 if main_state.server.is_some() {
@@ -330,7 +416,7 @@ fn do_input_logic(main_state: &mut MainState) {
                 // if it is client then send the move to the server
                 //TODO Promotion to rook, knight and bishop
                 if main_state.client.is_some() {
-                    main_state.active_game.pending_move = Some((move_from, move_to, Piece::Queen));
+                    main_state.active_game.pending_move = Some((move_from, move_to, promotion));
                 }
             }
         } else {
@@ -345,6 +431,10 @@ fn handle_action(action: Action, state: &mut MainState) {
     match action {
         Action::StartClient => {
             state.client = chess_client::start_client(state.input_staus.ip_input.clone());
+            if state.client.is_some() {
+                // the server sends its clock if the game is timed
+                state.active_game.clock = None;
+            }
         }
         Action::StartServer => {
             state.server = chess_server::start_server();
@@ -354,14 +444,42 @@ fn handle_action(action: Action, state: &mut MainState) {
             if state.client.is_some() {
                 return;
             }
-            let (game, threats) = get_loaded_game(STANDARD_BOARD.to_string()).unwrap();
+            let (game, threats) =
+                get_loaded_game(get_start_fen(state.variant), state.variant).unwrap();
             state.active_game.game = game;
-            state.active_game.win_status = WinStatus::Nothing;
+            state.active_game.result = GAME_ONGOING;
             state.active_game.active_threats = threats;
             state.active_game.hover_position = None;
             state.active_game.possible_moves = None;
             state.active_game.selected_square = None;
+            state.active_game.selected_drop = None;
             state.active_game.penging_send = true;
+            state.active_game.clock = state
+                .time_control
+                .clone()
+                .map(|time_control| init_clock(time_control, true));
+        }
+        Action::Resign => {
+            // the host plays white and the client black, in a local game the player to move resigns
+            if let Some(client) = &mut state.client {
+                // the server sends the result back
+                if client.is_player && !chess_client::send_resign(client) {
+                    println!("Could not send resignation");
+                }
+                return;
+            }
+
+            let is_white = state.server.is_some() || state.active_game.game.game.is_white_to_move;
+            match resign(&mut state.active_game.game, is_white) {
+                Ok(()) => {
+                    state.active_game.result = state.active_game.game.result.unwrap();
+                    state.active_game.selected_square = None;
+                    state.active_game.selected_drop = None;
+                    state.active_game.possible_moves = None;
+                    state.active_game.penging_send = true;
+                }
+                Err(error) => println!("Could not resign: {}", error),
+            }
         }
         Action::Quit => exit(0),
         Action::None => {}
@@ -379,16 +497,74 @@ fn handle_action(action: Action, state: &mut MainState) {
     }
 }
 
+/**
+Runs the clock of the player to move from the first move until the game is over and checks if the flag has fallen.
+A local game is paused while a popup is open, the client only shows the clock that the server sends
+*/
+fn update_clock(main_state: &mut MainState) {
+    if main_state.client.is_some() {
+        return;
+    }
+
+    let now = Instant::now();
+    let is_local = main_state.server.is_none();
+    let state = &mut main_state.active_game;
+    if let Some(clock) = &mut state.clock {
+        if state.result.outcome != WinStatus::Nothing
+            || state.game.history.is_empty()
+            || (is_local && main_state.active_message.is_some())
+        {
+            pause_clock(clock, now);
+            return;
+        }
+
+        start_clock(clock, now);
+        let result = get_time_result(clock, &state.game.game, now);
+        if result.outcome != WinStatus::Nothing {
+            state.game.result = Some(result);
+            state.result = result;
+            state.penging_send = true;
+        }
+    }
+}
+
+fn get_reason_text(reason: GameEndReason) -> &'static str {
+    match reason {
+        GameEndReason::Checkmate => "schackmatt",
+        GameEndReason::Resignation => "uppgivet",
+        GameEndReason::Timeout => "tiden tog slut",
+        GameEndReason::Agreement => "överenskommelse",
+        GameEndReason::Stalemate => "patt",
+        GameEndReason::ThreefoldRepetition => "trefaldig upprepning",
+        GameEndReason::FivefoldRepetition => "femfaldig upprepning",
+        GameEndReason::FiftyMoveRule => "50-dragsregeln",
+        GameEndReason::SeventyFiveMoveRule => "75-dragsregeln",
+        GameEndReason::InsufficientMaterial => "otillräckligt material",
+        GameEndReason::DeadPosition => "död ställning",
+        GameEndReason::KingCaptured => "kungen togs",
+        GameEndReason::ThirdCheck => "tredje schacken",
+        GameEndReason::KingInCenter => "kungen nådde mitten",
+        GameEndReason::AllPiecesLost => "alla pjäser förlorade",
+        GameEndReason::KingInGoal => "kungen nådde åttonde raden",
+    }
+}
+
 fn update_win_status(main_state: &mut MainState) {
     if main_state.active_message.is_none() {
-        if main_state.active_game.win_status != WinStatus::Nothing {
+        let result = main_state.active_game.result;
+        if result.outcome != WinStatus::Nothing {
             // updates the popup message asking to play again
-            let text = match main_state.active_game.win_status {
-                WinStatus::Tie => "Oavgjort, Spela igen?".to_string(),
-                WinStatus::WhiteWon => "Vit vann, Spela igen?".to_string(),
-                WinStatus::BlackWon => "Svart vann, Spela igen?".to_string(),
+            let mut text = match result.outcome {
+                WinStatus::Tie => "Oavgjort".to_string(),
+                WinStatus::WhiteWon => "Vit vann".to_string(),
+                WinStatus::BlackWon => "Svart vann".to_string(),
                 WinStatus::Nothing => "".to_string(),
             };
+            if let Some(reason) = result.reason {
+                text.push_str(", ");
+                text.push_str(get_reason_text(reason));
+            }
+            text.push_str("\nSpela igen?");
 
             main_state.input_staus.mouse_released = false;
             main_state.active_message = Some(PendingAction {
@@ -403,7 +579,7 @@ fn update_win_status(main_state: &mut MainState) {
         // will remove message if it is a promt to restart if the game has been reset by host
         // also will ensure that you cant pop up a menu to restart mid game as client
         if main_state.client.is_some()
-            && main_state.active_game.win_status == WinStatus::Nothing
+            && main_state.active_game.result.outcome == WinStatus::Nothing
             && main_state.active_message.as_ref().unwrap().confirm_value == Action::Restart
         {
             main_state.active_message = None;
@@ -429,6 +605,15 @@ fn render_header_buttons(ctx: &mut Context, main_state: &mut MainState) {
                 })
             }
             1 => {
+                main_state.active_message = Some(PendingAction {
+                    text: "Ge upp?".to_string(),
+                    confirm: main_state.render_config.icons.confirm.clone(),
+                    cancel: main_state.render_config.icons.exit.clone(),
+                    confirm_value: Action::Resign,
+                    cancel_value: Action::None,
+                })
+            }
+            2 => {
                 main_state.active_message = Some(PendingAction {
                     text: "Spela igen?".to_string(),
                     confirm: main_state.render_config.icons.confirm.clone(),
@@ -437,7 +622,7 @@ fn render_header_buttons(ctx: &mut Context, main_state: &mut MainState) {
                     cancel_value: Action::None,
                 })
             }
-            2 => {
+            3 => {
                 // this simply changes the chess pieces images
                 main_state.render_config.active_sprites_index += 1;
                 if main_state.render_config.active_sprites_index
@@ -446,7 +631,7 @@ fn render_header_buttons(ctx: &mut Context, main_state: &mut MainState) {
                     main_state.render_config.active_sprites_index = 0
                 }
             }
-            3 => {
+            4 => {
                 if is_connected {
                     main_state.active_message = Some(PendingAction {
                         text: "Avbryt anslutng?".to_string(),
@@ -465,7 +650,7 @@ fn render_header_buttons(ctx: &mut Context, main_state: &mut MainState) {
                     })
                 }
             }
-            4 => {
+            5 => {
                 main_state.active_message = Some(PendingAction {
                     text: "Anslut till en server\nIP: ".to_string(),
                     confirm: main_state.render_config.icons.confirm.clone(),
@@ -485,6 +670,7 @@ impl event::EventHandler<ggez::GameError> for MainState {
         let _server_result = chess_server::server_loop(self);
         chess_client::client_loop(self);
         do_input_logic(self);
+        update_clock(self);
         update_win_status(self);
         Ok(())
     }
@@ -496,6 +682,7 @@ impl event::EventHandler<ggez::GameError> for MainState {
         render_board(ctx)?;
         render_numbers(ctx, &self.render_config)?;
         render_multiplayer_status(ctx, &self);
+        render_clocks(ctx, self)?;
         render_highlight(ctx, self.active_game.selected_square, HIGHLIGHT_COLOR)?;
         if self.active_game.possible_moves.is_some() {
             for pos in self.active_game.possible_moves.as_ref().unwrap() {
@@ -504,6 +691,7 @@ impl event::EventHandler<ggez::GameError> for MainState {
         }
 
         render_pieces(ctx, &self.render_config, &mut self.active_game)?;
+        render_pockets(ctx, &self.render_config, &self.active_game)?;
 
         if self.active_message.is_none() {
             render_header_buttons(ctx, self);
@@ -632,8 +820,26 @@ pub fn main() -> GameResult {
                     .borderless(false),
             )
             .add_resource_path(resource_dir);
+    // the arguments are a time control like 5+3 or 40/90+30,30+30 and a variant like three-check,
+    // without a time control the game is untimed and without a variant it is standard chess
+    let mut time_control = None;
+    let mut variant = Variant::Standard;
+    for input in env::args().skip(1) {
+        if let Ok(parsed_variant) = parse_variant(&input) {
+            variant = parsed_variant;
+            continue;
+        }
+        match parse_time_control(&input) {
+            Ok(parsed_time_control) => time_control = Some(parsed_time_control),
+            Err(error) => {
+                println!("Could not read time control: {}", error);
+                exit(1);
+            }
+        }
+    }
+
     let (mut ctx, event_loop) = cb.build()?;
-    let state = MainState::new(&mut ctx)?;
+    let state = MainState::new(&mut ctx, time_control, variant)?;
     println!("Starting game...");
     event::run(ctx, event_loop, state);
 }
@@ -1,4 +1,9 @@
-use chess_engine::game_data::{Piece, PieceData, Position, BOARD_SIZE};
+use std::time::Instant;
+
+use chess_engine::clock::{format_time, get_time_left, is_clock_running};
+use chess_engine::game_data::{
+    Game, Piece, PieceData, Position, Variant, BOARD_SIZE, POCKET_PIECES,
+};
 use ggez::graphics::{self, Color, Rect};
 use ggez::{Context, GameError, GameResult};
 use glam::*;
@@ -17,6 +22,8 @@ const BOARD_RENDER_START: (f32, f32) = (
     SCREEN_SIZE.0 / 2.0 - BOARD_RENDER_SIZE / 2.0,
     SCREEN_SIZE.1 / 2.0 - BOARD_RENDER_SIZE / 2.0,
 );
+// the pockets in crazyhouse fills the margin beside the board
+const POCKET_SLOT_SIZE: f32 = BOARD_RENDER_START.0;
 
 const BLACK_BOARD_COLOR: Color = Color {
     r: 0.4367,
@@ -108,6 +115,38 @@ pub fn get_square_from_screen(mouse: Vec2) -> Option<Position> {
     return Some(Position { x, y });
 }
 
+/** White has the pocket to the left of the board from the bottom and black to the right from the top */
+fn get_pocket_render_pos(index: usize, is_white: bool) -> Vec2 {
+    if is_white {
+        Vec2::new(
+            0.0,
+            BOARD_RENDER_START.1 + BOARD_RENDER_SIZE - (index + 1) as f32 * POCKET_SLOT_SIZE,
+        )
+    } else {
+        Vec2::new(
+            SCREEN_SIZE.0 - POCKET_SLOT_SIZE,
+            BOARD_RENDER_START.1 + index as f32 * POCKET_SLOT_SIZE,
+        )
+    }
+}
+
+/** The piece under the mouse in the pocket of the player to move, None if there is no such piece in the pocket */
+pub fn get_pocket_piece_from_screen(mouse: Vec2, game: &Game) -> Option<Piece> {
+    if game.variant != Variant::Crazyhouse {
+        return None;
+    }
+
+    let pocket = game.pockets[if game.is_white_to_move { 0 } else { 1 }];
+    let size = Vec2::new(POCKET_SLOT_SIZE, POCKET_SLOT_SIZE);
+    for index in 0..POCKET_PIECES.len() {
+        let pos = get_pocket_render_pos(index, game.is_white_to_move);
+        if pocket[index] > 0 && is_inside_square(mouse.x, mouse.y, pos, size) {
+            return Some(POCKET_PIECES[index]);
+        }
+    }
+    None
+}
+
 fn get_piece_image(
     id: Piece,
     is_white: bool,
@@ -266,11 +305,63 @@ pub(crate) fn render_multiplayer_status(ctx: &mut Context, state: &MainState) {
     }
 }
 
+/** Renders the time left of both players under the board, the clock that is running is highlighted */
+pub(crate) fn render_clocks(ctx: &mut Context, state: &MainState) -> GameResult<()> {
+    if let Some(clock) = &state.active_game.clock {
+        let active_font = &state.render_config.fontsets[state.render_config.active_fontset_index];
+        let now = Instant::now();
+        let size = Vec2::new(220.0, 50.0);
+        let pos_y = BOARD_RENDER_START.1 + BOARD_RENDER_SIZE + 5.0;
+
+        for is_white in [true, false] {
+            let pos = Vec2::new(
+                if is_white {
+                    BOARD_RENDER_START.0
+                } else {
+                    BOARD_RENDER_START.0 + BOARD_RENDER_SIZE - size.x
+                },
+                pos_y,
+            );
+
+            let is_running = is_clock_running(clock) && clock.is_white_to_move == is_white;
+            render_round_rect(
+                ctx,
+                pos,
+                size,
+                if is_running {
+                    BUTTON_COLOR_SELECTED
+                } else {
+                    BUTTON_COLOR
+                },
+            )?;
+
+            let time_left = get_time_left(clock, is_white, now);
+            let mut text_msg = if is_white { "Vit " } else { "Svart " }.to_string();
+            text_msg.push_str(&format_time(time_left));
+
+            let mut text = graphics::Text::new(text_msg);
+            text.set_font(active_font.font, active_font.font_size);
+            graphics::draw(
+                ctx,
+                &text,
+                graphics::DrawParam::new()
+                    .dest(pos + size / 2.0)
+                    .offset(Vec2::new(0.5, 0.5))
+                    .color(if time_left.is_zero() {
+                        ERROR_COLOR
+                    } else if is_running {
+                        CONFIRM_COLOR
+                    } else {
+                        Color::WHITE
+                    }),
+            )?;
+        }
+    }
+    Ok(())
+}
+
 pub(crate) fn render_message(ctx: &mut Context, state: &MainState) -> Result<Action, GameError> {
     if let Some(msg) = &state.active_message {
-        let size = Vec2::new(300.0, 150.0);
-        let pos = Vec2::new(SCREEN_SIZE.0 / 2.0, SCREEN_SIZE.1 / 2.0);
-        let _err = render_round_rect(ctx, pos - size / 2.0, size, BUTTON_COLOR);
         let mut current_text = msg.text.clone();
         if msg.confirm_value == Action::StartClient {
             current_text.push_str(&state.input_staus.ip_input);
@@ -280,6 +371,11 @@ pub(crate) fn render_message(ctx: &mut Context, state: &MainState) -> Result<Act
 
         text.set_font(active_font.font, active_font.font_size);
 
+        // the popup is made wider for long messages
+        let size = Vec2::new(f32::max(300.0, text.width(ctx) + 40.0), 150.0);
+        let pos = Vec2::new(SCREEN_SIZE.0 / 2.0, SCREEN_SIZE.1 / 2.0);
+        let _err = render_round_rect(ctx, pos - size / 2.0, size, BUTTON_COLOR);
+
         let _err2 = graphics::draw(
             ctx,
             &text,
@@ -333,7 +429,7 @@ fn is_inside_square(mouse_x: f32, mouse_y: f32, pos: Vec2, size: Vec2) -> bool {
 pub(crate) fn render_buttons(ctx: &mut Context, state: &MainState) -> Option<usize> {
     let mut icons: Vec<&graphics::Image> = vec![
         &state.render_config.icons.exit,
-        // &state.render_config.icons.surrender,
+        &state.render_config.icons.surrender,
         &state.render_config.icons.replay,
         &state.render_config.icons.settings,
     ];
@@ -511,3 +607,64 @@ pub(crate) fn render_pieces(
 
     Ok(())
 }
+
+/** Renders the pieces in the pockets in crazyhouse with how many there are, the dragged piece follows the mouse */
+pub(crate) fn render_pockets(
+    ctx: &mut Context,
+    config: &RenderConfig,
+    state: &ActiveGame,
+) -> GameResult<()> {
+    let game = &state.game.game;
+    if game.variant != Variant::Crazyhouse {
+        return Ok(());
+    }
+
+    let active_sprites = &config.spritesets[config.active_sprites_index];
+    let active_font = &config.fontsets[config.active_fontset_index];
+    let scale = POCKET_SLOT_SIZE / BOARD_RENDER_TILE_SIZE;
+    let half_slot = Vec2::new(POCKET_SLOT_SIZE / 2.0, POCKET_SLOT_SIZE / 2.0);
+
+    for is_white in [true, false] {
+        let pocket = game.pockets[if is_white { 0 } else { 1 }];
+        for index in 0..POCKET_PIECES.len() {
+            if pocket[index] == 0 {
+                continue;
+            }
+
+            let pos = get_pocket_render_pos(index, is_white);
+            graphics::draw(
+                ctx,
+                get_piece_image(POCKET_PIECES[index], is_white, false, active_sprites),
+                graphics::DrawParam::new()
+                    .dest(pos + half_slot)
+                    .offset(Vec2::new(0.5, 0.5))
+                    .scale(Vec2::new(scale, scale)),
+            )?;
+
+            if pocket[index] > 1 {
+                let mut text = graphics::Text::new(pocket[index].to_string());
+                text.set_font(active_font.font, graphics::PxScale { x: 20.0, y: 20.0 });
+                graphics::draw(
+                    ctx,
+                    &text,
+                    graphics::DrawParam::new()
+                        .dest(pos + Vec2::new(POCKET_SLOT_SIZE, POCKET_SLOT_SIZE))
+                        .offset(Vec2::new(1.0, 1.0)),
+                )?;
+            }
+        }
+    }
+
+    // rendered last so it is on top of the board
+    if let (Some(piece), Some(hover_position)) = (state.selected_drop, state.hover_position) {
+        graphics::draw(
+            ctx,
+            get_piece_image(piece, game.is_white_to_move, false, active_sprites),
+            graphics::DrawParam::new()
+                .dest(hover_position)
+                .offset(Vec2::new(0.5, 0.5)),
+        )?;
+    }
+
+    Ok(())
+}